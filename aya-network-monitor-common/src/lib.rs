@@ -1,5 +1,7 @@
 #![no_std]
 
+use core::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
 // 以太网头
 #[derive(Debug, Clone, Copy)]
 #[repr(C, packed)]
@@ -9,6 +11,14 @@ pub struct EthHdr {
     pub ether_type: u16,
 }
 
+// 802.1Q / 802.1ad VLAN 标签（紧跟在以太网头的 ether_type 之后）
+#[derive(Debug, Clone, Copy)]
+#[repr(C, packed)]
+pub struct VlanHdr {
+    pub tci: u16,               // PCP(3) + DEI(1) + VLAN ID(12)
+    pub ether_type: u16,        // 内层以太网类型
+}
+
 // IP 头
 #[derive(Debug, Clone, Copy)]
 #[repr(C, packed)]
@@ -25,6 +35,36 @@ pub struct Ipv4Hdr {
     pub dst_ip: u32,
 }
 
+// IPv6 头
+#[derive(Debug, Clone, Copy)]
+#[repr(C, packed)]
+pub struct Ipv6Hdr {
+    pub version_tc_flow: u32,
+    pub payload_len: u16,
+    pub next_hdr: u8,
+    pub hop_limit: u8,
+    pub src_addr: [u8; 16],
+    pub dst_addr: [u8; 16],
+}
+
+// IPv6 扩展头公共前缀（Hop-by-Hop / Routing / Destination Options / AH）
+#[derive(Debug, Clone, Copy)]
+#[repr(C, packed)]
+pub struct Ipv6ExtHdr {
+    pub next_hdr: u8,
+    pub hdr_len: u8,
+}
+
+// IPv6 分片头
+#[derive(Debug, Clone, Copy)]
+#[repr(C, packed)]
+pub struct Ipv6FragHdr {
+    pub next_hdr: u8,
+    pub reserved: u8,
+    pub frag_off: u16,          // 高 13 位为分片偏移，最低位为 M 标志
+    pub id: u32,
+}
+
 // TCP 头
 #[derive(Debug, Clone, Copy)]
 #[repr(C, packed)]
@@ -63,38 +103,262 @@ pub struct IcmpHdr {
 pub const IPPROTO_TCP: u8 = 6;
 pub const IPPROTO_UDP: u8 = 17;
 pub const IPPROTO_ICMP: u8 = 1;
+pub const IPPROTO_ICMPV6: u8 = 58;
+
+// IPv6 扩展头
+pub const IPPROTO_HOPOPTS: u8 = 0;
+pub const IPPROTO_ROUTING: u8 = 43;
+pub const IPPROTO_FRAGMENT: u8 = 44;
+pub const IPPROTO_AH: u8 = 51;
+pub const IPPROTO_DSTOPTS: u8 = 60;
+
+// 最多遍历的 IPv6 扩展头数量（eBPF 循环必须有上界）
+pub const MAX_IPV6_EXT_HDRS: usize = 6;
 
 // 以太网类型
 pub const ETH_P_IP: u16 = 0x0800;
+pub const ETH_P_IPV6: u16 = 0x86DD;
+pub const ETH_P_8021Q: u16 = 0x8100;
+pub const ETH_P_8021AD: u16 = 0x88A8;
+
+// 最多跳过的 VLAN 标签数量（QinQ 为 2 层）
+pub const MAX_VLAN_TAGS: usize = 2;
+
+// 每个包捕获的最大字节数，从以太网头开始计算（以太网 MTU 1500 + 以太网头 + 两层 VLAN 标签）
+pub const MAX_CAPTURE_SIZE: usize = 1536;
+
+// 未配置 CAPTURE_CONFIG 时的默认捕获长度
+pub const DEFAULT_CAPTURE_SIZE: usize = 256;
+
+// 事件传输方式
+pub const TRANSPORT_PERF: u8 = 0;       // 每 CPU 的 Perf Event Array
+pub const TRANSPORT_RINGBUF: u8 = 1;    // 所有 CPU 共享的 BPF ring buffer（内核 >= 5.8）
 
-// Payload 大小限制（考虑 eBPF 栈大小限制和其他栈变量）
-pub const MAX_PAYLOAD_SIZE: usize = 192;
+// ring buffer 大小（必须是页大小的 2 的幂倍数）；只有 ring buffer 对象包含这个 map，perf 传输不分配
+pub const RING_BUF_SIZE: u32 = 16 * 1024 * 1024;
 
-// 网络事件（通过 Perf Event Array 发送到用户空间）
+// 事件方向（NetworkEvent.direction）
+pub const DIRECTION_INGRESS: u8 = 0;    // 收到的包（XDP 或 TC ingress）
+pub const DIRECTION_EGRESS: u8 = 1;     // 发出的包（TC egress）
+
+// 网络事件头部（通过 Perf Event Array 或 ring buffer 发送到用户空间，后面紧跟 cap_len 字节的原始帧）
 #[derive(Debug, Clone, Copy)]
 #[repr(C)]
 pub struct NetworkEvent {
-    pub protocol: u8,           // IPPROTO_TCP/UDP/ICMP
-    pub src_ip: u32,            // 源 IP（网络字节序）
-    pub dst_ip: u32,            // 目标 IP（网络字节序）
+    pub ktime_ns: u64,          // 内核时间戳（bpf_ktime_get_ns，CLOCK_MONOTONIC 纳秒）
+    pub ifindex: u32,           // 网卡 ifindex
+    pub rx_queue: u32,          // 收包队列（TC egress 为发送队列）
+    pub direction: u8,          // DIRECTION_INGRESS 或 DIRECTION_EGRESS
+    pub protocol: u8,           // IPPROTO_TCP/UDP/ICMP/ICMPV6
+    pub ip_version: u8,         // 4 或 6
+    pub vlan_count: u8,         // VLAN 标签数量（0-2）
+    pub vlan_ids: [u16; MAX_VLAN_TAGS], // VLAN ID（主机字节序，外层在前）
+    pub src_ip: [u8; 16],       // 源 IP（网络字节序，IPv4 只使用前 4 字节）
+    pub dst_ip: [u8; 16],       // 目标 IP（网络字节序，IPv4 只使用前 4 字节）
     pub src_port: u16,          // 源端口（网络字节序）
     pub dst_port: u16,          // 目标端口（网络字节序）
     pub packet_size: u32,       // 包大小
     pub tcp_flags: u8,          // TCP 标志位（仅 TCP 有效）
-    pub payload_len: u16,       // 实际捕获的 payload 长度（改为 u16 支持更大的值）
-    pub payload: [u8; MAX_PAYLOAD_SIZE],  // 数据包内容
+    pub cap_len: u16,           // 记录中紧跟的原始帧字节数（从以太网头开始）
+    pub payload_offset: u16,    // 传输层 payload 在原始帧中的起始偏移
+}
+
+// 变长事件记录在 eBPF 中的最大布局（每 CPU 暂存区），
+// 实际只发送 size_of::<NetworkEvent>() + cap_len 字节
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct EventRecord {
+    pub event: NetworkEvent,
+    pub data: [u8; MAX_CAPTURE_SIZE],
 }
 
-// 用户空间过滤配置（通过共享 map 传递到 eBPF）
+impl NetworkEvent {
+    /// 源 IP 地址
+    pub fn src_addr(&self) -> IpAddr {
+        ip_addr(self.ip_version, &self.src_ip)
+    }
+
+    /// 目标 IP 地址
+    pub fn dst_addr(&self) -> IpAddr {
+        ip_addr(self.ip_version, &self.dst_ip)
+    }
+
+    /// 数据包携带的 VLAN ID（外层在前）
+    pub fn vlans(&self) -> &[u16] {
+        let count = core::cmp::min(self.vlan_count as usize, MAX_VLAN_TAGS);
+        &self.vlan_ids[..count]
+    }
+}
+
+/// 把 16 字节地址按 IP 版本还原为 IpAddr
+pub fn ip_addr(version: u8, bytes: &[u8; 16]) -> IpAddr {
+    if version == 6 {
+        IpAddr::V6(Ipv6Addr::from(*bytes))
+    } else {
+        IpAddr::V4(Ipv4Addr::new(bytes[0], bytes[1], bytes[2], bytes[3]))
+    }
+}
+
+/// 把 IpAddr 编码为 (IP 版本, 16 字节地址)，与 NetworkEvent 的布局一致
+pub fn ip_bytes(addr: IpAddr) -> (u8, [u8; 16]) {
+    let mut bytes = [0u8; 16];
+    match addr {
+        IpAddr::V4(v4) => {
+            bytes[..4].copy_from_slice(&v4.octets());
+            (4, bytes)
+        }
+        IpAddr::V6(v6) => (6, v6.octets()),
+    }
+}
+
+// 网段过滤（SRC_NETS / DST_NETS LPM trie）的键长度：IP 版本 1 字节 + 地址 16 字节
+pub const NET_KEY_LEN: usize = 17;
+
+// 每个方向最多的网段数量
+pub const MAX_NET_PREFIXES: u32 = 64;
+
+/// LPM trie 的键：版本字节在前，使 IPv4 与 IPv6 网段互不匹配；
+/// 前缀长度 = 8 + 网段前缀长度
+#[inline(always)]
+pub fn net_key(version: u8, addr: &[u8; 16]) -> [u8; NET_KEY_LEN] {
+    let mut key = [0u8; NET_KEY_LEN];
+    key[0] = version;
+    let mut i = 0usize;
+    while i < 16 {
+        key[i + 1] = addr[i];
+        i += 1;
+    }
+    key
+}
+
+// 端口过滤位图（PORT_BITMAP map）：每组 65536 bit = 1024 个 u64，端口为主机字节序
+pub const PORT_BITMAP_WORDS: u32 = 1024;
+pub const PORT_SET_SRC: u32 = 0;        // --src-port
+pub const PORT_SET_DST: u32 = 1;        // --dst-port
+pub const PORT_SET_ANY: u32 = 2;        // --port（源或目标端口任意一个命中）
+pub const PORT_SET_COUNT: u32 = 3;
+
+// 过滤表达式程序（FILTER_PROG map）：--filter 编译成的逆波兰指令序列，XDP 中用位栈求值
+pub const MAX_FILTER_OPS: u32 = 32;
+
+pub const FILTER_OP_TRUE: u8 = 0;       // 压入 true
+pub const FILTER_OP_AND: u8 = 1;        // 弹出两个结果，压入 a && b
+pub const FILTER_OP_OR: u8 = 2;         // 弹出两个结果，压入 a || b
+pub const FILTER_OP_NOT: u8 = 3;        // 栈顶取反
+pub const FILTER_OP_PROTO: u8 = 4;      // protocol == arg
+pub const FILTER_OP_IP_VERSION: u8 = 5; // ip_version == arg
+pub const FILTER_OP_NET: u8 = 6;        // 地址（按 dir）的前 arg 位等于 addr，且版本为 ip_version
+pub const FILTER_OP_PORT: u8 = 7;       // TCP/UDP 端口（按 dir，主机字节序）在 [lo, hi] 内
+pub const FILTER_OP_VLAN: u8 = 8;       // arg=0: 带 VLAN 标签；arg=1: 任意一层 VLAN ID 等于 lo
+pub const FILTER_OP_LEN: u8 = 9;        // packet_size 与 lo 按 arg（FILTER_CMP_*）比较
+
+pub const FILTER_DIR_SRC: u8 = 0;
+pub const FILTER_DIR_DST: u8 = 1;
+pub const FILTER_DIR_ANY: u8 = 2;
+
+pub const FILTER_CMP_LT: u8 = 0;
+pub const FILTER_CMP_LE: u8 = 1;
+pub const FILTER_CMP_GT: u8 = 2;
+pub const FILTER_CMP_GE: u8 = 3;
+pub const FILTER_CMP_EQ: u8 = 4;
+pub const FILTER_CMP_NE: u8 = 5;
+
+// 过滤表达式指令
+#[derive(Debug, Clone, Copy, Default)]
+#[repr(C)]
+pub struct FilterOp {
+    pub kind: u8,               // FILTER_OP_*
+    pub dir: u8,                // FILTER_DIR_*（NET / PORT）
+    pub arg: u8,                // 协议号 / IP 版本 / 前缀长度 / 比较运算符 / VLAN 模式
+    pub ip_version: u8,         // NET 的地址版本
+    pub lo: u32,                // 端口下限 / VLAN ID / 包长
+    pub hi: u32,                // 端口上限
+    pub addr: [u8; 16],         // NET 的网络地址（网络字节序）
+}
+
+#[cfg(feature = "user")]
+unsafe impl aya::Pod for FilterOp {}
+
+// 用户空间过滤配置（通过 FILTER_CONFIG map 传递到 eBPF，索引 0）
+// IP 与 NetworkEvent 一致，使用网络字节序
 #[derive(Debug, Clone, Copy, Default)]
 #[repr(C)]
 pub struct FilterConfig {
     pub enabled: u8,            // 是否启用过滤
-    pub protocol: u8,           // 0=所有, 6=TCP, 17=UDP, 1=ICMP
-    pub src_ip: u32,            // 0=任意
-    pub dst_ip: u32,            // 0=任意
-    pub src_port: u16,          // 0=任意
-    pub dst_port: u16,          // 0=任意
-    pub min_packet_size: u32,   // 最小包大小过滤
-    pub max_packet_size: u32,   // 最大包大小过滤
+    pub protocol: u8,           // 0=所有, 6=TCP, 17=UDP, 1=ICMP, 58=ICMPv6
+    pub src_ip_version: u8,     // 0=任意源 IP, 4/6=按 src_ip 精确匹配
+    pub dst_ip_version: u8,     // 0=任意目标 IP, 4/6=按 dst_ip 精确匹配
+    pub src_ip: [u8; 16],
+    pub dst_ip: [u8; 16],
+    pub match_src_port: u8,     // 源端口必须在 PORT_SET_SRC 位图中
+    pub match_dst_port: u8,     // 目标端口必须在 PORT_SET_DST 位图中
+    pub match_any_port: u8,     // 源或目标端口在 PORT_SET_ANY 位图中
+    pub match_vlan: u8,         // 是否按 VLAN 过滤
+    pub vlan_id: u16,           // 任意一层 VLAN 标签等于此 ID 即匹配
+    pub match_src_net: u8,      // 源 IP 必须落在 SRC_NETS 中的某个网段
+    pub match_dst_net: u8,      // 目标 IP 必须落在 DST_NETS 中的某个网段
+    pub prog_len: u32,          // FILTER_PROG 中的指令数（0=没有过滤表达式）
+}
+
+#[cfg(feature = "user")]
+unsafe impl aya::Pod for FilterConfig {}
+
+// 捕获配置（通过 CAPTURE_CONFIG map 传递到 eBPF，索引 0）
+#[derive(Debug, Clone, Copy, Default)]
+#[repr(C)]
+pub struct CaptureConfig {
+    pub snaplen: u32,           // 每个包最多捕获的字节数（不超过 MAX_CAPTURE_SIZE）
+    pub transport: u8,          // TRANSPORT_PERF 或 TRANSPORT_RINGBUF
+    pub kernel_flows: u8,       // 1=只更新 FLOW_TABLE 中的流计数，不上送事件
 }
+
+#[cfg(feature = "user")]
+unsafe impl aya::Pod for CaptureConfig {}
+
+// 每 CPU 捕获统计（CAPTURE_STATS map，索引 0），用于判断捕获是否完整
+#[derive(Debug, Clone, Copy, Default)]
+#[repr(C)]
+pub struct CaptureStats {
+    pub seen: u64,              // 进入 XDP 程序的包
+    pub matched: u64,           // 通过解析和内核过滤的包
+    pub emitted: u64,           // 提交到 perf buffer / ring buffer 的事件
+    pub dropped: u64,           // 匹配但未能提交的事件（ring buffer 已满、暂存区不可用）
+}
+
+#[cfg(feature = "user")]
+unsafe impl aya::Pod for CaptureStats {}
+
+// 内核流表（FLOW_TABLE，LRU 每 CPU 哈希表）的容量，写满时淘汰最久未更新的流
+pub const MAX_KERNEL_FLOWS: u32 = 65536;
+
+// 内核流表的键：单向五元组，显式填充使哈希键中没有未初始化的字节
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[repr(C)]
+pub struct FlowTuple {
+    pub src_ip: [u8; 16],
+    pub dst_ip: [u8; 16],
+    pub src_port: u16,          // 网络字节序
+    pub dst_port: u16,          // 网络字节序
+    pub protocol: u8,
+    pub ip_version: u8,
+    pub _pad: [u8; 2],
+}
+
+#[cfg(feature = "user")]
+unsafe impl aya::Pod for FlowTuple {}
+
+// 内核流表的值（每 CPU 一份，由用户空间合并）
+#[derive(Debug, Clone, Copy, Default)]
+#[repr(C)]
+pub struct FlowCounters {
+    pub packets: u64,
+    pub bytes: u64,
+    pub first_seen_ns: u64,     // bpf_ktime_get_ns
+    pub last_seen_ns: u64,      // bpf_ktime_get_ns
+    pub ifindex: u32,           // 该 CPU 上第一个包的网卡
+    pub tcp_flags: u8,          // TCP 标志并集
+    pub _pad: [u8; 3],
+}
+
+#[cfg(feature = "user")]
+unsafe impl aya::Pod for FlowCounters {}
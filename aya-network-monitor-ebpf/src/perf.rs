@@ -0,0 +1,13 @@
+#![no_std]
+#![no_main]
+
+// 只用 perf buffer 的对象：没有 RING_EVENTS 和 bpf_ringbuf_output，5.8 之前的内核也能加载，
+// 选择 perf 传输时也不必分配 ring buffer
+
+mod program;
+
+/// 用户空间加载这个对象时 CAPTURE_CONFIG.transport 总是 TRANSPORT_PERF，不会走到这里
+#[inline(always)]
+fn ring_output(_bytes: &[u8]) -> bool {
+    false
+}
@@ -0,0 +1,662 @@
+//! 两个对象共用的 eBPF 程序：XDP 和 TC 入口、解析、过滤和 perf buffer 上送
+//!
+//! ring buffer 相关的 map 和 helper 只在 `main.rs` 中，`perf.rs` 的对象不含它们，
+//! 这样 5.8 之前的内核也能加载。
+
+use aya_ebpf::{
+    bindings::{xdp_action, BPF_F_NO_PREALLOC, BPF_NOEXIST, TC_ACT_PIPE},
+    helpers::bpf_ktime_get_ns,
+    macros::{classifier, map, xdp},
+    maps::{lpm_trie::Key, Array, LpmTrie, LruPerCpuHashMap, PerCpuArray, PerfEventByteArray},
+    programs::{TcContext, XdpContext},
+    EbpfContext,
+};
+use aya_network_monitor_common::{
+    CaptureConfig, CaptureStats, EventRecord, FilterConfig, NetworkEvent, EthHdr, VlanHdr, Ipv4Hdr, Ipv6Hdr, Ipv6ExtHdr, Ipv6FragHdr,
+    TcpHdr, UdpHdr, IcmpHdr,
+    ETH_P_IP, ETH_P_IPV6, ETH_P_8021Q, ETH_P_8021AD,
+    IPPROTO_TCP, IPPROTO_UDP, IPPROTO_ICMP, IPPROTO_ICMPV6,
+    IPPROTO_HOPOPTS, IPPROTO_ROUTING, IPPROTO_FRAGMENT, IPPROTO_AH, IPPROTO_DSTOPTS,
+    DEFAULT_CAPTURE_SIZE, MAX_CAPTURE_SIZE, MAX_IPV6_EXT_HDRS, MAX_VLAN_TAGS,
+    TRANSPORT_PERF, TRANSPORT_RINGBUF,
+    DIRECTION_EGRESS, DIRECTION_INGRESS,
+    FlowCounters, FlowTuple, MAX_KERNEL_FLOWS,
+    MAX_NET_PREFIXES, NET_KEY_LEN, net_key,
+    PORT_BITMAP_WORDS, PORT_SET_ANY, PORT_SET_COUNT, PORT_SET_DST, PORT_SET_SRC,
+    FilterOp, MAX_FILTER_OPS,
+    FILTER_OP_TRUE, FILTER_OP_AND, FILTER_OP_OR, FILTER_OP_NOT, FILTER_OP_PROTO, FILTER_OP_IP_VERSION,
+    FILTER_OP_NET, FILTER_OP_PORT, FILTER_OP_VLAN, FILTER_OP_LEN,
+    FILTER_DIR_SRC, FILTER_DIR_DST,
+    FILTER_CMP_LT, FILTER_CMP_LE, FILTER_CMP_GT, FILTER_CMP_GE, FILTER_CMP_EQ,
+};
+
+/// TC 下至少拉入线性区的字节数：以太网 + VLAN + IPv6 扩展头 + TCP 选项，足够解析到 payload
+const MIN_PULL_LEN: u32 = 256;
+
+// Perf Event Array - 用于向用户空间发送变长事件记录（NetworkEvent + 原始帧）
+#[map]
+static mut EVENTS: PerfEventByteArray = PerfEventByteArray::new(0);
+
+// 过滤配置 - 由用户空间在附加程序前写入索引 0
+#[map]
+static mut FILTER_CONFIG: Array<FilterConfig> = Array::with_max_entries(1, 0);
+
+// 源/目标网段 - 最长前缀匹配，键为 net_key(版本, 地址)，由用户空间按 --src-net/--dst-net 写入
+#[map]
+static mut SRC_NETS: LpmTrie<[u8; NET_KEY_LEN], u8> =
+    LpmTrie::with_max_entries(MAX_NET_PREFIXES, BPF_F_NO_PREALLOC);
+
+#[map]
+static mut DST_NETS: LpmTrie<[u8; NET_KEY_LEN], u8> =
+    LpmTrie::with_max_entries(MAX_NET_PREFIXES, BPF_F_NO_PREALLOC);
+
+// 端口位图 - PORT_SET_SRC/DST/ANY 三组，每组 65536 bit，由用户空间按 --src-port/--dst-port/--port 写入
+#[map]
+static mut PORT_BITMAP: Array<u64> = Array::with_max_entries(PORT_SET_COUNT * PORT_BITMAP_WORDS, 0);
+
+// 过滤表达式程序 - --filter 编译成的逆波兰指令，长度为 FilterConfig.prog_len
+#[map]
+static mut FILTER_PROG: Array<FilterOp> = Array::with_max_entries(MAX_FILTER_OPS, 0);
+
+// 捕获配置 - 由用户空间在附加程序前写入索引 0
+#[map]
+static mut CAPTURE_CONFIG: Array<CaptureConfig> = Array::with_max_entries(1, 0);
+
+// 每 CPU 事件记录暂存区 - 完整记录超出 512 字节的 eBPF 栈
+#[map]
+static mut EVENT_SCRATCH: PerCpuArray<EventRecord> = PerCpuArray::with_max_entries(1, 0);
+
+// 每 CPU 捕获统计 - 收到、匹配、上送和丢弃的包数，由用户空间汇总
+#[map]
+static mut CAPTURE_STATS: PerCpuArray<CaptureStats> = PerCpuArray::with_max_entries(1, 0);
+
+// 内核流表 - --kernel-flows 时按五元组累加包数和字节数，由用户空间定期遍历并移出超时的流
+#[map]
+static mut FLOW_TABLE: LruPerCpuHashMap<FlowTuple, FlowCounters> =
+    LruPerCpuHashMap::with_max_entries(MAX_KERNEL_FLOWS, 0);
+
+#[xdp]
+pub fn aya_network_monitor(ctx: XdpContext) -> u32 {
+    if let Some(stats) = stats() {
+        stats.seen += 1;
+    }
+
+    let pkt = Packet {
+        start: ctx.data(),
+        end: ctx.data_end(),
+        len: (ctx.data_end() - ctx.data()) as u32,
+        ifindex: ctx.ingress_ifindex() as u32,
+        rx_queue: ctx.rx_queue_index(),
+    };
+    let _ = try_capture(&ctx, &pkt, DIRECTION_INGRESS);
+    xdp_action::XDP_PASS
+}
+
+/// TC clsact 入口：与 XDP 共用解析代码，但挂在协议栈之后，能看到本机发出的包
+#[classifier]
+pub fn tc_ingress(ctx: TcContext) -> i32 {
+    tc_capture(ctx, DIRECTION_INGRESS)
+}
+
+#[classifier]
+pub fn tc_egress(ctx: TcContext) -> i32 {
+    tc_capture(ctx, DIRECTION_EGRESS)
+}
+
+#[inline(always)]
+fn tc_capture(ctx: TcContext, direction: u8) -> i32 {
+    if let Some(stats) = stats() {
+        stats.seen += 1;
+    }
+
+    // skb 可能是非线性的（GRO/GSO 包常常只有协议头在线性区），data..data_end 只覆盖线性区；
+    // 先把解析和捕获需要的字节拉入线性区，失败时按已有的线性区解析。pull 之后 data 指针会变，必须在之后读取
+    let snaplen = match unsafe { CAPTURE_CONFIG.get(0) } {
+        // 内核流统计只需要协议头
+        Some(config) if config.kernel_flows != 0 => 0,
+        Some(config) => config.snaplen,
+        None => DEFAULT_CAPTURE_SIZE as u32,
+    };
+    let wanted = core::cmp::min(core::cmp::max(snaplen, MIN_PULL_LEN), MAX_CAPTURE_SIZE as u32);
+    let _ = ctx.pull_data(core::cmp::min(wanted, ctx.len()));
+
+    // 包长取 skb->len
+    let skb = unsafe { &*ctx.skb.skb };
+    let pkt = Packet {
+        start: ctx.data(),
+        end: ctx.data_end(),
+        len: ctx.len(),
+        ifindex: skb.ifindex,
+        rx_queue: skb.queue_mapping,
+    };
+    let _ = try_capture(&ctx, &pkt, direction);
+
+    // 只观察不修改，交给后续的分类器/动作继续处理
+    TC_ACT_PIPE
+}
+
+/// 数据包的可访问范围，XDP 和 TC 上下文都先转换成它再解析
+struct Packet {
+    start: usize,
+    end: usize,
+    /// 线路上的包长（TC 下可能大于 end - start）
+    len: u32,
+    ifindex: u32,
+    rx_queue: u32,
+}
+
+/// 当前 CPU 的统计计数器（每 CPU 独立，无需原子操作）
+#[inline(always)]
+fn stats() -> Option<&'static mut CaptureStats> {
+    unsafe { CAPTURE_STATS.get_ptr_mut(0).map(|ptr| &mut *ptr) }
+}
+
+/// 把一个包计入 FLOW_TABLE 中当前 CPU 的流计数
+#[inline(always)]
+fn account_flow(key: &FlowTuple, size: u32, tcp_flags: u8, ifindex: u32) {
+    let now = unsafe { bpf_ktime_get_ns() };
+
+    match unsafe { FLOW_TABLE.get_ptr_mut(key) } {
+        Some(counters) => {
+            let counters = unsafe { &mut *counters };
+            // 流可能由其他 CPU 创建，本 CPU 的计数此时还是 0
+            if counters.packets == 0 {
+                counters.first_seen_ns = now;
+                counters.ifindex = ifindex;
+            }
+            counters.packets += 1;
+            counters.bytes += size as u64;
+            counters.last_seen_ns = now;
+            counters.tcp_flags |= tcp_flags;
+        }
+        None => {
+            let counters = FlowCounters {
+                packets: 1,
+                bytes: size as u64,
+                first_seen_ns: now,
+                last_seen_ns: now,
+                ifindex,
+                tcp_flags,
+                _pad: [0; 3],
+            };
+            // 其他 CPU 抢先创建时插入失败，只少计这一个包
+            let _ = unsafe { FLOW_TABLE.insert(key, &counters, BPF_NOEXIST as u64) };
+        }
+    }
+}
+
+/// 端口是否在 PORT_BITMAP 的某一组中
+#[inline(always)]
+fn port_in_set(set: u32, port: u16) -> bool {
+    let index = set * PORT_BITMAP_WORDS + (port as u32 / 64);
+    match unsafe { PORT_BITMAP.get(index) } {
+        Some(word) => (*word >> (port % 64)) & 1 != 0,
+        None => false,
+    }
+}
+
+/// 地址是否落在网段 trie 中的任意一个网段内
+#[inline(always)]
+fn net_contains(nets: &LpmTrie<[u8; NET_KEY_LEN], u8>, ip_version: u8, addr: &[u8; 16]) -> bool {
+    let key = Key::new((NET_KEY_LEN * 8) as u32, net_key(ip_version, addr));
+    nets.get(&key).is_some()
+}
+
+/// 返回数据包中 offset 处类型 T 的指针，越界时返回 Err
+#[inline(always)]
+fn ptr_at<T>(pkt: &Packet, offset: usize) -> Result<*const T, ()> {
+    if pkt.start + offset + core::mem::size_of::<T>() > pkt.end {
+        return Err(());
+    }
+
+    Ok((pkt.start + offset) as *const T)
+}
+
+/// 逐字节比较两个 16 字节地址（避免生成 memcmp 调用）
+#[inline(always)]
+fn addr_eq(a: &[u8; 16], b: &[u8; 16]) -> bool {
+    let mut diff = 0u8;
+    let mut i = 0usize;
+    while i < 16 {
+        diff |= a[i] ^ b[i];
+        i += 1;
+    }
+    diff == 0
+}
+
+/// 按用户空间下发的过滤配置检查数据包，不匹配的包不会进入 perf buffer
+#[inline(always)]
+fn filter_allows(
+    protocol: u8,
+    ip_version: u8,
+    src_ip: &[u8; 16],
+    dst_ip: &[u8; 16],
+    src_port: u16,
+    dst_port: u16,
+    size: u32,
+    vlan_count: u8,
+    vlan_ids: &[u16; MAX_VLAN_TAGS],
+) -> bool {
+    let config = match unsafe { FILTER_CONFIG.get(0) } {
+        Some(config) => config,
+        None => return true,
+    };
+
+    if config.enabled == 0 {
+        return true;
+    }
+    if config.protocol != 0 && config.protocol != protocol {
+        return false;
+    }
+    if config.src_ip_version != 0
+        && (config.src_ip_version != ip_version || !addr_eq(&config.src_ip, src_ip))
+    {
+        return false;
+    }
+    if config.dst_ip_version != 0
+        && (config.dst_ip_version != ip_version || !addr_eq(&config.dst_ip, dst_ip))
+    {
+        return false;
+    }
+    if config.match_src_net != 0 && !net_contains(unsafe { &SRC_NETS }, ip_version, src_ip) {
+        return false;
+    }
+    if config.match_dst_net != 0 && !net_contains(unsafe { &DST_NETS }, ip_version, dst_ip) {
+        return false;
+    }
+    // 端口为网络字节序，位图按主机字节序索引
+    let src_port = u16::from_be(src_port);
+    let dst_port = u16::from_be(dst_port);
+    if config.match_src_port != 0 && !port_in_set(PORT_SET_SRC, src_port) {
+        return false;
+    }
+    if config.match_dst_port != 0 && !port_in_set(PORT_SET_DST, dst_port) {
+        return false;
+    }
+    if config.match_any_port != 0
+        && !port_in_set(PORT_SET_ANY, src_port)
+        && !port_in_set(PORT_SET_ANY, dst_port)
+    {
+        return false;
+    }
+    if config.match_vlan != 0 {
+        let outer = vlan_count >= 1 && vlan_ids[0] == config.vlan_id;
+        let inner = vlan_count >= 2 && vlan_ids[1] == config.vlan_id;
+        if !outer && !inner {
+            return false;
+        }
+    }
+    if config.prog_len != 0
+        && !run_filter_prog(
+            config.prog_len,
+            protocol,
+            ip_version,
+            src_ip,
+            dst_ip,
+            src_port,
+            dst_port,
+            size,
+            vlan_count,
+            vlan_ids,
+        )
+    {
+        return false;
+    }
+
+    true
+}
+
+/// 地址的前 prefix_len 位是否与网络地址相同
+#[inline(always)]
+fn prefix_eq(addr: &[u8; 16], net: &[u8; 16], prefix_len: u8) -> bool {
+    let mut diff = 0u8;
+    let mut i = 0usize;
+    while i < 16 {
+        let bits = (prefix_len as usize).saturating_sub(i * 8);
+        let mask = if bits >= 8 { 0xFF } else { !(0xFFu8 >> bits) };
+        diff |= (addr[i] ^ net[i]) & mask;
+        i += 1;
+    }
+    diff == 0
+}
+
+/// 求值过滤表达式程序（逆波兰序）
+///
+/// 中间结果保存在 u64 位栈中（最低位为栈顶），指令数不超过 MAX_FILTER_OPS，
+/// 栈深度不会超过 64。读取指令失败时放行，交给用户空间判断。
+#[inline(always)]
+fn run_filter_prog(
+    prog_len: u32,
+    protocol: u8,
+    ip_version: u8,
+    src_ip: &[u8; 16],
+    dst_ip: &[u8; 16],
+    src_port: u16,
+    dst_port: u16,
+    size: u32,
+    vlan_count: u8,
+    vlan_ids: &[u16; MAX_VLAN_TAGS],
+) -> bool {
+    let mut stack = 0u64;
+    let mut i = 0u32;
+
+    while i < MAX_FILTER_OPS {
+        if i >= prog_len {
+            break;
+        }
+        let op = match unsafe { FILTER_PROG.get(i) } {
+            Some(op) => op,
+            None => return true,
+        };
+
+        let (src, dst) = match op.dir {
+            FILTER_DIR_SRC => (true, false),
+            FILTER_DIR_DST => (false, true),
+            _ => (true, true),
+        };
+
+        let result = match op.kind {
+            FILTER_OP_TRUE => true,
+            FILTER_OP_AND | FILTER_OP_OR => {
+                let a = stack & 1 != 0;
+                let b = (stack >> 1) & 1 != 0;
+                stack >>= 2;
+                if op.kind == FILTER_OP_AND { a && b } else { a || b }
+            }
+            FILTER_OP_NOT => {
+                let a = stack & 1 != 0;
+                stack >>= 1;
+                !a
+            }
+            FILTER_OP_PROTO => protocol == op.arg,
+            FILTER_OP_IP_VERSION => ip_version == op.arg,
+            FILTER_OP_NET => {
+                ip_version == op.ip_version
+                    && ((src && prefix_eq(src_ip, &op.addr, op.arg))
+                        || (dst && prefix_eq(dst_ip, &op.addr, op.arg)))
+            }
+            FILTER_OP_PORT => {
+                let in_range = |port: u16| op.lo <= port as u32 && port as u32 <= op.hi;
+                (protocol == IPPROTO_TCP || protocol == IPPROTO_UDP)
+                    && ((src && in_range(src_port)) || (dst && in_range(dst_port)))
+            }
+            FILTER_OP_VLAN => {
+                if op.arg == 0 {
+                    vlan_count > 0
+                } else {
+                    (vlan_count >= 1 && vlan_ids[0] as u32 == op.lo)
+                        || (vlan_count >= 2 && vlan_ids[1] as u32 == op.lo)
+                }
+            }
+            FILTER_OP_LEN => match op.arg {
+                FILTER_CMP_LT => size < op.lo,
+                FILTER_CMP_LE => size <= op.lo,
+                FILTER_CMP_GT => size > op.lo,
+                FILTER_CMP_GE => size >= op.lo,
+                FILTER_CMP_EQ => size == op.lo,
+                _ => size != op.lo,
+            },
+            // 未知指令：放行，交给用户空间判断
+            _ => return true,
+        };
+
+        stack = (stack << 1) | result as u64;
+        i += 1;
+    }
+
+    stack & 1 != 0
+}
+
+/// 解析 IPv6 头并跳过扩展头，返回 (上层协议, 传输层头偏移)
+///
+/// 非首个分片不包含传输层头，直接忽略。
+#[inline(always)]
+fn parse_ipv6(
+    pkt: &Packet,
+    offset: usize,
+    src_ip: &mut [u8; 16],
+    dst_ip: &mut [u8; 16],
+) -> Result<(u8, usize), ()> {
+    let ip_hdr = unsafe { &*ptr_at::<Ipv6Hdr>(pkt, offset)? };
+    *src_ip = ip_hdr.src_addr;
+    *dst_ip = ip_hdr.dst_addr;
+
+    let mut next_hdr = ip_hdr.next_hdr;
+    let mut offset = offset + core::mem::size_of::<Ipv6Hdr>();
+
+    // 遍历扩展头，直到遇到传输层协议
+    for _ in 0..MAX_IPV6_EXT_HDRS {
+        match next_hdr {
+            IPPROTO_HOPOPTS | IPPROTO_ROUTING | IPPROTO_DSTOPTS => {
+                let ext_hdr = unsafe { &*ptr_at::<Ipv6ExtHdr>(pkt, offset)? };
+                next_hdr = ext_hdr.next_hdr;
+                offset += (ext_hdr.hdr_len as usize + 1) * 8;
+            }
+            IPPROTO_AH => {
+                // AH 的长度以 4 字节为单位，且不包含前 2 个单位
+                let ext_hdr = unsafe { &*ptr_at::<Ipv6ExtHdr>(pkt, offset)? };
+                next_hdr = ext_hdr.next_hdr;
+                offset += (ext_hdr.hdr_len as usize + 2) * 4;
+            }
+            IPPROTO_FRAGMENT => {
+                let frag_hdr = unsafe { &*ptr_at::<Ipv6FragHdr>(pkt, offset)? };
+                if u16::from_be(frag_hdr.frag_off) & 0xFFF8 != 0 {
+                    return Err(());
+                }
+                next_hdr = frag_hdr.next_hdr;
+                offset += core::mem::size_of::<Ipv6FragHdr>();
+            }
+            _ => return Ok((next_hdr, offset)),
+        }
+    }
+
+    // 扩展头过多，放弃解析
+    Err(())
+}
+
+/// 解析、过滤并上送一个包；只读不改，调用方总是放行该包
+#[inline(always)]
+fn try_capture<C: EbpfContext>(ctx: &C, pkt: &Packet, direction: u8) -> Result<(), ()> {
+    let data_ptr = pkt.start;
+    let data_end = pkt.end;
+
+    if data_ptr == 0 || data_end == 0 {
+        return Ok(());
+    }
+
+    // 解析以太网头
+    let eth_hdr = unsafe { &*ptr_at::<EthHdr>(pkt, 0)? };
+    let mut ether_type = u16::from_be(eth_hdr.ether_type);
+    let mut l3_offset = core::mem::size_of::<EthHdr>();
+
+    // 跳过最多两层 VLAN 标签（802.1Q 或 QinQ 802.1ad）
+    let mut vlan_ids = [0u16; MAX_VLAN_TAGS];
+    let mut vlan_count = 0u8;
+    for i in 0..MAX_VLAN_TAGS {
+        if ether_type != ETH_P_8021Q && ether_type != ETH_P_8021AD {
+            break;
+        }
+        let vlan_hdr = unsafe { &*ptr_at::<VlanHdr>(pkt, l3_offset)? };
+        vlan_ids[i] = u16::from_be(vlan_hdr.tci) & 0x0FFF;
+        vlan_count += 1;
+        ether_type = u16::from_be(vlan_hdr.ether_type);
+        l3_offset += core::mem::size_of::<VlanHdr>();
+    }
+
+    let size = pkt.len as usize;
+
+    // 解析网络层，得到 IP 版本、地址、上层协议和传输层头偏移
+    let mut src_ip = [0u8; 16];
+    let mut dst_ip = [0u8; 16];
+
+    let (ip_version, protocol, l4_offset) = match ether_type {
+        ETH_P_IP => {
+            let ip_hdr = unsafe { &*ptr_at::<Ipv4Hdr>(pkt, l3_offset)? };
+            let ip_hdr_len = ((ip_hdr.version_ihl & 0x0F) * 4) as usize;
+
+            // src_ip/dst_ip 按内存中的原始字节保存，即网络字节序
+            let ip_src = ip_hdr.src_ip.to_ne_bytes();
+            let ip_dst = ip_hdr.dst_ip.to_ne_bytes();
+            src_ip[..4].copy_from_slice(&ip_src);
+            dst_ip[..4].copy_from_slice(&ip_dst);
+
+            (4u8, ip_hdr.protocol, l3_offset + ip_hdr_len)
+        }
+        ETH_P_IPV6 => {
+            let (protocol, l4_offset) = match parse_ipv6(pkt, l3_offset, &mut src_ip, &mut dst_ip) {
+                Ok(ret) => ret,
+                Err(_) => return Ok(()),
+            };
+            (6u8, protocol, l4_offset)
+        }
+        _ => return Ok(()),
+    };
+
+    // 解析传输层头，得到端口、TCP 标志和 payload 偏移
+    let (src_port, dst_port, tcp_flags, payload_offset) = match protocol {
+        IPPROTO_TCP => {
+            let tcp_hdr = unsafe { &*ptr_at::<TcpHdr>(pkt, l4_offset)? };
+
+            // 计算 TCP payload 的起始位置
+            let tcp_hdr_len = ((tcp_hdr.data_off >> 4) as usize) * 4;
+            (tcp_hdr.src_port, tcp_hdr.dst_port, tcp_hdr.flags, l4_offset + tcp_hdr_len)
+        }
+        IPPROTO_UDP => {
+            let udp_hdr = unsafe { &*ptr_at::<UdpHdr>(pkt, l4_offset)? };
+            (udp_hdr.src_port, udp_hdr.dst_port, 0, l4_offset + core::mem::size_of::<UdpHdr>())
+        }
+        IPPROTO_ICMP | IPPROTO_ICMPV6 => {
+            // ICMP 与 ICMPv6 的头部前 4 字节格式相同
+            ptr_at::<IcmpHdr>(pkt, l4_offset)?;
+            (0, 0, 0, l4_offset + core::mem::size_of::<IcmpHdr>())
+        }
+        _ => return Ok(()),
+    };
+
+    // 内核过滤：在复制 payload 之前丢弃不关心的包
+    if !filter_allows(
+        protocol,
+        ip_version,
+        &src_ip,
+        &dst_ip,
+        src_port,
+        dst_port,
+        size as u32,
+        vlan_count,
+        &vlan_ids,
+    ) {
+        return Ok(());
+    }
+
+    if let Some(stats) = stats() {
+        stats.matched += 1;
+    }
+
+    let (snaplen, transport, kernel_flows) = match unsafe { CAPTURE_CONFIG.get(0) } {
+        Some(config) => (config.snaplen as usize, config.transport, config.kernel_flows != 0),
+        None => (DEFAULT_CAPTURE_SIZE, TRANSPORT_PERF, false),
+    };
+
+    // 内核流统计：只累加流计数，不复制 payload，也不上送事件
+    if kernel_flows {
+        let key = FlowTuple {
+            src_ip,
+            dst_ip,
+            src_port,
+            dst_port,
+            protocol,
+            ip_version,
+            _pad: [0; 2],
+        };
+        account_flow(&key, size as u32, tcp_flags, pkt.ifindex);
+        return Ok(());
+    }
+
+    let record = match unsafe { EVENT_SCRATCH.get_ptr_mut(0) } {
+        Some(record) => unsafe { &mut *record },
+        None => {
+            if let Some(stats) = stats() {
+                stats.dropped += 1;
+            }
+            return Ok(());
+        }
+    };
+
+    // 从以太网头开始捕获原始帧，用户空间据此写 pcap 并通过 payload_offset 找到 payload
+    let frame_ptr = data_ptr as *const u8;
+    let to_copy = core::cmp::min(core::cmp::min(size, snaplen), MAX_CAPTURE_SIZE);
+
+    // 手动复制到暂存区，避免 eBPF 验证器问题
+    let mut i = 0usize;
+    loop {
+        if i >= to_copy {
+            break;
+        }
+        let src_ptr = unsafe { frame_ptr.add(i) };
+        // 确保不会越界
+        if src_ptr as usize >= data_end as usize {
+            break;
+        }
+        let byte = unsafe { *src_ptr };
+        record.data[i] = byte;
+        i += 1;
+    }
+    let cap_len = i as u16;
+
+    // 填写事件头部，与原始帧一起作为变长记录发送
+    record.event = NetworkEvent {
+        ktime_ns: unsafe { bpf_ktime_get_ns() },
+        ifindex: pkt.ifindex,
+        rx_queue: pkt.rx_queue,
+        direction,
+        protocol,
+        ip_version,
+        vlan_count,
+        vlan_ids,
+        src_ip,
+        dst_ip,
+        src_port,
+        dst_port,
+        packet_size: size as u32,
+        tcp_flags,
+        cap_len,
+        payload_offset: payload_offset as u16,
+    };
+
+    // 只发送头部和实际捕获的字节，避免每个小包都发送完整的暂存区
+    let record_len = core::mem::size_of::<NetworkEvent>() + i;
+    let bytes = unsafe {
+        core::slice::from_raw_parts(record as *const EventRecord as *const u8, record_len)
+    };
+
+    // perf buffer 的丢失由用户空间通过 read_events 的 lost 计数得到
+    let emitted = if transport == TRANSPORT_RINGBUF {
+        // ring buffer 已满时丢弃该事件
+        super::ring_output(bytes)
+    } else {
+        unsafe {
+            EVENTS.output(ctx, bytes, 0);
+        }
+        true
+    };
+
+    if let Some(stats) = stats() {
+        if emitted {
+            stats.emitted += 1;
+        } else {
+            stats.dropped += 1;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(_info: &core::panic::PanicInfo) -> ! {
+    loop {}
+}
+
+#[unsafe(link_section = "license")]
+#[unsafe(no_mangle)]
+static LICENSE: [u8; 13] = *b"Dual MIT/GPL\0";
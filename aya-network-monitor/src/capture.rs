@@ -0,0 +1,501 @@
+//! 捕获流水线
+//!
+//! `Capture::open` 加载 eBPF 程序、写入过滤和捕获配置并附加到网卡，`spawn_readers` 为
+//! ring buffer 或每个 CPU 的 perf buffer 各启动一个读取任务，把事件交给调用方提供的
+//! `EventHandler`。`run_offline` 从 pcap/pcapng 文件读取，走同样的处理流程。
+
+use std::{
+    fs::File,
+    io::BufReader,
+    path::Path,
+    sync::Arc,
+};
+
+use anyhow::Context as _;
+use aya::{
+    maps::{lpm_trie::{Key, LpmTrie}, perf::PerfEventArray, Array, MapData, PerCpuArray, PerCpuHashMap, RingBuf},
+    programs::{tc, SchedClassifier, TcAttachType, Xdp, XdpFlags},
+    util::{online_cpus, KernelVersion},
+    Ebpf,
+};
+use aya_network_monitor_common::{
+    CaptureConfig, CaptureStats, FilterConfig, FilterOp, NetworkEvent, MAX_CAPTURE_SIZE, MAX_FILTER_OPS,
+    MAX_NET_PREFIXES, NET_KEY_LEN, PORT_BITMAP_WORDS, PORT_SET_ANY, PORT_SET_DST, PORT_SET_SRC, TRANSPORT_PERF,
+    TRANSPORT_RINGBUF,
+};
+use bytes::BytesMut;
+use log::{debug, info, warn};
+use tokio::{sync::watch, task};
+
+use crate::{
+    decode::decode_frame,
+    event::{CapturedEvent, KernelClock},
+    filter::{self, Expr, Filter},
+    flow::{KernelFlows, SharedFlowSink},
+    handler::EventHandler,
+    iface::Interfaces,
+    net::IpNet,
+    pcap::PcapReader,
+    ports::{port_bitmap, PortRange},
+    stats::{self, CpuStats, LostCounters, ReaderSummary},
+};
+
+/// eBPF 程序的挂载点
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hook {
+    /// XDP：只有收到的包
+    Xdp,
+    /// TC clsact ingress
+    TcIngress,
+    /// TC clsact egress：本机发出的包
+    TcEgress,
+    /// TC clsact 两个方向
+    TcBoth,
+}
+
+impl Hook {
+    /// 需要加载的 TC 分类器程序和挂载方向
+    fn tc_programs(self) -> &'static [(&'static str, TcAttachType)] {
+        match self {
+            Hook::Xdp => &[],
+            Hook::TcIngress => &[("tc_ingress", TcAttachType::Ingress)],
+            Hook::TcEgress => &[("tc_egress", TcAttachType::Egress)],
+            Hook::TcBoth => &[("tc_ingress", TcAttachType::Ingress), ("tc_egress", TcAttachType::Egress)],
+        }
+    }
+}
+
+/// 实时捕获的参数
+#[derive(Debug, Clone)]
+pub struct CaptureOptions {
+    /// 网卡名，`all` 表示除 lo 以外的所有网卡
+    pub ifaces: Vec<String>,
+    pub hook: Hook,
+    /// XDP 使用 SKB 模式而不是驱动模式，仅用于 Hook::Xdp
+    pub xdp_skb_mode: bool,
+    /// TRANSPORT_PERF 或 TRANSPORT_RINGBUF；内核不支持 ring buffer 时回退到 perf
+    pub transport: u8,
+    /// 每个包最多捕获的字节数（从以太网头开始）
+    pub snaplen: usize,
+    /// 在内核中按五元组统计流，不上送逐包事件
+    pub kernel_flows: bool,
+}
+
+/// 已附加到网卡的 eBPF 程序；drop 时从网卡上卸载
+pub struct Capture {
+    ebpf: Ebpf,
+    ifaces: Interfaces,
+    transport: u8,
+    clock: KernelClock,
+    capture_stats: Arc<PerCpuArray<MapData, CaptureStats>>,
+    lost: LostCounters,
+    cpus: Vec<u32>,
+}
+
+impl Capture {
+    /// 加载 eBPF 程序，写入过滤和捕获配置，附加到网卡
+    pub fn open(options: &CaptureOptions, filter: &Filter) -> anyhow::Result<Self> {
+        if options.snaplen == 0 || options.snaplen > MAX_CAPTURE_SIZE {
+            anyhow::bail!("snaplen 必须在 1 到 {} 之间", MAX_CAPTURE_SIZE);
+        }
+
+        // 网卡名 → ifindex，事件中的 ifindex 据此换算回网卡名
+        let ifaces = Interfaces::resolve(&options.ifaces)?;
+
+        // BPF ring buffer 需要内核 5.8 及以上，更早的内核回退到每 CPU 的 perf buffer
+        let mut transport = options.transport;
+        if transport == TRANSPORT_RINGBUF {
+            match KernelVersion::current() {
+                Ok(version) if !supports_ringbuf(version) => {
+                    warn!("当前内核不支持 BPF ring buffer（需要 5.8+），回退到 perf 传输");
+                    transport = TRANSPORT_PERF;
+                }
+                Ok(_) => {}
+                Err(e) => warn!("无法获取内核版本，继续使用 ring buffer: {}", e),
+            }
+        }
+
+        // Bump the memlock rlimit
+        let rlim = libc::rlimit {
+            rlim_cur: libc::RLIM_INFINITY,
+            rlim_max: libc::RLIM_INFINITY,
+        };
+        let ret = unsafe { libc::setrlimit(libc::RLIMIT_MEMLOCK, &rlim) };
+        if ret != 0 {
+            debug!("remove limit on locked memory failed, ret is: {ret}");
+        }
+
+        // perf 传输加载不含 ring buffer 的对象：旧内核不认识 RINGBUF map 和 bpf_ringbuf_output，
+        // 新内核上也不必为用不到的 ring buffer 分配内存
+        let object: &[u8] = if transport == TRANSPORT_RINGBUF {
+            aya::include_bytes_aligned!(concat!(env!("OUT_DIR"), "/aya-network-monitor"))
+        } else {
+            aya::include_bytes_aligned!(concat!(env!("OUT_DIR"), "/aya-network-monitor-perf"))
+        };
+        let mut ebpf = Ebpf::load(object)?;
+
+        // 在附加之前写入内核过滤配置，避免附加后短暂地上送全部流量
+        let filter_prog = compile_filter(filter.filter_expr());
+        // 内核流统计没有用户空间这一层过滤，表达式必须完整地在内核中执行
+        if options.kernel_flows && filter_prog.is_empty() && *filter.filter_expr() != Expr::True {
+            anyhow::bail!("过滤表达式超过 {} 条指令，不能与 --kernel-flows 同时使用", MAX_FILTER_OPS);
+        }
+        if options.kernel_flows && filter.filter_expr().uses_payload() {
+            anyhow::bail!("app 条件需要在用户空间检查 payload，不能与 --kernel-flows 同时使用");
+        }
+        let mut prog_map: Array<_, FilterOp> = Array::try_from(ebpf.map_mut("FILTER_PROG").unwrap())?;
+        for (i, op) in filter_prog.iter().enumerate() {
+            prog_map.set(i as u32, *op, 0).context("写入过滤表达式程序失败")?;
+        }
+
+        let mut config = filter.to_config();
+        if !filter_prog.is_empty() {
+            config.enabled = 1;
+            config.prog_len = filter_prog.len() as u32;
+        }
+
+        let mut filter_config: Array<_, FilterConfig> =
+            Array::try_from(ebpf.map_mut("FILTER_CONFIG").unwrap())?;
+        filter_config.set(0, config, 0)
+            .context("写入内核过滤配置失败")?;
+
+        let spec = filter.spec();
+        write_nets(&mut ebpf, "SRC_NETS", &spec.src_nets)?;
+        write_nets(&mut ebpf, "DST_NETS", &spec.dst_nets)?;
+
+        let mut port_bitmaps: Array<_, u64> = Array::try_from(ebpf.map_mut("PORT_BITMAP").unwrap())?;
+        write_ports(&mut port_bitmaps, PORT_SET_SRC, &spec.src_ports)?;
+        write_ports(&mut port_bitmaps, PORT_SET_DST, &spec.dst_ports)?;
+        write_ports(&mut port_bitmaps, PORT_SET_ANY, &spec.ports)?;
+
+        let mut capture_config: Array<_, CaptureConfig> =
+            Array::try_from(ebpf.map_mut("CAPTURE_CONFIG").unwrap())?;
+        let capture = CaptureConfig {
+            snaplen: options.snaplen as u32,
+            transport,
+            kernel_flows: options.kernel_flows as u8,
+        };
+        capture_config.set(0, capture, 0)
+            .context("写入捕获配置失败")?;
+
+        if options.hook == Hook::Xdp {
+            let program: &mut Xdp = ebpf.program_mut("aya_network_monitor").unwrap().try_into()?;
+            program.load()?;
+
+            // 根据 XDP 模式选择标志
+            let (xdp_flags, xdp_mode) = if options.xdp_skb_mode {
+                (XdpFlags::SKB_MODE, "skb")
+            } else {
+                (XdpFlags::default(), "drv")
+            };
+
+            // 同一个程序附加到每个网卡
+            for iface in ifaces.names() {
+                program.attach(iface, xdp_flags)
+                    .context(format!("failed to attach the XDP program to {} with {} mode - try the other mode (drv/skb)", iface, xdp_mode))?;
+            }
+        } else {
+            // 网卡上已经有 clsact qdisc 时会返回错误，可以忽略
+            for iface in ifaces.names() {
+                if let Err(e) = tc::qdisc_add_clsact(iface) {
+                    debug!("{}: 添加 clsact qdisc 失败（可能已存在）: {}", iface, e);
+                }
+            }
+
+            for &(name, attach_type) in options.hook.tc_programs() {
+                let program: &mut SchedClassifier = ebpf.program_mut(name).unwrap().try_into()?;
+                program.load()?;
+                for iface in ifaces.names() {
+                    program.attach(iface, attach_type)
+                        .context(format!("failed to attach the TC program {} to {}", name, iface))?;
+                }
+            }
+        }
+
+        info!("已附加到网卡: {}", ifaces.names().collect::<Vec<_>>().join(", "));
+
+        // 内核时间戳（CLOCK_MONOTONIC）到墙上时间的换算
+        let clock = KernelClock::sample();
+
+        // eBPF 每 CPU 计数 + perf 丢失样本计数
+        let capture_stats: Arc<PerCpuArray<_, CaptureStats>> =
+            Arc::new(PerCpuArray::try_from(ebpf.take_map("CAPTURE_STATS").unwrap())?);
+        let cpus = online_cpus().map_err(|(_, e)| e).context("获取在线 CPU 失败")?;
+        let lost = LostCounters::new(&cpus);
+
+        Ok(Capture { ebpf, ifaces, transport, clock, capture_stats, lost, cpus })
+    }
+
+    /// 实际使用的传输方式（可能已从 ring buffer 回退到 perf）
+    pub fn transport(&self) -> u8 {
+        self.transport
+    }
+
+    pub fn ifaces(&self) -> &Interfaces {
+        &self.ifaces
+    }
+
+    /// eBPF 端每 CPU 的 CAPTURE_STATS
+    pub fn capture_stats(&self) -> Arc<PerCpuArray<MapData, CaptureStats>> {
+        self.capture_stats.clone()
+    }
+
+    /// 读取任务累加的 perf 丢失样本计数
+    pub fn lost(&self) -> LostCounters {
+        self.lost.clone()
+    }
+
+    /// 当前的每 CPU 捕获统计
+    pub fn stats(&self) -> anyhow::Result<Vec<CpuStats>> {
+        stats::snapshot(&self.capture_stats, &self.lost)
+    }
+
+    /// 启动读取任务：ring buffer 一个，perf buffer 每个 CPU 一个；只能调用一次
+    ///
+    /// make_handler 按读取任务名（`ringbuf` 或 `CPU N`）为每个任务创建一个 `EventHandler`。
+    /// shutdown 变化后任务停止读取，返回各自的计数。
+    pub fn spawn_readers(
+        &mut self,
+        mut make_handler: impl FnMut(&str) -> EventHandler,
+        shutdown: watch::Receiver<bool>,
+    ) -> anyhow::Result<Vec<task::JoinHandle<ReaderSummary>>> {
+        let clock = self.clock;
+        let mut handles = vec![];
+
+        if self.transport == TRANSPORT_RINGBUF {
+            // 单一消费者：所有 CPU 的事件按提交顺序从同一个 ring buffer 读出
+            let ring_buf = RingBuf::try_from(self.ebpf.take_map("RING_EVENTS").unwrap())?;
+            let mut ring_buf = tokio::io::unix::AsyncFd::with_interest(
+                ring_buf,
+                tokio::io::Interest::READABLE,
+            )?;
+            let mut handler = make_handler("ringbuf");
+            let ifaces = self.ifaces.clone();
+            let mut shutdown = shutdown.clone();
+
+            let handle = task::spawn(async move {
+                loop {
+                    let mut guard = tokio::select! {
+                        _ = shutdown.changed() => break,
+                        guard = ring_buf.readable_mut() => match guard {
+                            Ok(guard) => guard,
+                            Err(e) => {
+                                warn!("ring buffer: 等待可读失败: {}", e);
+                                break;
+                            }
+                        },
+                    };
+
+                    let ring_buf = guard.get_inner_mut();
+                    while let Some(item) = ring_buf.next() {
+                        // 变长记录：NetworkEvent 头部 + cap_len 字节原始帧
+                        if let Some(network_event) = CapturedEvent::from_record(&item, &clock, &ifaces) {
+                            if let Err(e) = handler.handle(&network_event) {
+                                warn!("ring buffer: 写入 pcapng 失败: {}", e);
+                            }
+                        }
+                    }
+                    guard.clear_ready();
+                }
+
+                handler.summary("ringbuf")
+            });
+
+            handles.push(handle);
+        } else {
+            // 获取 Perf Event Array
+            let mut perf_array = PerfEventArray::try_from(self.ebpf.take_map("EVENTS").unwrap())?;
+
+            // 为每个 CPU 创建处理任务
+            for &cpu_id in &self.cpus {
+                let buf = perf_array.open(cpu_id, None)?;
+
+                let mut buf = tokio::io::unix::AsyncFd::with_interest(
+                    buf,
+                    tokio::io::Interest::READABLE,
+                )?;
+                let mut handler = make_handler(&format!("CPU {}", cpu_id));
+                let lost = self.lost.clone();
+                let ifaces = self.ifaces.clone();
+                let mut shutdown = shutdown.clone();
+
+                let handle = task::spawn(async move {
+                    let mut buffers = (0..10)
+                        .map(|_| BytesMut::with_capacity(core::mem::size_of::<NetworkEvent>() + MAX_CAPTURE_SIZE))
+                        .collect::<Vec<_>>();
+
+                    loop {
+                        let mut guard = tokio::select! {
+                            _ = shutdown.changed() => break,
+                            guard = buf.readable_mut() => match guard {
+                                Ok(guard) => guard,
+                                Err(e) => {
+                                    warn!("CPU {}: 等待可读失败: {}", cpu_id, e);
+                                    break;
+                                }
+                            },
+                        };
+
+                        match guard.get_inner_mut().read_events(&mut buffers) {
+                            Ok(events) => {
+                                // perf buffer 已满时内核丢弃的样本
+                                if events.lost > 0 {
+                                    lost.add(cpu_id, events.lost);
+                                }
+
+                                for buf in buffers.iter_mut().take(events.read) {
+                                    // 变长记录：NetworkEvent 头部 + cap_len 字节原始帧
+                                    if let Some(network_event) = CapturedEvent::from_record(buf, &clock, &ifaces) {
+                                        if let Err(e) = handler.handle(&network_event) {
+                                            warn!("CPU {}: 写入 pcapng 失败: {}", cpu_id, e);
+                                        }
+                                    }
+                                }
+
+                                if events.read != buffers.len() {
+                                    guard.clear_ready();
+                                }
+                            }
+                            Err(e) => {
+                                warn!("CPU {}: 读取事件失败: {}", cpu_id, e);
+                                guard.clear_ready();
+                            }
+                        }
+                    }
+
+                    handler.summary(format!("CPU {}", cpu_id))
+                });
+
+                handles.push(handle);
+            }
+        }
+
+        Ok(handles)
+    }
+
+    /// 内核流表（--kernel-flows）；只能调用一次
+    pub fn kernel_flows(&mut self, idle_timeout_secs: u64, active_timeout_secs: u64) -> anyhow::Result<KernelFlows> {
+        let map = PerCpuHashMap::try_from(self.ebpf.take_map("FLOW_TABLE").unwrap())?;
+        Ok(KernelFlows::new(map, self.clock, self.ifaces.clone(), idle_timeout_secs, active_timeout_secs))
+    }
+}
+
+/// 离线模式：读取 pcap/pcapng 文件，走与实时捕获相同的解析、过滤和显示流程
+pub fn run_offline(path: &Path, handler: &mut EventHandler, flow_sink: Option<&SharedFlowSink>) -> anyhow::Result<()> {
+    let file = File::open(path).context(format!("打开文件失败: {}", path.display()))?;
+    let mut reader = PcapReader::new(BufReader::new(file))
+        .context(format!("解析文件头失败: {}", path.display()))?;
+
+    while let Some(packet) = reader.next_packet().context("读取数据包失败")? {
+        // 与 XDP 程序一样，只处理 IP 上的 TCP/UDP/ICMP
+        let network_event = match decode_frame(&packet) {
+            Some(event) => event,
+            None => continue,
+        };
+
+        handler.handle(&network_event)
+            .context("写入 pcapng 失败")?;
+
+        // 离线文件没有墙上时钟可用，按包的时间推进流超时和导出的 sysUptime
+        if let (Some(flows), Some(sink)) = (handler.flow_table(), flow_sink) {
+            let records = flows.lock().unwrap().tick(network_event.timestamp_ns);
+            let mut sink = sink.lock().unwrap();
+            sink.set_packet_clock(network_event.timestamp_ns);
+            sink.emit(&records);
+        }
+    }
+
+    Ok(())
+}
+
+/// BPF ring buffer（RINGBUF map 和 bpf_ringbuf_output）从内核 5.8 开始提供
+fn supports_ringbuf(version: KernelVersion) -> bool {
+    version >= KernelVersion::new(5, 8, 0)
+}
+
+/// 把 --filter 表达式编译为 XDP 指令；过长时返回空程序，表达式只在用户空间求值
+fn compile_filter(expr: &Expr) -> Vec<FilterOp> {
+    if *expr == Expr::True {
+        return Vec::new();
+    }
+
+    match filter::compile(expr) {
+        Some(ops) => {
+            info!("过滤表达式已编译到 XDP 程序（{} 条指令）", ops.len());
+            ops
+        }
+        None => {
+            warn!("过滤表达式超过 {} 条指令，只在用户空间求值", MAX_FILTER_OPS);
+            Vec::new()
+        }
+    }
+}
+
+/// 把网段写入 eBPF 的 LPM trie（SRC_NETS / DST_NETS）
+fn write_nets(ebpf: &mut Ebpf, name: &str, nets: &[IpNet]) -> anyhow::Result<()> {
+    if nets.len() > MAX_NET_PREFIXES as usize {
+        anyhow::bail!("{} 最多支持 {} 个网段", name, MAX_NET_PREFIXES);
+    }
+
+    let mut trie: LpmTrie<_, [u8; NET_KEY_LEN], u8> =
+        LpmTrie::try_from(ebpf.map_mut(name).unwrap())?;
+    for net in nets {
+        let (prefix_len, data) = net.lpm_key();
+        trie.insert(&Key::new(prefix_len, data), 1, 0)
+            .context(format!("写入网段 {} 失败", net))?;
+    }
+
+    Ok(())
+}
+
+/// 把端口范围展开为位图写入 PORT_BITMAP 的第 set 组（只写非零字）
+fn write_ports(
+    bitmaps: &mut Array<&mut MapData, u64>,
+    set: u32,
+    ranges: &[PortRange],
+) -> anyhow::Result<()> {
+    for (i, word) in port_bitmap(ranges).into_iter().enumerate() {
+        if word != 0 {
+            bitmaps.set(set * PORT_BITMAP_WORDS + i as u32, word, 0)
+                .context("写入端口位图失败")?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::FilterSpec;
+
+    #[test]
+    fn ringbuf_needs_kernel_5_8() {
+        assert!(!supports_ringbuf(KernelVersion::new(4, 19, 0)));
+        assert!(!supports_ringbuf(KernelVersion::new(5, 4, 250)));
+        assert!(!supports_ringbuf(KernelVersion::new(5, 7, 19)));
+        assert!(supports_ringbuf(KernelVersion::new(5, 8, 0)));
+        assert!(supports_ringbuf(KernelVersion::new(6, 1, 0)));
+    }
+
+    #[test]
+    fn compiled_filter_agrees_with_filter_matches() {
+        for text in [
+            "tcp and (port 80 or port 443) and not host 10.0.0.5",
+            "udp or (ip6 and len < 100)",
+            "vlan 100 or src net 10.0.0.0/8",
+        ] {
+            let filter = Filter::new(FilterSpec { expression: Some(text.to_string()), ..Default::default() }).unwrap();
+            let ops = compile_filter(filter.filter_expr());
+            assert!(!ops.is_empty(), "{:?}", text);
+
+            for event in filter::tests::sample_events() {
+                assert_eq!(filter::tests::eval_ops(&ops, &event), filter.matches(&event), "{:?}", text);
+            }
+        }
+
+        // 没有表达式时不下发程序
+        let filter = Filter::new(FilterSpec { protocol: Some(6), ..Default::default() }).unwrap();
+        assert!(compile_filter(filter.filter_expr()).is_empty());
+    }
+}
@@ -0,0 +1,434 @@
+//! 用户空间以太网帧解析
+//!
+//! 与 eBPF 程序中的解析逻辑保持一致，把离线文件中的帧转换为 `CapturedEvent`，
+//! 使其可以走同一套过滤和显示流程。
+
+use aya_network_monitor_common::{
+    NetworkEvent, ETH_P_8021AD, ETH_P_8021Q, ETH_P_IP, ETH_P_IPV6, IPPROTO_AH, IPPROTO_DSTOPTS,
+    IPPROTO_FRAGMENT, IPPROTO_HOPOPTS, IPPROTO_ICMP, IPPROTO_ICMPV6, IPPROTO_ROUTING, IPPROTO_TCP,
+    IPPROTO_UDP, MAX_IPV6_EXT_HDRS, MAX_VLAN_TAGS,
+};
+
+use std::sync::Arc;
+
+use crate::{event::CapturedEvent, pcap::PcapPacket};
+
+const ETH_HDR_LEN: usize = 14;
+const VLAN_HDR_LEN: usize = 4;
+const IPV4_HDR_LEN: usize = 20;
+const IPV6_HDR_LEN: usize = 40;
+const TCP_HDR_LEN: usize = 20;
+const UDP_HDR_LEN: usize = 8;
+const ICMP_HDR_LEN: usize = 4;
+
+/// 解析文件中的一个以太网帧
+///
+/// 捕获到的字节最多保留 65535 字节，时间戳、原始长度、收发方向和网卡名取自文件记录。
+/// 不是 IPv4/IPv6 上的 TCP/UDP/ICMP 时返回 None（与 eBPF 程序一样忽略）。
+pub fn decode_frame(packet: &PcapPacket) -> Option<CapturedEvent> {
+    // cap_len 和 payload_offset 都是 u16，超出部分与 eBPF 的 snaplen 一样截掉
+    let frame = &packet.data[..packet.data.len().min(u16::MAX as usize)];
+    let mut ether_type = read_u16(frame, 12)?;
+    let mut l3_offset = ETH_HDR_LEN;
+
+    // 跳过最多两层 VLAN 标签
+    let mut vlan_ids = [0u16; MAX_VLAN_TAGS];
+    let mut vlan_count = 0u8;
+    for vlan_id in vlan_ids.iter_mut() {
+        if ether_type != ETH_P_8021Q && ether_type != ETH_P_8021AD {
+            break;
+        }
+        *vlan_id = read_u16(frame, l3_offset)? & 0x0FFF;
+        vlan_count += 1;
+        ether_type = read_u16(frame, l3_offset + 2)?;
+        l3_offset += VLAN_HDR_LEN;
+    }
+
+    let mut src_ip = [0u8; 16];
+    let mut dst_ip = [0u8; 16];
+
+    let (ip_version, protocol, l4_offset) = match ether_type {
+        ETH_P_IP => {
+            let ip_hdr = frame.get(l3_offset..l3_offset + IPV4_HDR_LEN)?;
+            let ip_hdr_len = ((ip_hdr[0] & 0x0F) as usize) * 4;
+            src_ip[..4].copy_from_slice(&ip_hdr[12..16]);
+            dst_ip[..4].copy_from_slice(&ip_hdr[16..20]);
+            (4u8, ip_hdr[9], l3_offset + ip_hdr_len)
+        }
+        ETH_P_IPV6 => {
+            let ip_hdr = frame.get(l3_offset..l3_offset + IPV6_HDR_LEN)?;
+            src_ip.copy_from_slice(&ip_hdr[8..24]);
+            dst_ip.copy_from_slice(&ip_hdr[24..40]);
+            let (protocol, l4_offset) = skip_ipv6_ext_hdrs(frame, ip_hdr[6], l3_offset + IPV6_HDR_LEN)?;
+            (6u8, protocol, l4_offset)
+        }
+        _ => return None,
+    };
+
+    // 端口保持网络字节序，与 eBPF 发送的事件一致
+    let (src_port, dst_port, tcp_flags, payload_offset) = match protocol {
+        IPPROTO_TCP => {
+            let tcp_hdr = frame.get(l4_offset..l4_offset + TCP_HDR_LEN)?;
+            let tcp_hdr_len = ((tcp_hdr[12] >> 4) as usize) * 4;
+            (
+                u16::from_ne_bytes([tcp_hdr[0], tcp_hdr[1]]),
+                u16::from_ne_bytes([tcp_hdr[2], tcp_hdr[3]]),
+                tcp_hdr[13],
+                l4_offset + tcp_hdr_len,
+            )
+        }
+        IPPROTO_UDP => {
+            let udp_hdr = frame.get(l4_offset..l4_offset + UDP_HDR_LEN)?;
+            (
+                u16::from_ne_bytes([udp_hdr[0], udp_hdr[1]]),
+                u16::from_ne_bytes([udp_hdr[2], udp_hdr[3]]),
+                0,
+                l4_offset + UDP_HDR_LEN,
+            )
+        }
+        IPPROTO_ICMP | IPPROTO_ICMPV6 => {
+            frame.get(l4_offset..l4_offset + ICMP_HDR_LEN)?;
+            (0, 0, 0, l4_offset + ICMP_HDR_LEN)
+        }
+        _ => return None,
+    };
+
+    // 离线文件没有内核单调时钟和 ifindex，这些字段留空，直接使用文件时间戳和接口名
+    let header = NetworkEvent {
+        ktime_ns: 0,
+        ifindex: 0,
+        rx_queue: 0,
+        direction: packet.direction,
+        protocol,
+        ip_version,
+        vlan_count,
+        vlan_ids,
+        src_ip,
+        dst_ip,
+        src_port,
+        dst_port,
+        packet_size: packet.orig_len,
+        tcp_flags,
+        cap_len: frame.len() as u16,
+        // TCP 选项可能超出捕获范围，偏移不超过捕获长度，payload 为空
+        payload_offset: payload_offset.min(frame.len()) as u16,
+    };
+
+    Some(CapturedEvent {
+        header,
+        data: frame.to_vec(),
+        timestamp_ns: packet.timestamp_ns,
+        iface: Arc::from(packet.iface.as_deref().unwrap_or("-")),
+    })
+}
+
+/// 跳过 IPv6 扩展头，返回 (上层协议, 传输层头偏移)；非首个分片返回 None
+fn skip_ipv6_ext_hdrs(frame: &[u8], mut next_hdr: u8, mut offset: usize) -> Option<(u8, usize)> {
+    for _ in 0..MAX_IPV6_EXT_HDRS {
+        match next_hdr {
+            IPPROTO_HOPOPTS | IPPROTO_ROUTING | IPPROTO_DSTOPTS => {
+                let ext_hdr = frame.get(offset..offset + 2)?;
+                next_hdr = ext_hdr[0];
+                offset += (ext_hdr[1] as usize + 1) * 8;
+            }
+            IPPROTO_AH => {
+                let ext_hdr = frame.get(offset..offset + 2)?;
+                next_hdr = ext_hdr[0];
+                offset += (ext_hdr[1] as usize + 2) * 4;
+            }
+            IPPROTO_FRAGMENT => {
+                let frag_hdr = frame.get(offset..offset + 8)?;
+                if u16::from_be_bytes([frag_hdr[2], frag_hdr[3]]) & 0xFFF8 != 0 {
+                    return None;
+                }
+                next_hdr = frag_hdr[0];
+                offset += 8;
+            }
+            _ => return Some((next_hdr, offset)),
+        }
+    }
+
+    None
+}
+
+fn read_u16(frame: &[u8], offset: usize) -> Option<u16> {
+    let bytes = frame.get(offset..offset + 2)?;
+    Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aya_network_monitor_common::{DIRECTION_EGRESS, DIRECTION_INGRESS};
+
+    const SRC_V4: [u8; 4] = [10, 0, 0, 1];
+    const DST_V4: [u8; 4] = [10, 0, 0, 2];
+    const SRC_V6: [u8; 16] = [0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1];
+    const DST_V6: [u8; 16] = [0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2];
+
+    /// 以太网头，`vlans` 为 (TPID, VLAN ID)，外层在前
+    fn eth(vlans: &[(u16, u16)], ether_type: u16) -> Vec<u8> {
+        let mut frame = vec![0u8; 12];
+        for &(tpid, id) in vlans {
+            frame.extend_from_slice(&tpid.to_be_bytes());
+            // PCP/DEI 位不应影响 VLAN ID
+            frame.extend_from_slice(&(0xE000 | id).to_be_bytes());
+        }
+        frame.extend_from_slice(&ether_type.to_be_bytes());
+        frame
+    }
+
+    fn ipv4(protocol: u8) -> Vec<u8> {
+        let mut hdr = vec![0x45, 0, 0, 0, 0, 0, 0, 0, 64, protocol, 0, 0];
+        hdr.extend_from_slice(&SRC_V4);
+        hdr.extend_from_slice(&DST_V4);
+        hdr
+    }
+
+    fn ipv6(next_hdr: u8) -> Vec<u8> {
+        let mut hdr = vec![0x60, 0, 0, 0, 0, 0, next_hdr, 64];
+        hdr.extend_from_slice(&SRC_V6);
+        hdr.extend_from_slice(&DST_V6);
+        hdr
+    }
+
+    fn tcp(src_port: u16, dst_port: u16, flags: u8) -> Vec<u8> {
+        let mut hdr = Vec::new();
+        hdr.extend_from_slice(&src_port.to_be_bytes());
+        hdr.extend_from_slice(&dst_port.to_be_bytes());
+        hdr.extend_from_slice(&[0; 8]);
+        hdr.extend_from_slice(&[0x50, flags, 0, 0, 0, 0, 0, 0]);
+        hdr
+    }
+
+    fn udp(src_port: u16, dst_port: u16) -> Vec<u8> {
+        let mut hdr = Vec::new();
+        hdr.extend_from_slice(&src_port.to_be_bytes());
+        hdr.extend_from_slice(&dst_port.to_be_bytes());
+        hdr.extend_from_slice(&[0; 4]);
+        hdr
+    }
+
+    fn frame(parts: &[&[u8]]) -> Vec<u8> {
+        parts.concat()
+    }
+
+    fn ipv4_tcp_frame() -> Vec<u8> {
+        frame(&[&eth(&[], ETH_P_IP), &ipv4(IPPROTO_TCP), &tcp(40000, 443, 0x12), b"hello"])
+    }
+
+    /// 以文件记录的形式解析：没有接口名
+    fn decode(data: &[u8], orig_len: u32, timestamp_ns: u64, direction: u8) -> Option<CapturedEvent> {
+        decode_frame(&PcapPacket { timestamp_ns, data: data.to_vec(), orig_len, direction, iface: None })
+    }
+
+    #[test]
+    fn decodes_ipv4_tcp() {
+        let data = ipv4_tcp_frame();
+        let event = decode(&data, 1500, 1_700_000_000_123_456_789, DIRECTION_INGRESS).unwrap();
+
+        assert_eq!(event.timestamp_ns, 1_700_000_000_123_456_789);
+        assert_eq!(event.ktime_ns, 0);
+        assert_eq!(event.direction, DIRECTION_INGRESS);
+        assert_eq!(event.ip_version, 4);
+        assert_eq!(event.protocol, IPPROTO_TCP);
+        assert_eq!(event.src_addr().to_string(), "10.0.0.1");
+        assert_eq!(event.dst_addr().to_string(), "10.0.0.2");
+        assert_eq!(u16::from_be(event.src_port), 40000);
+        assert_eq!(u16::from_be(event.dst_port), 443);
+        assert_eq!(event.tcp_flags, 0x12);
+        assert_eq!(event.vlans(), &[] as &[u16]);
+        assert_eq!(event.packet_size, 1500);
+        assert_eq!(event.cap_len as usize, data.len());
+        assert_eq!(event.payload_offset, 54);
+        assert_eq!(event.payload(), b"hello");
+    }
+
+    #[test]
+    fn keeps_recorded_direction() {
+        let data = ipv4_tcp_frame();
+        let event = decode(&data, 1500, 0, DIRECTION_EGRESS).unwrap();
+        assert_eq!(event.direction, DIRECTION_EGRESS);
+    }
+
+    #[test]
+    fn uses_recorded_interface_name() {
+        let data = ipv4_tcp_frame();
+        let packet = PcapPacket {
+            timestamp_ns: 0,
+            data: data.clone(),
+            orig_len: 1500,
+            direction: DIRECTION_INGRESS,
+            iface: Some("eth1".to_string()),
+        };
+        let event = decode_frame(&packet).unwrap();
+        assert_eq!(&*event.iface, "eth1");
+        assert_eq!(event.ifindex, 0);
+
+        // 经典 pcap 没有接口名
+        assert_eq!(&*decode(&data, 1500, 0, DIRECTION_INGRESS).unwrap().iface, "-");
+    }
+
+    #[test]
+    fn decodes_ipv4_options_and_icmp() {
+        // IHL = 6，带 4 字节选项
+        let mut ip_hdr = ipv4(IPPROTO_ICMP);
+        ip_hdr[0] = 0x46;
+        ip_hdr.extend_from_slice(&[1, 1, 1, 0]);
+        let data = frame(&[&eth(&[], ETH_P_IP), &ip_hdr, &[8, 0, 0, 0], b"ping"]);
+        let event = decode(&data, data.len() as u32, 0, DIRECTION_INGRESS).unwrap();
+
+        assert_eq!(event.protocol, IPPROTO_ICMP);
+        assert_eq!((event.src_port, event.dst_port), (0, 0));
+        assert_eq!(event.payload_offset, 14 + 24 + 4);
+        assert_eq!(event.payload(), b"ping");
+    }
+
+    #[test]
+    fn decodes_ipv6_udp() {
+        let data = frame(&[&eth(&[], ETH_P_IPV6), &ipv6(IPPROTO_UDP), &udp(5353, 53), b"q"]);
+        let event = decode(&data, data.len() as u32, 0, DIRECTION_INGRESS).unwrap();
+
+        assert_eq!(event.ip_version, 6);
+        assert_eq!(event.protocol, IPPROTO_UDP);
+        assert_eq!(event.src_addr().to_string(), "2001:db8::1");
+        assert_eq!(event.dst_addr().to_string(), "2001:db8::2");
+        assert_eq!(u16::from_be(event.src_port), 5353);
+        assert_eq!(u16::from_be(event.dst_port), 53);
+        assert_eq!(event.payload_offset, 14 + 40 + 8);
+        assert_eq!(event.payload(), b"q");
+    }
+
+    #[test]
+    fn decodes_vlan_and_qinq() {
+        let single = frame(&[&eth(&[(ETH_P_8021Q, 100)], ETH_P_IP), &ipv4(IPPROTO_UDP), &udp(1, 2)]);
+        let event = decode(&single, single.len() as u32, 0, DIRECTION_INGRESS).unwrap();
+        assert_eq!(event.vlans(), &[100]);
+        assert_eq!(event.payload_offset, 14 + 4 + 20 + 8);
+
+        let qinq = frame(&[
+            &eth(&[(ETH_P_8021AD, 200), (ETH_P_8021Q, 4095)], ETH_P_IPV6),
+            &ipv6(IPPROTO_TCP),
+            &tcp(1, 2, 0x02),
+        ]);
+        let event = decode(&qinq, qinq.len() as u32, 0, DIRECTION_INGRESS).unwrap();
+        assert_eq!(event.vlans(), &[200, 4095]);
+        assert_eq!(event.protocol, IPPROTO_TCP);
+        assert_eq!(event.payload_offset, 14 + 8 + 40 + 20);
+    }
+
+    #[test]
+    fn rejects_more_than_two_vlan_tags() {
+        let data = frame(&[
+            &eth(&[(ETH_P_8021AD, 1), (ETH_P_8021Q, 2), (ETH_P_8021Q, 3)], ETH_P_IP),
+            &ipv4(IPPROTO_UDP),
+            &udp(1, 2),
+        ]);
+        assert!(decode(&data, data.len() as u32, 0, DIRECTION_INGRESS).is_none());
+    }
+
+    #[test]
+    fn skips_ipv6_extension_headers() {
+        // 逐跳选项（8 字节）-> 目的选项（16 字节）-> 分片（偏移 0）-> AH（12 字节）-> TCP
+        let hop_by_hop = [IPPROTO_DSTOPTS, 0, 0, 0, 0, 0, 0, 0];
+        let mut dst_opts = vec![IPPROTO_FRAGMENT, 1];
+        dst_opts.resize(16, 0);
+        let fragment = [IPPROTO_AH, 0, 0x00, 0x01, 0, 0, 0, 1];
+        let mut ah = vec![IPPROTO_TCP, 1];
+        ah.resize(12, 0);
+        let data = frame(&[
+            &eth(&[], ETH_P_IPV6),
+            &ipv6(IPPROTO_HOPOPTS),
+            &hop_by_hop,
+            &dst_opts,
+            &fragment,
+            &ah,
+            &tcp(1234, 80, 0x18),
+            b"GET",
+        ]);
+        let event = decode(&data, data.len() as u32, 0, DIRECTION_INGRESS).unwrap();
+
+        assert_eq!(event.protocol, IPPROTO_TCP);
+        assert_eq!(u16::from_be(event.dst_port), 80);
+        assert_eq!(event.payload_offset as usize, 14 + 40 + 8 + 16 + 8 + 12 + 20);
+        assert_eq!(event.payload(), b"GET");
+    }
+
+    #[test]
+    fn rejects_non_first_fragment() {
+        // 偏移 185 * 8 字节，后面不是传输层头
+        let fragment = [IPPROTO_UDP, 0, 0x05, 0xC8, 0, 0, 0, 1];
+        let data = frame(&[&eth(&[], ETH_P_IPV6), &ipv6(IPPROTO_FRAGMENT), &fragment, &udp(1, 2)]);
+        assert!(decode(&data, data.len() as u32, 0, DIRECTION_INGRESS).is_none());
+    }
+
+    #[test]
+    fn rejects_too_many_extension_headers() {
+        let mut data = frame(&[&eth(&[], ETH_P_IPV6), &ipv6(IPPROTO_DSTOPTS)]);
+        for _ in 0..MAX_IPV6_EXT_HDRS {
+            data.extend_from_slice(&[IPPROTO_DSTOPTS, 0, 0, 0, 0, 0, 0, 0]);
+        }
+        data.extend_from_slice(&udp(1, 2));
+        assert!(decode(&data, data.len() as u32, 0, DIRECTION_INGRESS).is_none());
+    }
+
+    #[test]
+    fn rejects_truncated_frames() {
+        let frames = [
+            ipv4_tcp_frame(),
+            frame(&[&eth(&[(ETH_P_8021Q, 7)], ETH_P_IPV6), &ipv6(IPPROTO_UDP), &udp(1, 2)]),
+            frame(&[
+                &eth(&[], ETH_P_IPV6),
+                &ipv6(IPPROTO_HOPOPTS),
+                &[IPPROTO_ICMPV6, 0, 0, 0, 0, 0, 0, 0],
+                &[128, 0, 0, 0],
+            ]),
+        ];
+        for data in &frames {
+            // 去掉 payload 后的完整帧可以解析，任何更短的截断都应被拒绝
+            let event = decode(data, data.len() as u32, 0, DIRECTION_INGRESS).unwrap();
+            let headers_len = event.payload_offset as usize;
+            assert!(decode(&data[..headers_len], data.len() as u32, 0, DIRECTION_INGRESS).is_some());
+            for cut in 0..headers_len {
+                assert!(decode(&data[..cut], data.len() as u32, 0, DIRECTION_INGRESS).is_none(), "cut at {}", cut);
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_unsupported_protocols() {
+        let arp = frame(&[&eth(&[], 0x0806), &[0; 28]]);
+        assert!(decode(&arp, arp.len() as u32, 0, DIRECTION_INGRESS).is_none());
+
+        // GRE
+        let gre = frame(&[&eth(&[], ETH_P_IP), &ipv4(47), &[0; 8]]);
+        assert!(decode(&gre, gre.len() as u32, 0, DIRECTION_INGRESS).is_none());
+    }
+
+    #[test]
+    fn keeps_whole_frame_up_to_u16_max() {
+        let mut data = ipv4_tcp_frame();
+        data.resize(1500, 0xAB);
+        let event = decode(&data, 1500, 0, DIRECTION_INGRESS).unwrap();
+        assert_eq!(event.cap_len, 1500);
+        assert_eq!(event.captured(), &data[..]);
+
+        // 超过 u16 范围的帧截断到 65535 字节，cap_len 与 data 保持一致
+        data.resize(70_000, 0xAB);
+        let event = decode(&data, 70_000, 0, DIRECTION_INGRESS).unwrap();
+        assert_eq!(event.cap_len, u16::MAX);
+        assert_eq!(event.captured().len(), u16::MAX as usize);
+        assert_eq!(event.packet_size, 70_000);
+    }
+
+    #[test]
+    fn payload_offset_stays_within_capture() {
+        // TCP 数据偏移为 15（60 字节头），但只捕获到 20 字节
+        let mut tcp_hdr = tcp(1, 2, 0x10);
+        tcp_hdr[12] = 0xF0;
+        let data = frame(&[&eth(&[], ETH_P_IP), &ipv4(IPPROTO_TCP), &tcp_hdr]);
+        let event = decode(&data, 200, 0, DIRECTION_INGRESS).unwrap();
+        assert_eq!(event.payload_offset as usize, data.len());
+        assert!(event.payload().is_empty());
+    }
+}
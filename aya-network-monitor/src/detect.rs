@@ -0,0 +1,421 @@
+//! 应用层协议识别
+//!
+//! 只看 payload 开头的特征字节，不依赖端口号：8080 上的 HTTP、5353 上的 DNS、
+//! 随机端口上的 TLS/SSH 都能识别。识别结果出现在 JSON 的 `app_protocol` 字段中，
+//! 过滤表达式中可以用 `app NAME` 匹配（只在用户空间求值）。
+//!
+//! 特征按从强到弱的顺序检查，第一个命中的即为结果；DNS 和 NTP 没有魔数，放在最后，
+//! 并且 DNS 要求整个报文能够解析。
+
+use aya_network_monitor_common::{IPPROTO_TCP, IPPROTO_UDP};
+
+use crate::{dissect::Packet, dns, http};
+
+/// 协议名和特征检查函数
+type Signature = (&'static str, fn(&Packet) -> bool);
+
+/// 可识别的应用层协议及其特征，按检查顺序排列
+const SIGNATURES: [Signature; 12] = [
+    ("http2", http2),
+    ("http", http1),
+    ("tls", tls),
+    ("ssh", ssh),
+    ("quic", quic),
+    ("smb", smb),
+    ("bittorrent", bittorrent),
+    ("stun", stun),
+    ("dhcp", dhcp),
+    ("mqtt", mqtt),
+    ("dns", dns_message),
+    ("ntp", ntp),
+];
+
+/// 识别 payload 的应用层协议；无法识别时返回 None
+pub fn detect(packet: &Packet) -> Option<&'static str> {
+    if packet.payload.is_empty() {
+        return None;
+    }
+    SIGNATURES.iter().find(|(_, matches)| matches(packet)).map(|&(name, _)| name)
+}
+
+/// 可识别的协议名，按检查顺序排列
+pub fn names() -> impl Iterator<Item = &'static str> {
+    SIGNATURES.iter().map(|&(name, _)| name)
+}
+
+/// 按名字查找协议（不区分大小写），返回静态的协议名
+pub fn lookup(name: &str) -> Option<&'static str> {
+    names().find(|known| known.eq_ignore_ascii_case(name))
+}
+
+fn tcp(packet: &Packet) -> bool {
+    packet.protocol == IPPROTO_TCP
+}
+
+fn udp(packet: &Packet) -> bool {
+    packet.protocol == IPPROTO_UDP
+}
+
+fn be16(payload: &[u8], offset: usize) -> u16 {
+    u16::from_be_bytes([payload[offset], payload[offset + 1]])
+}
+
+fn be32(payload: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes([payload[offset], payload[offset + 1], payload[offset + 2], payload[offset + 3]])
+}
+
+/// HTTP/2 明文连接前言（h2c）
+fn http2(packet: &Packet) -> bool {
+    tcp(packet) && packet.payload.starts_with(b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n")
+}
+
+/// HTTP/1.x 请求行或状态行
+fn http1(packet: &Packet) -> bool {
+    tcp(packet) && http::looks_like_http(packet.payload)
+}
+
+/// TLS 记录头：类型 20-23，版本 3.x，长度不超过 2^14 + 2048
+fn tls(packet: &Packet) -> bool {
+    let payload = packet.payload;
+    tcp(packet)
+        && payload.len() >= 5
+        && (0x14..=0x17).contains(&payload[0])
+        && payload[1] == 0x03
+        && payload[2] <= 0x04
+        && be16(payload, 3) <= 18432
+}
+
+/// SSH 版本交换：SSH-2.0-xxx 或 SSH-1.99-xxx
+fn ssh(packet: &Packet) -> bool {
+    tcp(packet) && (packet.payload.starts_with(b"SSH-2.0-") || packet.payload.starts_with(b"SSH-1.99-"))
+}
+
+/// QUIC 长包头：首字节最高两位为 1，已知版本号，连接 ID 不超过 20 字节
+fn quic(packet: &Packet) -> bool {
+    let payload = packet.payload;
+    if !udp(packet) || payload.len() < 7 || payload[0] & 0xc0 != 0xc0 {
+        return false;
+    }
+    let version = be32(payload, 1);
+    // v1、v2、draft-xx，0 为版本协商包
+    let known = version == 0x0000_0001 || version == 0x6b33_43cf || version >> 8 == 0x00ff_0000 || version == 0;
+    known && payload[5] <= 20
+}
+
+/// SMB1/SMB2 over TCP：4 字节 NetBIOS 会话头 + \xffSMB 或 \xfeSMB
+fn smb(packet: &Packet) -> bool {
+    let payload = packet.payload;
+    tcp(packet) && payload.len() >= 8 && payload[0] == 0 && matches!(&payload[4..8], b"\xffSMB" | b"\xfeSMB")
+}
+
+/// BitTorrent 握手
+fn bittorrent(packet: &Packet) -> bool {
+    tcp(packet) && packet.payload.starts_with(b"\x13BitTorrent protocol")
+}
+
+/// STUN/TURN：magic cookie 0x2112A442，消息长度与 payload 一致
+fn stun(packet: &Packet) -> bool {
+    let payload = packet.payload;
+    payload.len() >= 20
+        && payload[0] & 0xc0 == 0
+        && be32(payload, 4) == 0x2112_a442
+        && be16(payload, 2) as usize + 20 == payload.len()
+}
+
+/// DHCP/BOOTP：op 为请求或应答，以太网硬件类型，偏移 236 处的 magic cookie
+fn dhcp(packet: &Packet) -> bool {
+    let payload = packet.payload;
+    udp(packet)
+        && payload.len() >= 240
+        && matches!(payload[0], 1 | 2)
+        && payload[1] == 1
+        && payload[236..240] == [99, 130, 83, 99]
+}
+
+/// MQTT CONNECT：固定头 0x10，可变头以协议名 MQTT（3.1.1/5）或 MQIsdp（3.1）开头
+fn mqtt(packet: &Packet) -> bool {
+    let payload = packet.payload;
+    if !tcp(packet) || payload.len() < 10 || payload[0] != 0x10 {
+        return false;
+    }
+    // 剩余长度是 1-4 字节的变长整数
+    let len_bytes = payload[1..].iter().take(4).position(|b| b & 0x80 == 0).map(|i| i + 1);
+    match len_bytes {
+        Some(n) => payload[1 + n..].starts_with(b"\x00\x04MQTT") || payload[1 + n..].starts_with(b"\x00\x06MQIsdp"),
+        None => false,
+    }
+}
+
+/// DNS（含 mDNS）：头部合理并且整个报文可以解析；TCP 上带 2 字节长度前缀
+fn dns_message(packet: &Packet) -> bool {
+    // parse 遇到截断时仍返回已解析的部分，这里要求完整
+    let complete = |message: Option<dns::DnsMessage>| message.is_some_and(|message| !message.truncated);
+    let payload = packet.payload;
+    if udp(packet) {
+        dns::looks_like_dns(payload) && complete(dns::parse(payload))
+    } else {
+        tcp(packet)
+            && payload.len() > 2
+            && be16(payload, 0) as usize == payload.len() - 2
+            && dns::looks_like_dns(&payload[2..])
+            && complete(dns::parse_tcp(payload))
+    }
+}
+
+/// NTP：48 字节，版本 3 或 4，客户端或服务器模式
+fn ntp(packet: &Packet) -> bool {
+    let payload = packet.payload;
+    udp(packet)
+        && payload.len() == 48
+        && matches!((payload[0] >> 3) & 0x07, 3 | 4)
+        && matches!(payload[0] & 0x07, 3 | 4)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 两端都是随机端口，识别不依赖端口号
+    fn packet(protocol: u8, payload: &[u8]) -> Packet<'_> {
+        Packet { protocol, src_port: 40000, dst_port: 40001, payload }
+    }
+
+    fn tcp_app(payload: &[u8]) -> Option<&'static str> {
+        detect(&packet(IPPROTO_TCP, payload))
+    }
+
+    fn udp_app(payload: &[u8]) -> Option<&'static str> {
+        detect(&packet(IPPROTO_UDP, payload))
+    }
+
+    /// example.com A 查询
+    fn dns_query() -> Vec<u8> {
+        let mut msg = vec![0x12, 0x34, 0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0];
+        msg.extend_from_slice(b"\x07example\x03com\x00");
+        msg.extend_from_slice(&[0, 1, 0, 1]);
+        msg
+    }
+
+    /// MQTT CONNECT 的可变头和载荷：协议名、级别、标志、keepalive、客户端 ID
+    fn mqtt_body(name: &[u8]) -> Vec<u8> {
+        let mut body = (name.len() as u16).to_be_bytes().to_vec();
+        body.extend_from_slice(name);
+        body.extend_from_slice(&[0x04, 0x02, 0x00, 0x3c, 0x00, 0x01, b'c']);
+        body
+    }
+
+    /// STUN 绑定请求，属性长度为 attrs
+    fn stun_message(length: u16, attrs: usize) -> Vec<u8> {
+        let mut msg = vec![0x00, 0x01];
+        msg.extend_from_slice(&length.to_be_bytes());
+        msg.extend_from_slice(&[0x21, 0x12, 0xa4, 0x42]);
+        msg.extend_from_slice(&[0xAB; 12]);
+        msg.resize(20 + attrs, 0);
+        msg
+    }
+
+    #[test]
+    fn empty_payload_is_unknown() {
+        assert_eq!(tcp_app(b""), None);
+        assert_eq!(udp_app(b""), None);
+        assert_eq!(tcp_app(b"hello world"), None);
+    }
+
+    #[test]
+    fn lookup_is_case_insensitive() {
+        assert_eq!(names().count(), 12);
+        assert_eq!(lookup("TLS"), Some("tls"));
+        assert_eq!(lookup("BitTorrent"), Some("bittorrent"));
+        assert_eq!(lookup("ftp"), None);
+    }
+
+    #[test]
+    fn detects_http2_preface() {
+        assert_eq!(tcp_app(b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n\x00\x00\x12\x04"), Some("http2"));
+        assert_eq!(udp_app(b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n"), None);
+        assert_eq!(tcp_app(b"PRI * HTTP/2.0\r\n"), None);
+    }
+
+    #[test]
+    fn detects_http1() {
+        assert_eq!(tcp_app(b"GET /index.html HTTP/1.1\r\nHost: a\r\n\r\n"), Some("http"));
+        assert_eq!(tcp_app(b"HTTP/1.1 200 OK\r\n"), Some("http"));
+        assert_eq!(tcp_app(b"GETTING /"), None);
+        assert_eq!(udp_app(b"GET / HTTP/1.1\r\n"), None);
+    }
+
+    #[test]
+    fn detects_tls_record() {
+        assert_eq!(tcp_app(&[0x16, 0x03, 0x01, 0x02, 0x00, 0x01]), Some("tls"));
+        assert_eq!(tcp_app(&[0x17, 0x03, 0x03, 0x48, 0x00]), Some("tls"));
+        // 类型、版本或长度不对
+        assert_eq!(tcp_app(&[0x18, 0x03, 0x01, 0x00, 0x10]), None);
+        assert_eq!(tcp_app(&[0x16, 0x02, 0x00, 0x00, 0x10]), None);
+        assert_eq!(tcp_app(&[0x16, 0x03, 0x05, 0x00, 0x10]), None);
+        assert_eq!(tcp_app(&[0x17, 0x03, 0x03, 0x48, 0x01]), None);
+        assert_eq!(tcp_app(&[0x16, 0x03, 0x01, 0x00]), None);
+        assert_eq!(udp_app(&[0x16, 0x03, 0x01, 0x02, 0x00]), None);
+    }
+
+    #[test]
+    fn detects_ssh_banner() {
+        assert_eq!(tcp_app(b"SSH-2.0-OpenSSH_9.6\r\n"), Some("ssh"));
+        assert_eq!(tcp_app(b"SSH-1.99-Cisco-1.25\r\n"), Some("ssh"));
+        assert_eq!(tcp_app(b"SSH-1.5-old\r\n"), None);
+        assert_eq!(udp_app(b"SSH-2.0-OpenSSH_9.6\r\n"), None);
+    }
+
+    #[test]
+    fn detects_quic_long_header() {
+        let initial = |version: u32, dcid_len: u8| {
+            let mut payload = vec![0xc3];
+            payload.extend_from_slice(&version.to_be_bytes());
+            payload.push(dcid_len);
+            payload.extend_from_slice(&[0x11; 8]);
+            payload
+        };
+        assert_eq!(udp_app(&initial(1, 8)), Some("quic"));
+        assert_eq!(udp_app(&initial(0x6b33_43cf, 8)), Some("quic"));
+        assert_eq!(udp_app(&initial(0xff00_001d, 20)), Some("quic"));
+        assert_eq!(udp_app(&initial(0, 8)), Some("quic"));
+        // 未知版本、连接 ID 过长、短包头、TCP
+        assert_eq!(udp_app(&initial(0x1234_5678, 8)), None);
+        assert_eq!(udp_app(&initial(1, 21)), None);
+        let mut short = initial(1, 8);
+        short[0] = 0x43;
+        assert_eq!(udp_app(&short), None);
+        assert_eq!(tcp_app(&initial(1, 8)), None);
+    }
+
+    #[test]
+    fn detects_smb() {
+        assert_eq!(tcp_app(b"\x00\x00\x00\x40\xfeSMB\x40\x00"), Some("smb"));
+        assert_eq!(tcp_app(b"\x00\x00\x00\x40\xffSMB\x72\x00"), Some("smb"));
+        // NetBIOS 会话请求（0x81）不是 SMB 报文
+        assert_eq!(tcp_app(b"\x81\x00\x00\x40\xfeSMB\x40\x00"), None);
+        assert_eq!(tcp_app(b"\x00\x00\x00\x40\xfdSMB\x40\x00"), None);
+        assert_eq!(udp_app(b"\x00\x00\x00\x40\xfeSMB\x40\x00"), None);
+    }
+
+    #[test]
+    fn detects_bittorrent_handshake() {
+        assert_eq!(tcp_app(b"\x13BitTorrent protocol\x00\x00\x00\x00\x00\x10\x00\x05"), Some("bittorrent"));
+        assert_eq!(tcp_app(b"\x12BitTorrent protocol"), None);
+        assert_eq!(udp_app(b"\x13BitTorrent protocol"), None);
+    }
+
+    #[test]
+    fn detects_stun_with_matching_length() {
+        assert_eq!(udp_app(&stun_message(0, 0)), Some("stun"));
+        assert_eq!(udp_app(&stun_message(8, 8)), Some("stun"));
+        // TURN over TCP 同样识别
+        assert_eq!(tcp_app(&stun_message(8, 8)), Some("stun"));
+        // 长度字段与 payload 不一致（截断或后面跟着别的数据）
+        assert_eq!(udp_app(&stun_message(8, 0)), None);
+        assert_eq!(udp_app(&stun_message(0, 8)), None);
+        assert_eq!(udp_app(&stun_message(8, 12)), None);
+        // magic cookie 不对，或首字节高两位不为 0
+        let mut msg = stun_message(0, 0);
+        msg[7] = 0x43;
+        assert_eq!(udp_app(&msg), None);
+        let mut msg = stun_message(0, 0);
+        msg[0] = 0x40;
+        assert_eq!(udp_app(&msg), None);
+    }
+
+    #[test]
+    fn detects_dhcp() {
+        let mut discover = vec![0u8; 300];
+        discover[..4].copy_from_slice(&[1, 1, 6, 0]);
+        discover[236..240].copy_from_slice(&[99, 130, 83, 99]);
+        assert_eq!(udp_app(&discover), Some("dhcp"));
+        discover[0] = 2;
+        assert_eq!(udp_app(&discover), Some("dhcp"));
+
+        let mut bad = discover.clone();
+        bad[0] = 3;
+        assert_eq!(udp_app(&bad), None);
+        let mut bad = discover.clone();
+        bad[239] = 0;
+        assert_eq!(udp_app(&bad), None);
+        assert_eq!(udp_app(&discover[..239]), None);
+        assert_eq!(tcp_app(&discover), None);
+    }
+
+    #[test]
+    fn detects_mqtt_connect() {
+        // MQTT 3.1.1，剩余长度一个字节
+        let body = mqtt_body(b"MQTT");
+        let connect = [&[0x10, body.len() as u8][..], &body].concat();
+        assert_eq!(tcp_app(&connect), Some("mqtt"));
+        // MQTT 3.1
+        let body = mqtt_body(b"MQIsdp");
+        assert_eq!(tcp_app(&[&[0x10, body.len() as u8][..], &body].concat()), Some("mqtt"));
+        // 剩余长度 321 = 0xC1 0x02，两个字节
+        assert_eq!(tcp_app(&[&[0x10, 0xc1, 0x02][..], &mqtt_body(b"MQTT")].concat()), Some("mqtt"));
+        // 四个字节的最大剩余长度
+        assert_eq!(tcp_app(&[&[0x10, 0xff, 0xff, 0xff, 0x7f][..], &mqtt_body(b"MQTT")].concat()), Some("mqtt"));
+    }
+
+    #[test]
+    fn rejects_bad_mqtt_varint() {
+        let body = mqtt_body(b"MQTT");
+        // 第五个字节仍有续位
+        assert_eq!(tcp_app(&[&[0x10, 0x80, 0x80, 0x80, 0x80, 0x01][..], &body].concat()), None);
+        // 声明两个字节但协议名紧跟在第一个字节之后
+        assert_eq!(tcp_app(&[&[0x10, 0x80][..], &body].concat()), None);
+        // 单字节长度后多出一个字节
+        assert_eq!(tcp_app(&[&[0x10, 0x0d, 0x00][..], &body].concat()), None);
+        // PUBLISH、协议名不对、太短、UDP
+        assert_eq!(tcp_app(&[&[0x30, body.len() as u8][..], &body].concat()), None);
+        assert_eq!(tcp_app(&[&[0x10, 0x0d][..], &mqtt_body(b"MQTX")].concat()), None);
+        assert_eq!(tcp_app(b"\x10\x08\x00\x04MQTT"), None);
+        assert_eq!(udp_app(&[&[0x10, body.len() as u8][..], &body].concat()), None);
+    }
+
+    #[test]
+    fn detects_dns_over_udp() {
+        assert_eq!(udp_app(&dns_query()), Some("dns"));
+        // 头部像 DNS 但问题截断，无法解析
+        let query = dns_query();
+        assert_eq!(udp_app(&query[..query.len() - 3]), None);
+        // 两个问题
+        let mut query = dns_query();
+        query[5] = 2;
+        assert_eq!(udp_app(&query), None);
+    }
+
+    #[test]
+    fn detects_dns_over_tcp() {
+        let query = dns_query();
+        let framed = [&(query.len() as u16).to_be_bytes()[..], &query].concat();
+        assert_eq!(tcp_app(&framed), Some("dns"));
+        // 没有长度前缀
+        assert_eq!(tcp_app(&query), None);
+        // 长度前缀与 payload 不一致
+        let wrong = [&(query.len() as u16 + 1).to_be_bytes()[..], &query].concat();
+        assert_eq!(tcp_app(&wrong), None);
+        assert_eq!(tcp_app(&framed[..framed.len() - 1]), None);
+        assert_eq!(tcp_app(&[0, 0]), None);
+    }
+
+    #[test]
+    fn detects_ntp() {
+        let mut client = vec![0u8; 48];
+        client[0] = 0x23; // LI 0，版本 4，客户端模式
+        assert_eq!(udp_app(&client), Some("ntp"));
+        let mut server = vec![0u8; 48];
+        server[0] = 0x1c; // 版本 3，服务器模式
+        assert_eq!(udp_app(&server), Some("ntp"));
+
+        // 控制报文（模式 6）、版本 2、长度不是 48、TCP
+        let mut control = client.clone();
+        control[0] = 0x26;
+        assert_eq!(udp_app(&control), None);
+        let mut old = client.clone();
+        old[0] = 0x13;
+        assert_eq!(udp_app(&old), None);
+        assert_eq!(udp_app(&[client.as_slice(), &[0; 20]].concat()), None);
+        assert_eq!(tcp_app(&client), None);
+    }
+}
@@ -0,0 +1,431 @@
+//! 协议解析器
+//!
+//! 每种应用层协议实现一个 `Dissector`：`probe` 按内容快速判断 payload 是否像该协议，
+//! `decode` 把 payload 解析为一棵 `Node` 树。`Registry` 先尝试端口提示匹配的解析器，
+//! 再按注册顺序用内容特征探测其余解析器，第一个解析成功的结果即为该包的解析结果。
+//!
+//! 协议模式和 hex/text 模式把树渲染为缩进文本，JSON 模式把树渲染为以协议名为键的对象，
+//! 新增协议只需实现 `Dissector` 并注册，不需要修改输出代码。
+
+use std::fmt;
+
+use serde_json::{Map, Value as JsonValue};
+
+use crate::{dns::DnsDissector, event::CapturedEvent, http::HttpDissector, tls::TlsDissector};
+
+/// 交给解析器的包信息；端口为主机字节序
+#[derive(Debug, Clone, Copy)]
+pub struct Packet<'a> {
+    pub protocol: u8,
+    pub src_port: u16,
+    pub dst_port: u16,
+    pub payload: &'a [u8],
+}
+
+impl<'a> Packet<'a> {
+    /// 捕获事件的传输层 payload
+    pub fn from_event(event: &'a CapturedEvent) -> Self {
+        Packet {
+            protocol: event.protocol,
+            src_port: u16::from_be(event.src_port),
+            dst_port: u16::from_be(event.dst_port),
+            payload: event.payload(),
+        }
+    }
+
+    /// 源端口或目标端口是否为 port
+    pub fn has_port(&self, port: u16) -> bool {
+        self.src_port == port || self.dst_port == port
+    }
+}
+
+/// 解析树中的值
+#[derive(Debug, Clone)]
+pub enum Value {
+    Text(String),
+    Number(u64),
+    Bool(bool),
+    List(Vec<Value>),
+    Node(Node),
+}
+
+impl Value {
+    fn is_scalar(&self) -> bool {
+        !matches!(self, Value::List(_) | Value::Node(_))
+    }
+
+    fn to_json(&self) -> JsonValue {
+        match self {
+            Value::Text(text) => JsonValue::String(text.clone()),
+            Value::Number(n) => JsonValue::from(*n),
+            Value::Bool(b) => JsonValue::Bool(*b),
+            Value::List(items) => JsonValue::Array(items.iter().map(Value::to_json).collect()),
+            Value::Node(node) => node.to_json(),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Text(text) => f.write_str(text),
+            Value::Number(n) => write!(f, "{}", n),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::List(items) => {
+                let items: Vec<String> = items.iter().map(|item| item.to_string()).collect();
+                f.write_str(&items.join(", "))
+            }
+            Value::Node(node) => f.write_str(node.summary.as_deref().unwrap_or("")),
+        }
+    }
+}
+
+impl From<&str> for Value {
+    fn from(text: &str) -> Self {
+        Value::Text(text.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(text: String) -> Self {
+        Value::Text(text)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Value::Bool(b)
+    }
+}
+
+impl From<u8> for Value {
+    fn from(n: u8) -> Self {
+        Value::Number(n as u64)
+    }
+}
+
+impl From<u16> for Value {
+    fn from(n: u16) -> Self {
+        Value::Number(n as u64)
+    }
+}
+
+impl From<u32> for Value {
+    fn from(n: u32) -> Self {
+        Value::Number(n as u64)
+    }
+}
+
+impl From<u64> for Value {
+    fn from(n: u64) -> Self {
+        Value::Number(n)
+    }
+}
+
+impl From<Node> for Value {
+    fn from(node: Node) -> Self {
+        Value::Node(node)
+    }
+}
+
+impl<T: Into<Value>> From<Vec<T>> for Value {
+    fn from(items: Vec<T>) -> Self {
+        Value::List(items.into_iter().map(Into::into).collect())
+    }
+}
+
+/// 解析树的节点：可选的一行摘要 + 有序的字段
+#[derive(Debug, Clone, Default)]
+pub struct Node {
+    pub summary: Option<String>,
+    pub fields: Vec<(String, Value)>,
+}
+
+impl Node {
+    pub fn new(summary: impl Into<String>) -> Self {
+        Node { summary: Some(summary.into()), fields: Vec::new() }
+    }
+
+    /// 追加一个字段
+    pub fn field(mut self, name: impl Into<String>, value: impl Into<Value>) -> Self {
+        self.fields.push((name.into(), value.into()));
+        self
+    }
+
+    /// value 为 Some 时追加字段
+    pub fn field_opt<V: Into<Value>>(self, name: impl Into<String>, value: Option<V>) -> Self {
+        match value {
+            Some(value) => self.field(name, value),
+            None => self,
+        }
+    }
+
+    /// JSON 对象：summary 和各字段；同名字段（如重复的 HTTP 头）合并为数组
+    fn to_json(&self) -> JsonValue {
+        let mut map = Map::new();
+        if let Some(ref summary) = self.summary {
+            map.insert("summary".to_string(), JsonValue::String(summary.clone()));
+        }
+        for (name, value) in &self.fields {
+            let value = value.to_json();
+            if self.fields.iter().filter(|(other, _)| other == name).count() > 1 {
+                if let JsonValue::Array(values) =
+                    map.entry(name.clone()).or_insert_with(|| JsonValue::Array(Vec::new()))
+                {
+                    values.push(value);
+                }
+            } else {
+                map.insert(name.clone(), value);
+            }
+        }
+        JsonValue::Object(map)
+    }
+
+    /// 缩进文本：标量字段一行，列表中的子节点只显示摘要，嵌套节点递归缩进
+    fn render(&self, out: &mut String, indent: usize) {
+        let pad = " ".repeat(indent);
+        for (name, value) in &self.fields {
+            match value {
+                Value::List(items) if items.is_empty() => {}
+                Value::List(items) if items.iter().all(Value::is_scalar) => {
+                    out.push_str(&format!("{}{}: {}\n", pad, name, value));
+                }
+                Value::List(items) => {
+                    out.push_str(&format!("{}{}:\n", pad, name));
+                    for item in items {
+                        match item {
+                            Value::Node(node) if node.summary.is_none() => {
+                                out.push_str(&format!("{}  -\n", pad));
+                                node.render(out, indent + 4);
+                            }
+                            item => out.push_str(&format!("{}  - {}\n", pad, item)),
+                        }
+                    }
+                }
+                Value::Node(node) => {
+                    match node.summary {
+                        Some(ref summary) => out.push_str(&format!("{}{}: {}\n", pad, name, summary)),
+                        None => out.push_str(&format!("{}{}:\n", pad, name)),
+                    }
+                    node.render(out, indent + 2);
+                }
+                scalar => out.push_str(&format!("{}{}: {}\n", pad, name, scalar)),
+            }
+        }
+    }
+}
+
+/// 一个包的解析结果
+#[derive(Debug, Clone)]
+pub struct Dissection {
+    /// 解析器名称，同时是 JSON 中的键
+    pub protocol: &'static str,
+    pub root: Node,
+}
+
+impl Dissection {
+    /// 多行文本：摘要行 + 缩进的字段
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        out.push_str(self.root.summary.as_deref().unwrap_or(self.protocol));
+        out.push('\n');
+        self.root.render(&mut out, 2);
+        out
+    }
+
+    pub fn to_json(&self) -> JsonValue {
+        self.root.to_json()
+    }
+}
+
+/// 协议解析器
+pub trait Dissector: Send + Sync {
+    /// 协议名（小写），用作 JSON 键
+    fn name(&self) -> &'static str;
+
+    /// 端口提示：(IP 协议号, 端口)，源或目标端口匹配时优先尝试，不经过 probe
+    fn ports(&self) -> &[(u8, u16)] {
+        &[]
+    }
+
+    /// 按内容判断 payload 是否可能是该协议；应当只检查少量字节
+    fn probe(&self, packet: &Packet) -> bool;
+
+    /// 解析 payload；无法解析时返回 None
+    fn decode(&self, packet: &Packet) -> Option<Node>;
+}
+
+/// 解析器注册表
+#[derive(Default)]
+pub struct Registry {
+    dissectors: Vec<Box<dyn Dissector>>,
+}
+
+impl Registry {
+    /// 空注册表
+    pub fn new() -> Self {
+        Registry::default()
+    }
+
+    /// 内置解析器：HTTP、DNS、TLS
+    pub fn builtin() -> Self {
+        let mut registry = Registry::new();
+        registry.register(Box::new(HttpDissector));
+        registry.register(Box::new(DnsDissector));
+        registry.register(Box::new(TlsDissector));
+        registry
+    }
+
+    /// 注册一个解析器；内容探测按注册顺序进行
+    pub fn register(&mut self, dissector: Box<dyn Dissector>) {
+        self.dissectors.push(dissector);
+    }
+
+    /// 解析一个包：先试端口提示匹配的解析器，再按内容探测其余解析器
+    pub fn dissect(&self, packet: &Packet) -> Option<Dissection> {
+        if packet.payload.is_empty() {
+            return None;
+        }
+
+        let hinted = |dissector: &dyn Dissector| {
+            dissector.ports().iter().any(|&(protocol, port)| protocol == packet.protocol && packet.has_port(port))
+        };
+
+        let by_port = self.dissectors.iter().filter(|d| hinted(d.as_ref()));
+        let by_content = self.dissectors.iter().filter(|d| !hinted(d.as_ref()) && d.probe(packet));
+
+        by_port.chain(by_content).find_map(|dissector| {
+            dissector.decode(packet).map(|root| Dissection { protocol: dissector.name(), root })
+        })
+    }
+
+    /// 解析一个捕获事件的 payload
+    pub fn dissect_event(&self, event: &CapturedEvent) -> Option<Dissection> {
+        self.dissect(&Packet::from_event(event))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 内容以 magic 开头即解析成功的测试解析器
+    struct Magic {
+        name: &'static str,
+        magic: &'static [u8],
+        ports: &'static [(u8, u16)],
+    }
+
+    impl Dissector for Magic {
+        fn name(&self) -> &'static str {
+            self.name
+        }
+
+        fn ports(&self) -> &[(u8, u16)] {
+            self.ports
+        }
+
+        fn probe(&self, packet: &Packet) -> bool {
+            packet.payload.starts_with(self.magic)
+        }
+
+        fn decode(&self, packet: &Packet) -> Option<Node> {
+            packet.payload.starts_with(self.magic).then(|| Node::new(self.name))
+        }
+    }
+
+    fn registry() -> Registry {
+        let mut registry = Registry::new();
+        registry.register(Box::new(Magic { name: "first", magic: b"AB", ports: &[] }));
+        registry.register(Box::new(Magic { name: "second", magic: b"A", ports: &[(17, 9000)] }));
+        registry
+    }
+
+    fn packet(protocol: u8, ports: (u16, u16), payload: &[u8]) -> Packet<'_> {
+        Packet { protocol, src_port: ports.0, dst_port: ports.1, payload }
+    }
+
+    fn protocol(registry: &Registry, packet: &Packet) -> Option<&'static str> {
+        registry.dissect(packet).map(|dissection| dissection.protocol)
+    }
+
+    #[test]
+    fn probes_in_registration_order() {
+        let registry = registry();
+        assert_eq!(protocol(&registry, &packet(6, (1, 2), b"ABC")), Some("first"));
+        assert_eq!(protocol(&registry, &packet(6, (1, 2), b"AC")), Some("second"));
+        assert_eq!(protocol(&registry, &packet(6, (1, 2), b"XY")), None);
+        assert_eq!(protocol(&registry, &packet(6, (1, 2), b"")), None);
+    }
+
+    #[test]
+    fn port_hint_takes_precedence() {
+        let registry = registry();
+        // 源端口或目标端口匹配，且 IP 协议号一致
+        assert_eq!(protocol(&registry, &packet(17, (9000, 1), b"ABC")), Some("second"));
+        assert_eq!(protocol(&registry, &packet(17, (1, 9000), b"ABC")), Some("second"));
+        assert_eq!(protocol(&registry, &packet(6, (1, 9000), b"ABC")), Some("first"));
+
+        // 端口提示的解析器解析失败时继续按内容探测
+        let mut registry = Registry::new();
+        registry.register(Box::new(Magic { name: "hinted", magic: b"Z", ports: &[(17, 9000)] }));
+        registry.register(Box::new(Magic { name: "probed", magic: b"A", ports: &[] }));
+        assert_eq!(protocol(&registry, &packet(17, (1, 9000), b"ABC")), Some("probed"));
+    }
+
+    fn sample() -> Dissection {
+        let question = Node::default().field("name", "example.com").field("type", "A");
+        let root = Node::new("Sample Message")
+            .field("id", 7u16)
+            .field("flags", vec!["rd", "ra"])
+            .field("empty", Vec::<u8>::new())
+            .field("header", "a")
+            .field("header", "b")
+            .field("questions", vec![question])
+            .field("answers", vec![Node::new("example.com A 1.2.3.4")])
+            .field("section", Node::new("summary").field("ok", true))
+            .field_opt("missing", None::<u8>);
+        Dissection { protocol: "sample", root }
+    }
+
+    #[test]
+    fn renders_indented_text() {
+        assert_eq!(
+            sample().to_text(),
+            "Sample Message\n\
+             \x20 id: 7\n\
+             \x20 flags: rd, ra\n\
+             \x20 header: a\n\
+             \x20 header: b\n\
+             \x20 questions:\n\
+             \x20   -\n\
+             \x20     name: example.com\n\
+             \x20     type: A\n\
+             \x20 answers:\n\
+             \x20   - example.com A 1.2.3.4\n\
+             \x20 section: summary\n\
+             \x20   ok: true\n"
+        );
+
+        // 没有摘要时以协议名开头
+        let dissection = Dissection { protocol: "bare", root: Node::default().field("n", 1u8) };
+        assert_eq!(dissection.to_text(), "bare\n  n: 1\n");
+    }
+
+    #[test]
+    fn renders_json_object() {
+        assert_eq!(
+            sample().to_json(),
+            serde_json::json!({
+                "summary": "Sample Message",
+                "id": 7,
+                "flags": ["rd", "ra"],
+                "empty": [],
+                "header": ["a", "b"],
+                "questions": [{ "name": "example.com", "type": "A" }],
+                "answers": [{ "summary": "example.com A 1.2.3.4" }],
+                "section": { "summary": "summary", "ok": true },
+            })
+        );
+    }
+}
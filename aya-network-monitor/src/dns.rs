@@ -0,0 +1,743 @@
+//! DNS 解析
+//!
+//! 解析 DNS 报文的头部、问题和回答/授权/附加三个资源记录段，跟随名字压缩指针，
+//! 解码常见的记录类型（A/AAAA/CNAME/NS/PTR/MX/TXT/SRV/SOA/SVCB/HTTPS）和 EDNS（OPT）。
+//! 报文被 `--snaplen` 截断时返回已经解析到的部分，并标记 `truncated`。
+
+use std::{
+    fmt,
+    net::{Ipv4Addr, Ipv6Addr},
+};
+
+use crate::dissect::{Dissector, Node, Packet};
+
+const HEADER_LEN: usize = 12;
+/// 跟随压缩指针的次数上限；指针只能指向前面的数据，不会成环，这里限制指针链的长度
+const MAX_POINTERS: usize = 64;
+
+const TYPE_A: u16 = 1;
+const TYPE_NS: u16 = 2;
+const TYPE_CNAME: u16 = 5;
+const TYPE_SOA: u16 = 6;
+const TYPE_PTR: u16 = 12;
+const TYPE_MX: u16 = 15;
+const TYPE_TXT: u16 = 16;
+const TYPE_AAAA: u16 = 28;
+const TYPE_SRV: u16 = 33;
+const TYPE_DNAME: u16 = 39;
+const TYPE_OPT: u16 = 41;
+const TYPE_SVCB: u16 = 64;
+const TYPE_HTTPS: u16 = 65;
+
+/// 记录类型的名称；未知类型按 RFC 3597 显示为 TYPE<n>
+pub fn type_name(rtype: u16) -> String {
+    match rtype {
+        TYPE_A => "A",
+        TYPE_NS => "NS",
+        TYPE_CNAME => "CNAME",
+        TYPE_SOA => "SOA",
+        TYPE_PTR => "PTR",
+        TYPE_MX => "MX",
+        TYPE_TXT => "TXT",
+        TYPE_AAAA => "AAAA",
+        TYPE_SRV => "SRV",
+        TYPE_DNAME => "DNAME",
+        TYPE_OPT => "OPT",
+        43 => "DS",
+        46 => "RRSIG",
+        47 => "NSEC",
+        48 => "DNSKEY",
+        50 => "NSEC3",
+        TYPE_SVCB => "SVCB",
+        TYPE_HTTPS => "HTTPS",
+        257 => "CAA",
+        255 => "ANY",
+        other => return format!("TYPE{}", other),
+    }
+    .to_string()
+}
+
+fn class_name(class: u16) -> String {
+    match class {
+        1 => "IN".to_string(),
+        3 => "CH".to_string(),
+        4 => "HS".to_string(),
+        254 => "NONE".to_string(),
+        255 => "ANY".to_string(),
+        other => format!("CLASS{}", other),
+    }
+}
+
+/// 响应码的名称（含 EDNS 扩展的高 8 位）
+fn rcode_name(rcode: u16) -> String {
+    match rcode {
+        0 => "NOERROR".to_string(),
+        1 => "FORMERR".to_string(),
+        2 => "SERVFAIL".to_string(),
+        3 => "NXDOMAIN".to_string(),
+        4 => "NOTIMP".to_string(),
+        5 => "REFUSED".to_string(),
+        6 => "YXDOMAIN".to_string(),
+        7 => "YXRRSET".to_string(),
+        8 => "NXRRSET".to_string(),
+        9 => "NOTAUTH".to_string(),
+        10 => "NOTZONE".to_string(),
+        16 => "BADVERS".to_string(),
+        23 => "BADCOOKIE".to_string(),
+        other => format!("RCODE{}", other),
+    }
+}
+
+/// 问题段中的一项
+#[derive(Debug, Clone)]
+pub struct Question {
+    pub name: String,
+    pub qtype: String,
+    pub class: String,
+}
+
+/// SVCB/HTTPS 的一个参数，值为展示格式
+#[derive(Debug, Clone)]
+pub struct SvcParam {
+    pub key: String,
+    pub value: String,
+}
+
+/// 资源记录的数据
+#[derive(Debug, Clone)]
+pub enum RData {
+    A(Ipv4Addr),
+    Aaaa(Ipv6Addr),
+    /// CNAME、NS、PTR、DNAME
+    Name(String),
+    Mx {
+        preference: u16,
+        exchange: String,
+    },
+    Txt(Vec<String>),
+    Srv {
+        priority: u16,
+        weight: u16,
+        port: u16,
+        target: String,
+    },
+    Soa {
+        mname: String,
+        rname: String,
+        serial: u32,
+        refresh: u32,
+        retry: u32,
+        expire: u32,
+        minimum: u32,
+    },
+    /// SVCB、HTTPS
+    Svcb {
+        priority: u16,
+        target: String,
+        params: Vec<SvcParam>,
+    },
+    /// EDNS 伪记录
+    Opt {
+        udp_payload_size: u16,
+        version: u8,
+        dnssec_ok: bool,
+        options: Vec<u16>,
+    },
+    /// 未解码的类型，十六进制
+    Unknown(String),
+}
+
+impl fmt::Display for RData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RData::A(addr) => write!(f, "{}", addr),
+            RData::Aaaa(addr) => write!(f, "{}", addr),
+            RData::Name(name) => f.write_str(name),
+            RData::Mx { preference, exchange } => write!(f, "{} {}", preference, exchange),
+            RData::Txt(strings) => {
+                let quoted: Vec<String> = strings.iter().map(|s| format!("{:?}", s)).collect();
+                f.write_str(&quoted.join(" "))
+            }
+            RData::Srv { priority, weight, port, target } => write!(f, "{} {} {} {}", priority, weight, port, target),
+            RData::Soa { mname, rname, serial, refresh, retry, expire, minimum } => {
+                write!(f, "{} {} {} {} {} {} {}", mname, rname, serial, refresh, retry, expire, minimum)
+            }
+            RData::Svcb { priority, target, params } => {
+                write!(f, "{} {}", priority, target)?;
+                for param in params {
+                    if param.value.is_empty() {
+                        write!(f, " {}", param.key)?;
+                    } else {
+                        write!(f, " {}={}", param.key, param.value)?;
+                    }
+                }
+                Ok(())
+            }
+            RData::Opt { udp_payload_size, version, dnssec_ok, options } => {
+                write!(f, "udp={} version={}", udp_payload_size, version)?;
+                if *dnssec_ok {
+                    f.write_str(" do")?;
+                }
+                if !options.is_empty() {
+                    let codes: Vec<String> = options.iter().map(|code| code.to_string()).collect();
+                    write!(f, " options={}", codes.join(","))?;
+                }
+                Ok(())
+            }
+            RData::Unknown(hex) => write!(f, "\\# {} {}", hex.len() / 2, hex),
+        }
+    }
+}
+
+/// 资源记录
+#[derive(Debug, Clone)]
+pub struct Record {
+    pub name: String,
+    pub rtype: String,
+    /// OPT 记录的 class 是 UDP 负载大小，显示在 data 中
+    pub class: Option<String>,
+    pub ttl: u32,
+    pub data: RData,
+}
+
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.class {
+            Some(ref class) => write!(f, "{} {} {} {} {}", self.name, self.ttl, class, self.rtype, self.data),
+            None => write!(f, "{} {} {}", self.name, self.rtype, self.data),
+        }
+    }
+}
+
+/// 解析后的 DNS 报文
+#[derive(Debug, Clone)]
+pub struct DnsMessage {
+    pub id: u16,
+    pub response: bool,
+    pub opcode: u8,
+    /// 置位的标志：aa, tc, rd, ra, ad, cd
+    pub flags: Vec<&'static str>,
+    /// 响应码，有 OPT 记录时包含扩展的高 8 位
+    pub rcode: String,
+    pub questions: Vec<Question>,
+    pub answers: Vec<Record>,
+    pub authority: Vec<Record>,
+    pub additional: Vec<Record>,
+    /// 头部声明的记录数多于实际解析到的（报文被截断或格式错误）
+    pub truncated: bool,
+}
+
+/// 按大端读取报文；名字解析需要整个报文以跟随压缩指针
+struct Reader<'a> {
+    msg: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self.msg.get(self.pos..self.pos.checked_add(len)?)?;
+        self.pos += len;
+        Some(bytes)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.bytes(1).map(|b| b[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        self.bytes(2).map(|b| u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        self.bytes(4).map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    /// 读取一个（可能压缩的）名字，以 . 结尾；根为 "."
+    fn name(&mut self) -> Option<String> {
+        let mut name = String::new();
+        let mut pos = self.pos;
+        // 第一次跳转后，调用方的读取位置停在指针之后
+        let mut resume = None;
+        let mut pointers = 0;
+
+        loop {
+            let len = *self.msg.get(pos)?;
+            match len & 0xc0 {
+                0x00 => {
+                    pos += 1;
+                    if len == 0 {
+                        break;
+                    }
+                    let label = self.msg.get(pos..pos + len as usize)?;
+                    for &byte in label {
+                        // 与 dig 一样转义 . 和不可打印字符
+                        match byte {
+                            b'.' | b'\\' => {
+                                name.push('\\');
+                                name.push(byte as char);
+                            }
+                            0x21..=0x7e => name.push(byte as char),
+                            _ => name.push_str(&format!("\\{:03}", byte)),
+                        }
+                    }
+                    name.push('.');
+                    pos += len as usize;
+                }
+                0xc0 => {
+                    let low = *self.msg.get(pos + 1)?;
+                    if resume.is_none() {
+                        resume = Some(pos + 2);
+                    }
+                    pointers += 1;
+                    // 指针只能指向前面已经出现的数据，向后或指向自身的指针视为格式错误
+                    let target = (((len & 0x3f) as usize) << 8) | low as usize;
+                    if target >= pos || pointers > MAX_POINTERS {
+                        return None;
+                    }
+                    pos = target;
+                }
+                // 0x40 / 0x80 为保留的标签类型
+                _ => return None,
+            }
+        }
+
+        self.pos = resume.unwrap_or(pos);
+        if name.is_empty() {
+            name.push('.');
+        }
+        Some(name)
+    }
+}
+
+/// 解析 UDP 上的 DNS 报文；不足 12 字节的头部时返回 None
+pub fn parse(payload: &[u8]) -> Option<DnsMessage> {
+    if payload.len() < HEADER_LEN {
+        return None;
+    }
+
+    let mut r = Reader { msg: payload, pos: 0 };
+    let id = r.u16()?;
+    let flags = r.u16()?;
+    let counts = [r.u16()?, r.u16()?, r.u16()?, r.u16()?];
+
+    const FLAG_NAMES: [(u16, &str); 6] =
+        [(0x0400, "aa"), (0x0200, "tc"), (0x0100, "rd"), (0x0080, "ra"), (0x0020, "ad"), (0x0010, "cd")];
+
+    let mut message = DnsMessage {
+        id,
+        response: flags & 0x8000 != 0,
+        opcode: ((flags >> 11) & 0x0f) as u8,
+        flags: FLAG_NAMES.iter().filter(|(bit, _)| flags & bit != 0).map(|(_, name)| *name).collect(),
+        rcode: String::new(),
+        questions: Vec::new(),
+        answers: Vec::new(),
+        authority: Vec::new(),
+        additional: Vec::new(),
+        truncated: false,
+    };
+
+    let mut rcode = flags & 0x000f;
+    let complete = (|| {
+        for _ in 0..counts[0] {
+            let name = r.name()?;
+            let qtype = r.u16()?;
+            let class = r.u16()?;
+            message.questions.push(Question { name, qtype: type_name(qtype), class: class_name(class) });
+        }
+
+        for (section, count) in counts[1..].iter().enumerate() {
+            for _ in 0..*count {
+                let (record, extended_rcode) = parse_record(&mut r)?;
+                if let Some(extended_rcode) = extended_rcode {
+                    rcode |= (extended_rcode as u16) << 4;
+                }
+                match section {
+                    0 => message.answers.push(record),
+                    1 => message.authority.push(record),
+                    _ => message.additional.push(record),
+                }
+            }
+        }
+        Some(())
+    })()
+    .is_some();
+
+    message.truncated = !complete;
+    message.rcode = rcode_name(rcode);
+    Some(message)
+}
+
+/// 解析 TCP 上的 DNS 报文（前面有 2 字节长度）
+pub fn parse_tcp(payload: &[u8]) -> Option<DnsMessage> {
+    parse(payload.get(2..)?)
+}
+
+/// 解析一条资源记录；OPT 记录同时返回扩展响应码的高 8 位
+fn parse_record(r: &mut Reader<'_>) -> Option<(Record, Option<u8>)> {
+    let name = r.name()?;
+    let rtype = r.u16()?;
+    let class = r.u16()?;
+    let ttl = r.u32()?;
+    let rdlength = r.u16()? as usize;
+    let rdata_start = r.pos;
+    let rdata = r.bytes(rdlength)?;
+
+    if rtype == TYPE_OPT {
+        // class 为 UDP 负载大小，ttl 为 扩展 RCODE(8) | 版本(8) | DO(1) | Z(15)
+        let mut options = Vec::new();
+        let mut opt = Reader { msg: rdata, pos: 0 };
+        while let (Some(code), Some(len)) = (opt.u16(), opt.u16()) {
+            if opt.bytes(len as usize).is_none() {
+                break;
+            }
+            options.push(code);
+        }
+        let data = RData::Opt {
+            udp_payload_size: class,
+            version: (ttl >> 16) as u8,
+            dnssec_ok: ttl & 0x8000 != 0,
+            options,
+        };
+        let record = Record { name, rtype: type_name(rtype), class: None, ttl: 0, data };
+        return Some((record, Some((ttl >> 24) as u8)));
+    }
+
+    // 名字可能压缩，指向 rdata 之外，所以在整个报文上解析
+    let mut data_reader = Reader { msg: &r.msg[..rdata_start + rdlength], pos: rdata_start };
+    let data = parse_rdata(rtype, rdata, &mut data_reader).unwrap_or_else(|| RData::Unknown(hex(rdata)));
+
+    let record = Record { name, rtype: type_name(rtype), class: Some(class_name(class)), ttl, data };
+    Some((record, None))
+}
+
+fn parse_rdata(rtype: u16, rdata: &[u8], r: &mut Reader<'_>) -> Option<RData> {
+    let data = match rtype {
+        TYPE_A => RData::A(Ipv4Addr::from(<[u8; 4]>::try_from(rdata).ok()?)),
+        TYPE_AAAA => RData::Aaaa(Ipv6Addr::from(<[u8; 16]>::try_from(rdata).ok()?)),
+        TYPE_CNAME | TYPE_NS | TYPE_PTR | TYPE_DNAME => RData::Name(r.name()?),
+        TYPE_MX => RData::Mx { preference: r.u16()?, exchange: r.name()? },
+        TYPE_TXT => {
+            let mut strings = Vec::new();
+            while let Some(len) = r.u8() {
+                strings.push(String::from_utf8_lossy(r.bytes(len as usize)?).into_owned());
+            }
+            RData::Txt(strings)
+        }
+        TYPE_SRV => RData::Srv { priority: r.u16()?, weight: r.u16()?, port: r.u16()?, target: r.name()? },
+        TYPE_SOA => RData::Soa {
+            mname: r.name()?,
+            rname: r.name()?,
+            serial: r.u32()?,
+            refresh: r.u32()?,
+            retry: r.u32()?,
+            expire: r.u32()?,
+            minimum: r.u32()?,
+        },
+        TYPE_SVCB | TYPE_HTTPS => {
+            let priority = r.u16()?;
+            let target = r.name()?;
+            let mut params = Vec::new();
+            while let Some(key) = r.u16() {
+                let len = r.u16()?;
+                let value = r.bytes(len as usize)?;
+                params.push(svc_param(key, value));
+            }
+            RData::Svcb { priority, target, params }
+        }
+        _ => return None,
+    };
+    Some(data)
+}
+
+/// SVCB 参数的展示格式（RFC 9460）
+fn svc_param(key: u16, value: &[u8]) -> SvcParam {
+    let text = match key {
+        0 => value
+            .chunks_exact(2)
+            .map(|k| svc_key_name(u16::from_be_bytes([k[0], k[1]])))
+            .collect::<Vec<_>>()
+            .join(","),
+        1 => {
+            let mut alpn = Vec::new();
+            let mut r = Reader { msg: value, pos: 0 };
+            while let Some(len) = r.u8() {
+                match r.bytes(len as usize) {
+                    Some(id) => alpn.push(String::from_utf8_lossy(id).into_owned()),
+                    None => break,
+                }
+            }
+            alpn.join(",")
+        }
+        2 => String::new(),
+        3 if value.len() == 2 => u16::from_be_bytes([value[0], value[1]]).to_string(),
+        4 => value
+            .chunks_exact(4)
+            .map(|a| Ipv4Addr::new(a[0], a[1], a[2], a[3]).to_string())
+            .collect::<Vec<_>>()
+            .join(","),
+        // ECH 配置较长，只显示长度
+        5 => format!("<{} bytes>", value.len()),
+        6 => value
+            .chunks_exact(16)
+            .map(|a| Ipv6Addr::from(<[u8; 16]>::try_from(a).unwrap()).to_string())
+            .collect::<Vec<_>>()
+            .join(","),
+        _ => hex(value),
+    };
+    SvcParam { key: svc_key_name(key), value: text }
+}
+
+fn svc_key_name(key: u16) -> String {
+    match key {
+        0 => "mandatory".to_string(),
+        1 => "alpn".to_string(),
+        2 => "no-default-alpn".to_string(),
+        3 => "port".to_string(),
+        4 => "ipv4hint".to_string(),
+        5 => "ech".to_string(),
+        6 => "ipv6hint".to_string(),
+        other => format!("key{}", other),
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// 摘要行，如 DNS Response id=0x3f2a NOERROR [rd ra] (1 questions, 2 answers, 0 authority, 1 additional)
+fn summary(message: &DnsMessage) -> String {
+    let mut output = String::new();
+    output.push_str(if message.response { "DNS Response" } else { "DNS Query" });
+    output.push_str(&format!(" id=0x{:04x}", message.id));
+    if message.response {
+        output.push_str(&format!(" {}", message.rcode));
+    }
+    if message.opcode != 0 {
+        output.push_str(&format!(" opcode={}", message.opcode));
+    }
+    if !message.flags.is_empty() {
+        output.push_str(&format!(" [{}]", message.flags.join(" ")));
+    }
+    output.push_str(&format!(
+        " ({} questions, {} answers, {} authority, {} additional)",
+        message.questions.len(),
+        message.answers.len(),
+        message.authority.len(),
+        message.additional.len()
+    ));
+    if message.truncated {
+        output.push_str(" [truncated]");
+    }
+    output
+}
+
+fn record_nodes(records: &[Record]) -> Vec<Node> {
+    records
+        .iter()
+        .map(|record| {
+            Node::new(record.to_string())
+                .field("name", record.name.clone())
+                .field("type", record.rtype.clone())
+                .field_opt("class", record.class.clone())
+                .field("ttl", record.ttl)
+                .field("data", record.data.to_string())
+        })
+        .collect()
+}
+
+/// 解析树：问题和资源记录各为一个带摘要的子节点
+pub fn to_node(message: &DnsMessage) -> Node {
+    let questions: Vec<Node> = message
+        .questions
+        .iter()
+        .map(|question| {
+            Node::new(format!("{} {} {}", question.name, question.qtype, question.class))
+                .field("name", question.name.clone())
+                .field("type", question.qtype.clone())
+                .field("class", question.class.clone())
+        })
+        .collect();
+
+    Node::new(summary(message))
+        .field("id", message.id)
+        .field("response", message.response)
+        .field("opcode", message.opcode)
+        .field("flags", message.flags.iter().map(|flag| flag.to_string()).collect::<Vec<_>>())
+        .field("rcode", message.rcode.clone())
+        .field("questions", questions)
+        .field("answers", record_nodes(&message.answers))
+        .field("authority", record_nodes(&message.authority))
+        .field("additional", record_nodes(&message.additional))
+        .field("truncated", message.truncated)
+}
+
+/// 头部是否像 DNS 报文：opcode 0、Z 位为 0、一个问题、其余记录数不大
+pub fn looks_like_dns(payload: &[u8]) -> bool {
+    if payload.len() < HEADER_LEN + 5 {
+        return false;
+    }
+    let count = |offset: usize| u16::from_be_bytes([payload[offset], payload[offset + 1]]);
+    let flags = count(2);
+    flags & 0x7840 == 0 && count(4) == 1 && count(6) <= 64 && count(8) <= 64 && count(10) <= 64
+}
+
+/// DNS 解析器：53 端口（UDP，或带长度前缀的 TCP）和形似 DNS 头部的 UDP 报文
+pub struct DnsDissector;
+
+impl Dissector for DnsDissector {
+    fn name(&self) -> &'static str {
+        "dns"
+    }
+
+    fn ports(&self) -> &[(u8, u16)] {
+        &[(17, 53), (6, 53), (17, 5353)]
+    }
+
+    fn probe(&self, packet: &Packet) -> bool {
+        packet.protocol == 17 && looks_like_dns(packet.payload)
+    }
+
+    fn decode(&self, packet: &Packet) -> Option<Node> {
+        let message = match packet.protocol {
+            6 => parse_tcp(packet.payload)?,
+            _ => parse(packet.payload)?,
+        };
+        Some(to_node(&message))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: [u8; 12] = [0x12, 0x34, 0x81, 0x80, 0, 1, 0, 2, 0, 0, 0, 0];
+
+    /// www.example.com 的响应：CNAME 到 cdn.example.com，再给出它的 A 记录，名字全部压缩
+    fn compressed_response() -> Vec<u8> {
+        let mut msg = HEADER.to_vec();
+        // 问题（偏移 12）：www.example.com A IN
+        msg.extend_from_slice(b"\x03www\x07example\x03com\x00\x00\x01\x00\x01");
+        // 回答 1（偏移 33）：指向问题中的名字；rdata（偏移 45）为 cdn + 指向 example.com（偏移 16）
+        msg.extend_from_slice(&[0xc0, 12, 0, 5, 0, 1, 0, 0, 0x01, 0x2c, 0, 6]);
+        msg.extend_from_slice(b"\x03cdn\xc0\x10");
+        // 回答 2：指向回答 1 的 rdata
+        msg.extend_from_slice(&[0xc0, 45, 0, 1, 0, 1, 0, 0, 0, 60, 0, 4, 93, 184, 216, 34]);
+        msg
+    }
+
+    /// 只有一个问题的查询，问题名字为 name
+    fn query(name: &[u8]) -> Vec<u8> {
+        let mut msg = vec![0x12, 0x34, 0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0];
+        msg.extend_from_slice(name);
+        msg.extend_from_slice(&[0, 1, 0, 1]);
+        msg
+    }
+
+    #[test]
+    fn compressed_response_is_decoded() {
+        let message = parse(&compressed_response()).unwrap();
+        assert!(message.response);
+        assert_eq!(message.flags, ["rd", "ra"]);
+        assert_eq!(message.rcode, "NOERROR");
+        assert!(!message.truncated);
+
+        assert_eq!(message.questions.len(), 1);
+        assert_eq!(message.questions[0].name, "www.example.com.");
+        assert_eq!(message.questions[0].qtype, "A");
+
+        assert_eq!(message.answers.len(), 2);
+        assert_eq!(message.answers[0].to_string(), "www.example.com. 300 IN CNAME cdn.example.com.");
+        assert_eq!(message.answers[1].to_string(), "cdn.example.com. 60 IN A 93.184.216.34");
+    }
+
+    #[test]
+    fn tcp_length_prefix() {
+        let msg = compressed_response();
+        let mut tcp = (msg.len() as u16).to_be_bytes().to_vec();
+        tcp.extend_from_slice(&msg);
+        assert_eq!(parse_tcp(&tcp).unwrap().answers.len(), 2);
+        assert!(parse_tcp(&[0]).is_none());
+    }
+
+    #[test]
+    fn pointer_loops_are_rejected() {
+        // 指向自身
+        let msg = query(&[0xc0, 12]);
+        assert!(Reader { msg: &msg, pos: 12 }.name().is_none());
+        let message = parse(&msg).unwrap();
+        assert!(message.questions.is_empty());
+        assert!(message.truncated);
+
+        // 两个指针互相指向：12 -> 14 -> 12
+        let msg = query(&[0xc0, 14, 0xc0, 12]);
+        assert!(Reader { msg: &msg, pos: 12 }.name().is_none());
+        assert!(Reader { msg: &msg, pos: 14 }.name().is_none());
+        assert!(parse(&msg).unwrap().questions.is_empty());
+    }
+
+    #[test]
+    fn forward_pointers_are_rejected() {
+        // 问题名字指向问题之后的 foo.（偏移 18）
+        let mut msg = query(&[0xc0, 18]);
+        msg.extend_from_slice(b"\x03foo\x00");
+        assert!(Reader { msg: &msg, pos: 12 }.name().is_none());
+        let message = parse(&msg).unwrap();
+        assert!(message.questions.is_empty());
+        assert!(message.truncated);
+
+        // 指针越过报文末尾
+        let msg = query(&[0xff, 0xff]);
+        assert!(Reader { msg: &msg, pos: 12 }.name().is_none());
+    }
+
+    #[test]
+    fn truncated_rdata() {
+        let full = compressed_response();
+
+        // A 记录的 rdata 少了 2 字节：该记录丢弃，前面的记录保留
+        let message = parse(&full[..full.len() - 2]).unwrap();
+        assert!(message.truncated);
+        assert_eq!(message.answers.len(), 1);
+
+        // rdlength 超出报文
+        let mut msg = full.clone();
+        let rdlength = msg.len() - 6;
+        msg[rdlength..rdlength + 2].copy_from_slice(&200u16.to_be_bytes());
+        let mut r = Reader { msg: &msg, pos: 51 };
+        assert!(parse_record(&mut r).is_none());
+        assert_eq!(parse(&msg).unwrap().answers.len(), 1);
+
+        // rdata 中的名字超出 rdlength：按未知数据显示，不读 rdata 之外的字节
+        let mut msg = full.clone();
+        msg[44] = 2;
+        msg.truncate(47);
+        msg[7] = 1;
+        let message = parse(&msg).unwrap();
+        assert!(matches!(message.answers[0].data, RData::Unknown(_)));
+
+        // A 记录长度不是 4
+        let mut msg = full;
+        msg[62] = 3;
+        msg.pop();
+        assert!(matches!(parse(&msg).unwrap().answers[1].data, RData::Unknown(_)));
+    }
+
+    #[test]
+    fn malformed_input_does_not_panic() {
+        let full = compressed_response();
+        for len in 0..full.len() {
+            let message = parse(&full[..len]);
+            assert_eq!(message.is_none(), len < HEADER_LEN);
+            if let Some(message) = message {
+                assert!(message.truncated, "长度 {}", len);
+            }
+        }
+        for i in HEADER_LEN..full.len() {
+            for value in [0x00, 0x3f, 0x40, 0x80, 0xc0, 0xff] {
+                let mut msg = full.clone();
+                msg[i] = value;
+                let _ = parse(&msg);
+            }
+        }
+    }
+}
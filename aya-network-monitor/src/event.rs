@@ -0,0 +1,180 @@
+//! 用户空间事件
+//!
+//! eBPF 发送的每条记录由固定的 `NetworkEvent` 头部和紧随其后的 `cap_len` 字节原始帧组成。
+//! 头部中的 `ktime_ns` 是内核单调时钟，接收时通过 `KernelClock` 换算为 Unix 时间；
+//! `ifindex` 通过 `Interfaces` 换算为网卡名。
+
+use std::{ops::Deref, sync::Arc};
+
+use aya_network_monitor_common::NetworkEvent;
+
+use crate::iface::Interfaces;
+
+/// 内核单调时钟（CLOCK_MONOTONIC）到 Unix 时间的换算
+///
+/// `bpf_ktime_get_ns` 与用户空间的 CLOCK_MONOTONIC 是同一个时钟，
+/// 启动时采样一次两者的差值即可把内核时间戳转换为墙上时间。
+#[derive(Debug, Clone, Copy)]
+pub struct KernelClock {
+    offset_ns: i128,
+}
+
+impl KernelClock {
+    /// 采样当前的 CLOCK_REALTIME - CLOCK_MONOTONIC 差值
+    pub fn sample() -> Self {
+        // 在两次 REALTIME 读取之间读取 MONOTONIC，取中点以减小误差
+        let before = clock_ns(libc::CLOCK_REALTIME);
+        let monotonic = clock_ns(libc::CLOCK_MONOTONIC);
+        let after = clock_ns(libc::CLOCK_REALTIME);
+        let realtime = before + (after - before) / 2;
+
+        KernelClock { offset_ns: realtime - monotonic }
+    }
+
+    /// 把 bpf_ktime_get_ns 时间戳转换为 Unix 时间（纳秒）
+    pub fn to_unix_ns(self, ktime_ns: u64) -> u64 {
+        (ktime_ns as i128 + self.offset_ns).max(0) as u64
+    }
+}
+
+fn clock_ns(clock: libc::clockid_t) -> i128 {
+    let mut ts = libc::timespec { tv_sec: 0, tv_nsec: 0 };
+    unsafe { libc::clock_gettime(clock, &mut ts) };
+    ts.tv_sec as i128 * 1_000_000_000 + ts.tv_nsec as i128
+}
+
+/// 头部 + 原始帧数据
+#[derive(Debug, Clone)]
+pub struct CapturedEvent {
+    pub header: NetworkEvent,
+    pub data: Vec<u8>,
+    /// 捕获时间（Unix 时间，纳秒）
+    pub timestamp_ns: u64,
+    /// 网卡名
+    pub iface: Arc<str>,
+}
+
+impl CapturedEvent {
+    /// 解析一条变长记录；记录比头部短时返回 None
+    pub fn from_record(record: &[u8], clock: &KernelClock, ifaces: &Interfaces) -> Option<Self> {
+        let header_len = core::mem::size_of::<NetworkEvent>();
+        if record.len() < header_len {
+            return None;
+        }
+
+        let header = unsafe { (record.as_ptr() as *const NetworkEvent).read_unaligned() };
+
+        // perf 记录可能带有对齐填充，以 cap_len 为准
+        let data_end = core::cmp::min(header_len + header.cap_len as usize, record.len());
+        let data = record[header_len..data_end].to_vec();
+
+        Some(CapturedEvent {
+            header,
+            data,
+            timestamp_ns: clock.to_unix_ns(header.ktime_ns),
+            iface: ifaces.name(header.ifindex),
+        })
+    }
+
+    /// 捕获到的原始帧（以太网头开始）
+    pub fn captured(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// 传输层 payload（捕获范围内的部分）
+    pub fn payload(&self) -> &[u8] {
+        let offset = core::cmp::min(self.header.payload_offset as usize, self.data.len());
+        &self.data[offset..]
+    }
+}
+
+impl Deref for CapturedEvent {
+    type Target = NetworkEvent;
+
+    fn deref(&self) -> &NetworkEvent {
+        &self.header
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{decode::decode_frame, pcap::PcapPacket};
+    use aya_network_monitor_common::DIRECTION_INGRESS;
+
+    /// 以太网 + IPv4 + UDP 帧，端口 1234 -> 53，带 4 字节 payload
+    fn udp_frame() -> Vec<u8> {
+        let mut frame = vec![0u8; 12];
+        frame.extend_from_slice(&[0x08, 0x00]);
+        frame.extend_from_slice(&[0x45, 0, 0, 0, 0, 0, 0, 0, 64, 17, 0, 0, 10, 0, 0, 1, 10, 0, 0, 2]);
+        frame.extend_from_slice(&[0x04, 0xD2, 0x00, 0x35, 0, 0, 0, 0]);
+        frame.extend_from_slice(b"abcd");
+        frame
+    }
+
+    /// 用户空间解析出的头部，原始长度 100
+    fn header(frame: &[u8]) -> NetworkEvent {
+        let packet = PcapPacket {
+            timestamp_ns: 0,
+            data: frame.to_vec(),
+            orig_len: 100,
+            direction: DIRECTION_INGRESS,
+            iface: None,
+        };
+        decode_frame(&packet).unwrap().header
+    }
+
+    /// 按 eBPF 的布局拼出一条记录：头部 + 原始帧 + 对齐填充
+    fn record(header: &NetworkEvent, frame: &[u8], padding: usize) -> Vec<u8> {
+        let header_len = core::mem::size_of::<NetworkEvent>();
+        let header_bytes =
+            unsafe { core::slice::from_raw_parts(header as *const NetworkEvent as *const u8, header_len) };
+        let mut record = header_bytes.to_vec();
+        record.extend_from_slice(frame);
+        record.resize(record.len() + padding, 0xEE);
+        record
+    }
+
+    #[test]
+    fn kernel_clock_adds_offset() {
+        let clock = KernelClock { offset_ns: 1_700_000_000_000_000_000 };
+        assert_eq!(clock.to_unix_ns(123), 1_700_000_000_000_000_123);
+
+        // 偏移为负且结果小于 0 时截断为 0
+        let clock = KernelClock { offset_ns: -1_000 };
+        assert_eq!(clock.to_unix_ns(10), 0);
+    }
+
+    #[test]
+    fn from_record_uses_cap_len_and_converts_time() {
+        let frame = udp_frame();
+        let mut header = header(&frame);
+        header.ifindex = 3;
+        header.ktime_ns = 5_000;
+        let clock = KernelClock { offset_ns: 1_000_000 };
+        let ifaces = Interfaces::from_list(&[(2, "eth0"), (3, "eth1")]);
+
+        let event = CapturedEvent::from_record(&record(&header, &frame, 3), &clock, &ifaces).unwrap();
+        assert_eq!(event.captured(), &frame[..]);
+        assert_eq!(event.payload(), b"abcd");
+        assert_eq!(event.packet_size, 100);
+        assert_eq!(event.timestamp_ns, 1_005_000);
+        assert_eq!(&*event.iface, "eth1");
+    }
+
+    #[test]
+    fn from_record_rejects_short_records() {
+        let frame = udp_frame();
+        let header = header(&frame);
+        let bytes = record(&header, &frame, 0);
+        let clock = KernelClock { offset_ns: 0 };
+        let ifaces = Interfaces::from_list(&[]);
+
+        assert!(CapturedEvent::from_record(&bytes[..core::mem::size_of::<NetworkEvent>() - 1], &clock, &ifaces).is_none());
+
+        // cap_len 超出记录长度时只取记录中实际有的字节
+        let event = CapturedEvent::from_record(&bytes[..bytes.len() - 4], &clock, &ifaces).unwrap();
+        assert_eq!(event.captured(), &frame[..frame.len() - 4]);
+        assert!(event.payload().is_empty());
+    }
+}
@@ -0,0 +1,584 @@
+//! NetFlow v5 / v9 与 IPFIX 导出
+//!
+//! `--export ipfix://collector:4739`（或 `netflow5://`、`netflow9://`）把流记录通过 UDP 发送到采集器。
+//! v9 和 IPFIX 使用同一组字段，IPv4 和 IPv6 各一个模板，模板随第一个报文发送并定期重发；
+//! v5 的报文格式固定，只能携带 IPv4 流。
+
+use std::{
+    fmt,
+    io,
+    net::{SocketAddr, ToSocketAddrs, UdpSocket},
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use crate::flow::{now_ns, FlowRecord};
+
+/// 模板重发间隔：采集器重启后最多这么久就能重新解码
+const TEMPLATE_REFRESH: Duration = Duration::from_secs(60);
+
+/// 每个报文最多携带的流记录数，使报文不超过常见的 1500 字节 MTU
+const V5_MAX_RECORDS: usize = 30;
+const MAX_RECORDS: usize = 15;
+
+const TEMPLATE_ID_V4: u16 = 256;
+const TEMPLATE_ID_V6: u16 = 257;
+
+/// 导出协议
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    NetflowV5,
+    NetflowV9,
+    Ipfix,
+}
+
+impl ExportFormat {
+    fn default_port(self) -> u16 {
+        match self {
+            ExportFormat::Ipfix => 4739,
+            ExportFormat::NetflowV5 | ExportFormat::NetflowV9 => 2055,
+        }
+    }
+}
+
+impl fmt::Display for ExportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ExportFormat::NetflowV5 => "netflow5",
+            ExportFormat::NetflowV9 => "netflow9",
+            ExportFormat::Ipfix => "ipfix",
+        })
+    }
+}
+
+/// `--export` 的参数：协议 + 采集器地址，如 ipfix://collector:4739，省略端口时使用协议的默认端口
+#[derive(Debug, Clone)]
+pub struct ExportTarget {
+    pub format: ExportFormat,
+    pub addr: SocketAddr,
+}
+
+impl FromStr for ExportTarget {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (scheme, host) = s
+            .split_once("://")
+            .ok_or_else(|| format!("无效的导出地址: {}（格式为 ipfix://host:port）", s))?;
+
+        let format = match scheme.to_lowercase().as_str() {
+            "netflow5" => ExportFormat::NetflowV5,
+            "netflow9" => ExportFormat::NetflowV9,
+            "ipfix" => ExportFormat::Ipfix,
+            other => return Err(format!("未知的导出协议: {}（可选 netflow5, netflow9, ipfix）", other)),
+        };
+
+        let host = host.trim_end_matches('/');
+        let resolved = match host.to_socket_addrs() {
+            Ok(addrs) => addrs.collect::<Vec<_>>(),
+            // 没有端口：使用默认端口，IPv6 地址去掉方括号
+            Err(_) => (host.trim_start_matches('[').trim_end_matches(']'), format.default_port())
+                .to_socket_addrs()
+                .map_err(|e| format!("无法解析采集器地址 {}: {}", host, e))?
+                .collect(),
+        };
+
+        let addr = resolved.into_iter().next().ok_or_else(|| format!("无法解析采集器地址: {}", host))?;
+        Ok(ExportTarget { format, addr })
+    }
+}
+
+impl fmt::Display for ExportTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}://{}", self.format, self.addr)
+    }
+}
+
+/// v9 / IPFIX 模板中的字段
+#[derive(Debug, Clone, Copy)]
+enum Field {
+    SrcAddr4,
+    DstAddr4,
+    SrcAddr6,
+    DstAddr6,
+    SrcPort,
+    DstPort,
+    Protocol,
+    TcpFlags,
+    InputIfindex,
+    Packets,
+    Bytes,
+    Start,
+    End,
+}
+
+const FIELDS_V4: [Field; 11] = [
+    Field::SrcAddr4,
+    Field::DstAddr4,
+    Field::SrcPort,
+    Field::DstPort,
+    Field::Protocol,
+    Field::TcpFlags,
+    Field::InputIfindex,
+    Field::Packets,
+    Field::Bytes,
+    Field::Start,
+    Field::End,
+];
+
+const FIELDS_V6: [Field; 11] = [
+    Field::SrcAddr6,
+    Field::DstAddr6,
+    Field::SrcPort,
+    Field::DstPort,
+    Field::Protocol,
+    Field::TcpFlags,
+    Field::InputIfindex,
+    Field::Packets,
+    Field::Bytes,
+    Field::Start,
+    Field::End,
+];
+
+impl Field {
+    /// (字段类型 / 信息元素 ID, 长度)；除时间外 v9 与 IPFIX 的编号相同
+    fn spec(self, format: ExportFormat) -> (u16, u16) {
+        match self {
+            Field::Bytes => (1, 8),
+            Field::Packets => (2, 8),
+            Field::Protocol => (4, 1),
+            Field::TcpFlags => (6, 1),
+            Field::SrcPort => (7, 2),
+            Field::SrcAddr4 => (8, 4),
+            Field::InputIfindex => (10, 4),
+            Field::DstPort => (11, 2),
+            Field::DstAddr4 => (12, 4),
+            Field::SrcAddr6 => (27, 16),
+            Field::DstAddr6 => (28, 16),
+            // v9：LAST_SWITCHED / FIRST_SWITCHED（sysUptime 毫秒）；IPFIX：flowStart/EndMilliseconds（Unix 毫秒）
+            Field::Start if format == ExportFormat::Ipfix => (152, 8),
+            Field::End if format == ExportFormat::Ipfix => (153, 8),
+            Field::Start => (22, 4),
+            Field::End => (21, 4),
+        }
+    }
+}
+
+/// 流导出器
+pub struct FlowExporter {
+    target: ExportTarget,
+    socket: UdpSocket,
+    /// sysUptime 的起点（Unix 纳秒）
+    boot_ns: u64,
+    /// 离线模式下最近一个数据包的时间，代替墙上时钟
+    packet_clock: Option<u64>,
+    /// v5：已导出的流数；v9：已发送的报文数；IPFIX：已导出的数据记录数
+    sequence: u32,
+    last_template: Option<Instant>,
+}
+
+impl FlowExporter {
+    pub fn new(target: ExportTarget) -> io::Result<Self> {
+        let bind: SocketAddr = if target.addr.is_ipv4() {
+            "0.0.0.0:0".parse().unwrap()
+        } else {
+            "[::]:0".parse().unwrap()
+        };
+        let socket = UdpSocket::bind(bind)?;
+        socket.connect(target.addr)?;
+
+        Ok(FlowExporter {
+            target,
+            socket,
+            boot_ns: now_ns(),
+            packet_clock: None,
+            sequence: 0,
+            last_template: None,
+        })
+    }
+
+    pub fn target(&self) -> &ExportTarget {
+        &self.target
+    }
+
+    /// 离线模式（`--read`）：按数据包时间推进时钟，第一次调用的时间作为 sysUptime 的起点
+    pub fn set_packet_clock(&mut self, timestamp_ns: u64) {
+        if self.packet_clock.is_none() {
+            self.boot_ns = timestamp_ns;
+        }
+        self.packet_clock = Some(self.packet_clock.map_or(timestamp_ns, |clock| clock.max(timestamp_ns)));
+    }
+
+    /// 报文头中的当前时间：实时捕获时为墙上时钟，离线时为最近一个数据包的时间
+    fn now_ns(&self) -> u64 {
+        self.packet_clock.unwrap_or_else(now_ns)
+    }
+
+    /// 导出一批流记录，按报文大小拆分发送
+    pub fn export(&mut self, records: &[FlowRecord]) -> io::Result<()> {
+        match self.target.format {
+            ExportFormat::NetflowV5 => {
+                // v5 只能携带 IPv4 流
+                let v4: Vec<&FlowRecord> = records.iter().filter(|r| r.key.ip_version == 4).collect();
+                for chunk in v4.chunks(V5_MAX_RECORDS) {
+                    let packet = self.encode_v5(chunk);
+                    self.socket.send(&packet)?;
+                }
+            }
+            ExportFormat::NetflowV9 | ExportFormat::Ipfix => {
+                for chunk in records.chunks(MAX_RECORDS) {
+                    let packet = self.encode_templated(chunk);
+                    self.socket.send(&packet)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// 相对导出器启动时间的毫秒数（NetFlow 的 sysUptime）
+    fn uptime_ms(&self, unix_ns: u64) -> u32 {
+        (unix_ns.saturating_sub(self.boot_ns) / 1_000_000) as u32
+    }
+
+    fn encode_v5(&mut self, records: &[&FlowRecord]) -> Vec<u8> {
+        let now = self.now_ns();
+        let mut buf = Vec::with_capacity(24 + records.len() * 48);
+
+        buf.extend_from_slice(&5u16.to_be_bytes());
+        buf.extend_from_slice(&(records.len() as u16).to_be_bytes());
+        buf.extend_from_slice(&self.uptime_ms(now).to_be_bytes());
+        buf.extend_from_slice(&((now / 1_000_000_000) as u32).to_be_bytes());
+        buf.extend_from_slice(&((now % 1_000_000_000) as u32).to_be_bytes());
+        buf.extend_from_slice(&self.sequence.to_be_bytes());
+        buf.extend_from_slice(&[0, 0]); // engine_type, engine_id
+        buf.extend_from_slice(&0u16.to_be_bytes()); // 不采样
+
+        for record in records {
+            let (key, stats) = (&record.key, &record.stats);
+            buf.extend_from_slice(&key.src_ip[..4]);
+            buf.extend_from_slice(&key.dst_ip[..4]);
+            buf.extend_from_slice(&[0; 4]); // nexthop
+            buf.extend_from_slice(&(stats.ifindex as u16).to_be_bytes());
+            buf.extend_from_slice(&0u16.to_be_bytes()); // output
+            buf.extend_from_slice(&(stats.packets.min(u32::MAX as u64) as u32).to_be_bytes());
+            buf.extend_from_slice(&(stats.bytes.min(u32::MAX as u64) as u32).to_be_bytes());
+            buf.extend_from_slice(&self.uptime_ms(stats.first_seen_ns).to_be_bytes());
+            buf.extend_from_slice(&self.uptime_ms(stats.last_seen_ns).to_be_bytes());
+            // 端口在 FlowKey 中已经是网络字节序
+            buf.extend_from_slice(&key.src_port.to_ne_bytes());
+            buf.extend_from_slice(&key.dst_port.to_ne_bytes());
+            buf.push(0); // pad1
+            buf.push(stats.tcp_flags);
+            buf.push(key.protocol);
+            buf.push(0); // tos
+            buf.extend_from_slice(&[0; 4]); // src_as, dst_as
+            buf.extend_from_slice(&[0, 0]); // src_mask, dst_mask
+            buf.extend_from_slice(&[0; 2]); // pad2
+        }
+
+        self.sequence = self.sequence.wrapping_add(records.len() as u32);
+        buf
+    }
+
+    /// v9 / IPFIX 报文：头部 + （需要时）模板集合 + IPv4/IPv6 数据集合
+    fn encode_templated(&mut self, records: &[FlowRecord]) -> Vec<u8> {
+        let format = self.target.format;
+        let now = self.now_ns();
+
+        let send_templates = self.last_template.is_none_or(|at| at.elapsed() >= TEMPLATE_REFRESH);
+        if send_templates {
+            self.last_template = Some(Instant::now());
+        }
+
+        let mut body = Vec::new();
+        let mut record_count = 0u16;
+
+        if send_templates {
+            let set_start = begin_set(&mut body, if format == ExportFormat::Ipfix { 2 } else { 0 });
+            for (template_id, fields) in [(TEMPLATE_ID_V4, &FIELDS_V4), (TEMPLATE_ID_V6, &FIELDS_V6)] {
+                body.extend_from_slice(&template_id.to_be_bytes());
+                body.extend_from_slice(&(fields.len() as u16).to_be_bytes());
+                for field in fields.iter() {
+                    let (id, len) = field.spec(format);
+                    body.extend_from_slice(&id.to_be_bytes());
+                    body.extend_from_slice(&len.to_be_bytes());
+                }
+                record_count += 1;
+            }
+            end_set(&mut body, set_start);
+        }
+
+        let mut data_records = 0u32;
+        for (template_id, fields, ip_version) in [(TEMPLATE_ID_V4, &FIELDS_V4, 4), (TEMPLATE_ID_V6, &FIELDS_V6, 6)] {
+            let matching: Vec<&FlowRecord> = records.iter().filter(|r| r.key.ip_version == ip_version).collect();
+            if matching.is_empty() {
+                continue;
+            }
+
+            let set_start = begin_set(&mut body, template_id);
+            for record in matching {
+                for field in fields.iter() {
+                    self.encode_field(&mut body, *field, record);
+                }
+                record_count += 1;
+                data_records += 1;
+            }
+            end_set(&mut body, set_start);
+        }
+
+        let mut buf = Vec::with_capacity(20 + body.len());
+        if format == ExportFormat::Ipfix {
+            // version, length, export time, sequence（此前导出的数据记录数）, observation domain
+            buf.extend_from_slice(&10u16.to_be_bytes());
+            buf.extend_from_slice(&((16 + body.len()) as u16).to_be_bytes());
+            buf.extend_from_slice(&((now / 1_000_000_000) as u32).to_be_bytes());
+            buf.extend_from_slice(&self.sequence.to_be_bytes());
+            buf.extend_from_slice(&0u32.to_be_bytes());
+            self.sequence = self.sequence.wrapping_add(data_records);
+        } else {
+            // version, count（模板 + 数据记录）, sysUptime, unix secs, sequence（报文序号）, source id
+            buf.extend_from_slice(&9u16.to_be_bytes());
+            buf.extend_from_slice(&record_count.to_be_bytes());
+            buf.extend_from_slice(&self.uptime_ms(now).to_be_bytes());
+            buf.extend_from_slice(&((now / 1_000_000_000) as u32).to_be_bytes());
+            buf.extend_from_slice(&self.sequence.to_be_bytes());
+            buf.extend_from_slice(&0u32.to_be_bytes());
+            self.sequence = self.sequence.wrapping_add(1);
+        }
+        buf.extend_from_slice(&body);
+        buf
+    }
+
+    fn encode_field(&self, buf: &mut Vec<u8>, field: Field, record: &FlowRecord) {
+        let (key, stats) = (&record.key, &record.stats);
+        let ipfix = self.target.format == ExportFormat::Ipfix;
+
+        match field {
+            Field::SrcAddr4 => buf.extend_from_slice(&key.src_ip[..4]),
+            Field::DstAddr4 => buf.extend_from_slice(&key.dst_ip[..4]),
+            Field::SrcAddr6 => buf.extend_from_slice(&key.src_ip),
+            Field::DstAddr6 => buf.extend_from_slice(&key.dst_ip),
+            // 端口在 FlowKey 中已经是网络字节序
+            Field::SrcPort => buf.extend_from_slice(&key.src_port.to_ne_bytes()),
+            Field::DstPort => buf.extend_from_slice(&key.dst_port.to_ne_bytes()),
+            Field::Protocol => buf.push(key.protocol),
+            Field::TcpFlags => buf.push(stats.tcp_flags),
+            Field::InputIfindex => buf.extend_from_slice(&stats.ifindex.to_be_bytes()),
+            Field::Packets => buf.extend_from_slice(&stats.packets.to_be_bytes()),
+            Field::Bytes => buf.extend_from_slice(&stats.bytes.to_be_bytes()),
+            Field::Start if ipfix => buf.extend_from_slice(&(stats.first_seen_ns / 1_000_000).to_be_bytes()),
+            Field::End if ipfix => buf.extend_from_slice(&(stats.last_seen_ns / 1_000_000).to_be_bytes()),
+            Field::Start => buf.extend_from_slice(&self.uptime_ms(stats.first_seen_ns).to_be_bytes()),
+            Field::End => buf.extend_from_slice(&self.uptime_ms(stats.last_seen_ns).to_be_bytes()),
+        }
+    }
+}
+
+/// 写入集合头部（ID + 长度占位），返回集合起始位置
+fn begin_set(buf: &mut Vec<u8>, set_id: u16) -> usize {
+    let start = buf.len();
+    buf.extend_from_slice(&set_id.to_be_bytes());
+    buf.extend_from_slice(&[0, 0]);
+    start
+}
+
+/// 补齐到 4 字节并回填集合长度
+fn end_set(buf: &mut Vec<u8>, start: usize) {
+    while !(buf.len() - start).is_multiple_of(4) {
+        buf.push(0);
+    }
+    let len = (buf.len() - start) as u16;
+    buf[start + 2..start + 4].copy_from_slice(&len.to_be_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::flow::{FlowEnd, FlowKey, FlowStats};
+
+    /// 离线文件中第一个数据包的时间
+    const FIRST_PACKET_NS: u64 = 1_700_000_000_000_000_000;
+    const MS: u64 = 1_000_000;
+
+    /// 在 127.0.0.1 上模拟采集器，返回接收套接字和对应的导出器
+    fn collector(format: ExportFormat) -> (UdpSocket, FlowExporter) {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let exporter = FlowExporter::new(ExportTarget { format, addr: socket.local_addr().unwrap() }).unwrap();
+        (socket, exporter)
+    }
+
+    fn receive(socket: &UdpSocket) -> Vec<u8> {
+        let mut buf = [0u8; 2048];
+        let n = socket.recv(&mut buf).unwrap();
+        buf[..n].to_vec()
+    }
+
+    /// 10.0.0.1:40000 -> 10.0.0.2:443 的 TCP 流，时间相对第一个数据包
+    fn record(ip_version: u8, first_ms: u64, last_ms: u64) -> FlowRecord {
+        let mut key = FlowKey {
+            protocol: 6,
+            ip_version,
+            src_ip: [0; 16],
+            dst_ip: [0; 16],
+            src_port: 40000u16.to_be(),
+            dst_port: 443u16.to_be(),
+        };
+        if ip_version == 4 {
+            key.src_ip[..4].copy_from_slice(&[10, 0, 0, 1]);
+            key.dst_ip[..4].copy_from_slice(&[10, 0, 0, 2]);
+        } else {
+            key.src_ip = [0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1];
+            key.dst_ip = [0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2];
+        }
+        let stats = FlowStats {
+            iface: Arc::from("file"),
+            ifindex: 0,
+            packets: 10,
+            bytes: 4321,
+            first_seen_ns: FIRST_PACKET_NS + first_ms * MS,
+            last_seen_ns: FIRST_PACKET_NS + last_ms * MS,
+            tcp_flags: 0x1b,
+        };
+        FlowRecord { key, stats, end: FlowEnd::Idle }
+    }
+
+    fn u16_at(buf: &[u8], at: usize) -> u16 {
+        u16::from_be_bytes(buf[at..at + 2].try_into().unwrap())
+    }
+
+    fn u32_at(buf: &[u8], at: usize) -> u32 {
+        u32::from_be_bytes(buf[at..at + 4].try_into().unwrap())
+    }
+
+    fn u64_at(buf: &[u8], at: usize) -> u64 {
+        u64::from_be_bytes(buf[at..at + 8].try_into().unwrap())
+    }
+
+    /// 把报文体拆成 (集合 ID, 集合内容)
+    fn sets(mut body: &[u8]) -> Vec<(u16, &[u8])> {
+        let mut sets = Vec::new();
+        while !body.is_empty() {
+            let len = u16_at(body, 2) as usize;
+            assert!(len >= 4 && len <= body.len() && len.is_multiple_of(4), "集合长度 {} 无效", len);
+            sets.push((u16_at(body, 0), &body[4..len]));
+            body = &body[len..];
+        }
+        sets
+    }
+
+    fn set_ids(body: &[u8]) -> Vec<u16> {
+        sets(body).iter().map(|(id, _)| *id).collect()
+    }
+
+    /// 解码模板集合：[(模板 ID, [(字段 ID, 长度)])]
+    fn templates(mut set: &[u8]) -> Vec<(u16, Vec<(u16, u16)>)> {
+        let mut templates = Vec::new();
+        while set.len() >= 4 {
+            let (id, count) = (u16_at(set, 0), u16_at(set, 2) as usize);
+            let fields = (0..count).map(|i| (u16_at(set, 4 + i * 4), u16_at(set, 6 + i * 4))).collect();
+            templates.push((id, fields));
+            set = &set[4 + count * 4..];
+        }
+        templates
+    }
+
+    #[test]
+    fn ipfix_header_template_and_data_sets() {
+        let (socket, mut exporter) = collector(ExportFormat::Ipfix);
+        exporter.set_packet_clock(FIRST_PACKET_NS);
+        exporter.set_packet_clock(FIRST_PACKET_NS + 5000 * MS);
+        exporter.export(&[record(4, 1500, 4000), record(6, 0, 10)]).unwrap();
+        let packet = receive(&socket);
+
+        assert_eq!(u16_at(&packet, 0), 10);
+        assert_eq!(u16_at(&packet, 2) as usize, packet.len());
+        // 导出时间取最近一个数据包的时间，而不是读文件时的墙上时钟
+        assert_eq!(u32_at(&packet, 4) as u64, FIRST_PACKET_NS / 1_000_000_000 + 5);
+        assert_eq!(u32_at(&packet, 8), 0);
+
+        assert_eq!(set_ids(&packet[16..]), [2, TEMPLATE_ID_V4, TEMPLATE_ID_V6]);
+        let sets = sets(&packet[16..]);
+
+        let templates = templates(sets[0].1);
+        assert_eq!(templates.len(), 2);
+        assert_eq!(templates[0].0, TEMPLATE_ID_V4);
+        assert_eq!(
+            templates[0].1,
+            [(8, 4), (12, 4), (7, 2), (11, 2), (4, 1), (6, 1), (10, 4), (2, 8), (1, 8), (152, 8), (153, 8)]
+        );
+        assert_eq!(templates[1].0, TEMPLATE_ID_V6);
+        assert_eq!(&templates[1].1[..2], [(27, 16), (28, 16)]);
+
+        let data = sets[1].1;
+        assert_eq!(&data[0..8], [10, 0, 0, 1, 10, 0, 0, 2]);
+        assert_eq!((u16_at(data, 8), u16_at(data, 10)), (40000, 443));
+        assert_eq!((data[12], data[13]), (6, 0x1b));
+        assert_eq!(u32_at(data, 14), 0);
+        assert_eq!((u64_at(data, 18), u64_at(data, 26)), (10, 4321));
+        assert_eq!(u64_at(data, 34), FIRST_PACKET_NS / MS + 1500);
+        assert_eq!(u64_at(data, 42), FIRST_PACKET_NS / MS + 4000);
+
+        // 模板已经发送过：第二个报文只有数据集合，序号为此前导出的数据记录数
+        exporter.export(&[record(4, 6000, 7000)]).unwrap();
+        let packet = receive(&socket);
+        assert_eq!(u32_at(&packet, 8), 2);
+        assert_eq!(set_ids(&packet[16..]), [TEMPLATE_ID_V4]);
+    }
+
+    #[test]
+    fn netflow9_uptime_starts_at_first_packet() {
+        let (socket, mut exporter) = collector(ExportFormat::NetflowV9);
+        exporter.set_packet_clock(FIRST_PACKET_NS);
+        exporter.set_packet_clock(FIRST_PACKET_NS + 5000 * MS);
+        // 时钟不会倒退
+        exporter.set_packet_clock(FIRST_PACKET_NS + 4000 * MS);
+        exporter.export(&[record(4, 1500, 4000)]).unwrap();
+        let packet = receive(&socket);
+
+        assert_eq!(u16_at(&packet, 0), 9);
+        // 两个模板 + 一条数据记录
+        assert_eq!(u16_at(&packet, 2), 3);
+        assert_eq!(u32_at(&packet, 4), 5000);
+        assert_eq!(u32_at(&packet, 8) as u64, FIRST_PACKET_NS / 1_000_000_000 + 5);
+        assert_eq!(u32_at(&packet, 12), 0);
+
+        assert_eq!(set_ids(&packet[20..]), [0, TEMPLATE_ID_V4]);
+        let sets = sets(&packet[20..]);
+        let templates = templates(sets[0].1);
+        assert_eq!(&templates[0].1[9..], [(22, 4), (21, 4)]);
+
+        // v9 的首末包时间是 sysUptime 毫秒，离线时从第一个数据包算起
+        let data = sets[1].1;
+        assert_eq!((u32_at(data, 34), u32_at(data, 38)), (1500, 4000));
+
+        exporter.export(&[record(4, 6000, 7000)]).unwrap();
+        let packet = receive(&socket);
+        assert_eq!((u16_at(&packet, 2), u32_at(&packet, 12)), (1, 1));
+        assert_eq!(set_ids(&packet[20..]), [TEMPLATE_ID_V4]);
+    }
+
+    #[test]
+    fn netflow5_skips_ipv6_and_uses_packet_uptime() {
+        let (socket, mut exporter) = collector(ExportFormat::NetflowV5);
+        exporter.set_packet_clock(FIRST_PACKET_NS);
+        exporter.set_packet_clock(FIRST_PACKET_NS + 5000 * MS);
+        exporter.export(&[record(6, 0, 10), record(4, 1500, 4000)]).unwrap();
+        let packet = receive(&socket);
+
+        assert_eq!(packet.len(), 24 + 48);
+        assert_eq!((u16_at(&packet, 0), u16_at(&packet, 2)), (5, 1));
+        assert_eq!(u32_at(&packet, 4), 5000);
+        assert_eq!(u32_at(&packet, 8) as u64, FIRST_PACKET_NS / 1_000_000_000 + 5);
+        assert_eq!(u32_at(&packet, 16), 0);
+
+        let flow = &packet[24..];
+        assert_eq!(&flow[0..8], [10, 0, 0, 1, 10, 0, 0, 2]);
+        assert_eq!((u32_at(flow, 16), u32_at(flow, 20)), (10, 4321));
+        assert_eq!((u32_at(flow, 24), u32_at(flow, 28)), (1500, 4000));
+        assert_eq!((u16_at(flow, 32), u16_at(flow, 34)), (40000, 443));
+        assert_eq!((flow[37], flow[38]), (0x1b, 6));
+    }
+}
@@ -0,0 +1,1071 @@
+//! tcpdump 风格的过滤表达式
+//!
+//! `--filter "tcp and (port 80 or port 443) and not host 10.0.0.5"` 解析为 `Expr` 语法树，
+//! 命令行上的单项过滤参数（`--protocol`、`--src-ip` 等）也转换为同样的语法树，与表达式做 and。
+//! 实时捕获时，表达式通过 `compile` 编译为 `FilterOp` 逆波兰指令写入 eBPF 的 `FILTER_PROG`，
+//! 在 XDP 中先行过滤；用户空间仍对到达的事件完整求值一次。
+//!
+//! `FilterSpec` 是单项参数和表达式文本的集合，`Filter` 是编译好的过滤条件，
+//! 同时提供用户空间求值和写入 eBPF `FILTER_CONFIG` 的内核配置。
+//!
+//! 支持的语法：
+//!
+//! ```text
+//! expr      := and ( ("or" | "||") and )*
+//! and       := unary ( [ "and" | "&&" ] unary )*
+//! unary     := ("not" | "!") unary | "(" expr ")" | primitive
+//! primitive := [src|dst] host ADDR
+//!            | [src|dst] net CIDR
+//!            | [src|dst] port N
+//!            | [src|dst] portrange N-M
+//!            | tcp | udp | icmp | icmp6 | ip | ip6
+//!            | [ip|ip6] proto (NAME | N)
+//!            | vlan [ID]
+//!            | len (< | <= | > | >= | == | = | !=) N
+//!            | less N | greater N
+//!            | app NAME
+//! ```
+//!
+//! 与 tcpdump 相同，相邻的条件之间可以省略 and：`tcp port 443` 等价于 `tcp and port 443`。
+//!
+//! `app NAME` 按 payload 内容识别的应用层协议匹配（见 `detect`），只能在用户空间求值：
+//! 编译到 XDP 时按所在位置放宽为恒真或恒假，内核只做预筛，结果仍由用户空间决定。
+
+use std::{fmt, net::IpAddr};
+
+use aya_network_monitor_common::{
+    ip_bytes, FilterConfig, FilterOp, FILTER_CMP_EQ, FILTER_CMP_GE, FILTER_CMP_GT, FILTER_CMP_LE,
+    FILTER_CMP_LT, FILTER_CMP_NE, FILTER_DIR_ANY, FILTER_DIR_DST, FILTER_DIR_SRC, FILTER_OP_AND,
+    FILTER_OP_IP_VERSION, FILTER_OP_LEN, FILTER_OP_NET, FILTER_OP_NOT, FILTER_OP_OR, FILTER_OP_PORT,
+    FILTER_OP_PROTO, FILTER_OP_TRUE, FILTER_OP_VLAN, IPPROTO_ICMP, IPPROTO_ICMPV6, IPPROTO_TCP,
+    IPPROTO_UDP, MAX_FILTER_OPS,
+};
+
+use crate::{
+    detect,
+    dissect::Packet,
+    event::CapturedEvent,
+    net::IpNet,
+    ports::PortRange,
+};
+
+/// 地址/端口条件作用的方向
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dir {
+    Src,
+    Dst,
+    /// 源或目标任意一个匹配（tcpdump 的默认行为）
+    Any,
+}
+
+/// 包长比较运算符
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CmpOp {
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+}
+
+impl CmpOp {
+    fn eval(self, lhs: u32, rhs: u32) -> bool {
+        match self {
+            CmpOp::Lt => lhs < rhs,
+            CmpOp::Le => lhs <= rhs,
+            CmpOp::Gt => lhs > rhs,
+            CmpOp::Ge => lhs >= rhs,
+            CmpOp::Eq => lhs == rhs,
+            CmpOp::Ne => lhs != rhs,
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            CmpOp::Lt => "<",
+            CmpOp::Le => "<=",
+            CmpOp::Gt => ">",
+            CmpOp::Ge => ">=",
+            CmpOp::Eq => "==",
+            CmpOp::Ne => "!=",
+        }
+    }
+}
+
+/// 过滤表达式语法树
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    /// 匹配所有包（空过滤条件）
+    True,
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    /// IP 协议号（IPPROTO_*）
+    Proto(u8),
+    /// IP 版本（4 或 6）
+    IpVersion(u8),
+    Host(Dir, IpAddr),
+    Net(Dir, IpNet),
+    /// 端口范围（主机字节序）
+    Port(Dir, PortRange),
+    /// 任意一层 VLAN 标签等于该 ID；None 表示带有 VLAN 标签
+    Vlan(Option<u16>),
+    /// 原始包长比较
+    Len(CmpOp, u32),
+    /// 按 payload 识别的应用层协议（只在用户空间求值）
+    App(&'static str),
+}
+
+impl Expr {
+    pub fn and(self, other: Expr) -> Expr {
+        match (self, other) {
+            (Expr::True, e) | (e, Expr::True) => e,
+            (a, b) => Expr::And(Box::new(a), Box::new(b)),
+        }
+    }
+
+    pub fn or(self, other: Expr) -> Expr {
+        Expr::Or(Box::new(self), Box::new(other))
+    }
+
+    /// 多个条件任意一个匹配；列表为空时返回 True
+    pub fn any_of(exprs: impl IntoIterator<Item = Expr>) -> Expr {
+        exprs.into_iter().reduce(Expr::or).unwrap_or(Expr::True)
+    }
+
+    /// 是否包含只能在用户空间求值的条件（app）
+    pub fn uses_payload(&self) -> bool {
+        match self {
+            Expr::And(a, b) | Expr::Or(a, b) => a.uses_payload() || b.uses_payload(),
+            Expr::Not(e) => e.uses_payload(),
+            Expr::App(_) => true,
+            _ => false,
+        }
+    }
+
+    pub fn matches(&self, event: &CapturedEvent) -> bool {
+        match self {
+            Expr::True => true,
+            Expr::And(a, b) => a.matches(event) && b.matches(event),
+            Expr::Or(a, b) => a.matches(event) || b.matches(event),
+            Expr::Not(e) => !e.matches(event),
+            Expr::Proto(protocol) => event.protocol == *protocol,
+            Expr::IpVersion(version) => event.ip_version == *version,
+            Expr::Host(dir, addr) => dir.test(|src| {
+                if src { event.src_addr() == *addr } else { event.dst_addr() == *addr }
+            }),
+            Expr::Net(dir, net) => dir.test(|src| {
+                net.contains(if src { event.src_addr() } else { event.dst_addr() })
+            }),
+            Expr::Port(dir, range) => {
+                // ICMP 没有端口
+                if event.protocol != IPPROTO_TCP && event.protocol != IPPROTO_UDP {
+                    return false;
+                }
+                dir.test(|src| {
+                    // 事件中的端口为网络字节序
+                    range.contains(u16::from_be(if src { event.src_port } else { event.dst_port }))
+                })
+            }
+            Expr::Vlan(Some(id)) => event.vlans().contains(id),
+            Expr::Vlan(None) => event.vlan_count > 0,
+            Expr::Len(op, value) => op.eval(event.packet_size, *value),
+            Expr::App(name) => detect::detect(&Packet::from_event(event)) == Some(*name),
+        }
+    }
+}
+
+impl Dir {
+    /// test(true) 检查源，test(false) 检查目标
+    fn test(self, test: impl Fn(bool) -> bool) -> bool {
+        match self {
+            Dir::Src => test(true),
+            Dir::Dst => test(false),
+            Dir::Any => test(true) || test(false),
+        }
+    }
+
+    fn prefix(self) -> &'static str {
+        match self {
+            Dir::Src => "src ",
+            Dir::Dst => "dst ",
+            Dir::Any => "",
+        }
+    }
+}
+
+fn proto_name(protocol: u8) -> Option<&'static str> {
+    match protocol {
+        IPPROTO_TCP => Some("tcp"),
+        IPPROTO_UDP => Some("udp"),
+        IPPROTO_ICMP => Some("icmp"),
+        IPPROTO_ICMPV6 => Some("icmp6"),
+        _ => None,
+    }
+}
+
+fn proto_by_name(name: &str) -> Option<u8> {
+    match name {
+        "tcp" => Some(IPPROTO_TCP),
+        "udp" => Some(IPPROTO_UDP),
+        "icmp" => Some(IPPROTO_ICMP),
+        "icmp6" | "icmpv6" => Some(IPPROTO_ICMPV6),
+        _ => name.parse().ok(),
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::True => write!(f, "all"),
+            Expr::And(a, b) => write!(f, "({} and {})", a, b),
+            Expr::Or(a, b) => write!(f, "({} or {})", a, b),
+            Expr::Not(e) => write!(f, "not {}", e),
+            Expr::Proto(protocol) => match proto_name(*protocol) {
+                Some(name) => write!(f, "{}", name),
+                None => write!(f, "proto {}", protocol),
+            },
+            Expr::IpVersion(4) => write!(f, "ip"),
+            Expr::IpVersion(_) => write!(f, "ip6"),
+            Expr::Host(dir, addr) => write!(f, "{}host {}", dir.prefix(), addr),
+            Expr::Net(dir, net) => write!(f, "{}net {}", dir.prefix(), net),
+            Expr::Port(dir, range) if range.is_single() => write!(f, "{}port {}", dir.prefix(), range),
+            Expr::Port(dir, range) => write!(f, "{}portrange {}", dir.prefix(), range),
+            Expr::Vlan(Some(id)) => write!(f, "vlan {}", id),
+            Expr::Vlan(None) => write!(f, "vlan"),
+            Expr::Len(op, value) => write!(f, "len {} {}", op.as_str(), value),
+            Expr::App(name) => write!(f, "app {}", name),
+        }
+    }
+}
+
+/// 把表达式编译为 XDP 中求值的逆波兰指令；超过 MAX_FILTER_OPS 条时返回 None（只在用户空间求值）
+///
+/// 含 app 条件时编译结果是原表达式的放宽（匹配的包是原表达式的超集）
+pub fn compile(expr: &Expr) -> Option<Vec<FilterOp>> {
+    let mut ops = Vec::new();
+    compile_into(expr, false, &mut ops);
+    if ops.len() > MAX_FILTER_OPS as usize {
+        return None;
+    }
+    Some(ops)
+}
+
+/// negated 表示 expr 位于奇数个 not 之下
+fn compile_into(expr: &Expr, negated: bool, ops: &mut Vec<FilterOp>) {
+    let op = |kind: u8| FilterOp { kind, ..Default::default() };
+    let dir = |dir: Dir| match dir {
+        Dir::Src => FILTER_DIR_SRC,
+        Dir::Dst => FILTER_DIR_DST,
+        Dir::Any => FILTER_DIR_ANY,
+    };
+
+    match expr {
+        Expr::True => ops.push(op(FILTER_OP_TRUE)),
+        Expr::And(a, b) | Expr::Or(a, b) => {
+            compile_into(a, negated, ops);
+            compile_into(b, negated, ops);
+            let kind = if matches!(expr, Expr::And(..)) { FILTER_OP_AND } else { FILTER_OP_OR };
+            ops.push(op(kind));
+        }
+        Expr::Not(e) => {
+            compile_into(e, !negated, ops);
+            ops.push(op(FILTER_OP_NOT));
+        }
+        Expr::Proto(protocol) => ops.push(FilterOp { arg: *protocol, ..op(FILTER_OP_PROTO) }),
+        Expr::IpVersion(version) => ops.push(FilterOp { arg: *version, ..op(FILTER_OP_IP_VERSION) }),
+        Expr::Host(d, addr) => {
+            let (ip_version, addr) = ip_bytes(*addr);
+            let prefix_len = if ip_version == 4 { 32 } else { 128 };
+            ops.push(FilterOp { dir: dir(*d), arg: prefix_len, ip_version, addr, ..op(FILTER_OP_NET) });
+        }
+        Expr::Net(d, net) => {
+            let (ip_version, addr, prefix_len) = net.parts();
+            ops.push(FilterOp { dir: dir(*d), arg: prefix_len, ip_version, addr, ..op(FILTER_OP_NET) });
+        }
+        Expr::Port(d, range) => {
+            let (lo, hi) = range.bounds();
+            ops.push(FilterOp { dir: dir(*d), lo: lo as u32, hi: hi as u32, ..op(FILTER_OP_PORT) });
+        }
+        Expr::Vlan(id) => ops.push(FilterOp {
+            arg: id.is_some() as u8,
+            lo: id.unwrap_or(0) as u32,
+            ..op(FILTER_OP_VLAN)
+        }),
+        Expr::Len(cmp, value) => {
+            let arg = match cmp {
+                CmpOp::Lt => FILTER_CMP_LT,
+                CmpOp::Le => FILTER_CMP_LE,
+                CmpOp::Gt => FILTER_CMP_GT,
+                CmpOp::Ge => FILTER_CMP_GE,
+                CmpOp::Eq => FILTER_CMP_EQ,
+                CmpOp::Ne => FILTER_CMP_NE,
+            };
+            ops.push(FilterOp { arg, lo: *value, ..op(FILTER_OP_LEN) });
+        }
+        // 内核看不到完整的 payload：not 之外放宽为恒真，not 之内放宽为恒假
+        Expr::App(_) => {
+            ops.push(op(FILTER_OP_TRUE));
+            if negated {
+                ops.push(op(FILTER_OP_NOT));
+            }
+        }
+    }
+}
+
+/// 过滤参数或过滤表达式无效
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum FilterError {
+    #[error("无效的 IP 地址: {0}")]
+    InvalidAddress(String),
+    #[error("无效的前缀长度: {len}（0-{max}）")]
+    InvalidPrefixLen { len: String, max: u8 },
+    #[error("无效的端口: {0}")]
+    InvalidPort(String),
+    #[error("无效的端口范围: {0}（起始端口大于结束端口）")]
+    InvalidPortRange(String),
+    /// 表达式的语法错误
+    #[error("{0}")]
+    Syntax(String),
+}
+
+/// 解析过滤表达式；空字符串返回 True
+pub fn parse(input: &str) -> Result<Expr, FilterError> {
+    let tokens = tokenize(input)?;
+    if tokens.is_empty() {
+        return Ok(Expr::True);
+    }
+
+    let mut parser = Parser { tokens, pos: 0 };
+    let expr = parser.parse_or()?;
+    match parser.peek() {
+        None => Ok(expr),
+        Some(token) => Err(FilterError::Syntax(format!("多余的输入: {}", token))),
+    }
+}
+
+fn tokenize(input: &str) -> Result<Vec<String>, FilterError> {
+    let mut tokens = Vec::new();
+    let chars: Vec<char> = input.chars().collect();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == '(' || c == ')' {
+            tokens.push(c.to_string());
+            i += 1;
+        } else if matches!((c, next), ('&', Some('&')) | ('|', Some('|')) | ('<', Some('=')) | ('>', Some('=')) | ('=', Some('=')) | ('!', Some('='))) {
+            tokens.push(chars[i..i + 2].iter().collect());
+            i += 2;
+        } else if matches!(c, '!' | '<' | '>' | '=') {
+            tokens.push(c.to_string());
+            i += 1;
+        } else if c.is_ascii_alphanumeric() || matches!(c, '.' | ':' | '/' | '-' | '_') {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || matches!(chars[i], '.' | ':' | '/' | '-' | '_')) {
+                i += 1;
+            }
+            tokens.push(chars[start..i].iter().collect::<String>().to_lowercase());
+        } else {
+            return Err(FilterError::Syntax(format!("无法识别的字符: '{}'", c)));
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<String>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.pos).map(|s| s.as_str())
+    }
+
+    fn next(&mut self) -> Option<String> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn expect_value(&mut self, what: &str) -> Result<String, FilterError> {
+        self.next().ok_or_else(|| FilterError::Syntax(format!("{} 后缺少参数", what)))
+    }
+
+    fn parse_or(&mut self) -> Result<Expr, FilterError> {
+        let mut expr = self.parse_and()?;
+        while matches!(self.peek(), Some("or") | Some("||")) {
+            self.pos += 1;
+            expr = expr.or(self.parse_and()?);
+        }
+        Ok(expr)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr, FilterError> {
+        let mut expr = self.parse_unary()?;
+        loop {
+            match self.peek() {
+                Some("and") | Some("&&") => self.pos += 1,
+                // 相邻的条件之间省略了 and
+                Some(token) if !matches!(token, "or" | "||" | ")") => {}
+                _ => break,
+            }
+            expr = Expr::And(Box::new(expr), Box::new(self.parse_unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, FilterError> {
+        match self.peek() {
+            Some("not") | Some("!") => {
+                self.pos += 1;
+                Ok(Expr::Not(Box::new(self.parse_unary()?)))
+            }
+            Some("(") => {
+                self.pos += 1;
+                let expr = self.parse_or()?;
+                match self.next().as_deref() {
+                    Some(")") => Ok(expr),
+                    _ => Err(FilterError::Syntax("缺少右括号".to_string())),
+                }
+            }
+            Some(_) => self.parse_primitive(),
+            None => Err(FilterError::Syntax("表达式不完整".to_string())),
+        }
+    }
+
+    fn parse_primitive(&mut self) -> Result<Expr, FilterError> {
+        let token = self.next().unwrap_or_default();
+
+        let dir = match token.as_str() {
+            "src" => Some(Dir::Src),
+            "dst" => Some(Dir::Dst),
+            _ => None,
+        };
+        let keyword = match dir {
+            Some(_) => self.expect_value(&token)?,
+            None => token,
+        };
+
+        match keyword.as_str() {
+            "host" => {
+                let value = self.expect_value("host")?;
+                let addr = value.parse().map_err(|_| FilterError::InvalidAddress(value.clone()))?;
+                Ok(Expr::Host(dir.unwrap_or(Dir::Any), addr))
+            }
+            "net" => {
+                let value = self.expect_value("net")?;
+                Ok(Expr::Net(dir.unwrap_or(Dir::Any), value.parse()?))
+            }
+            "port" | "portrange" => {
+                let value = self.expect_value(&keyword)?;
+                if keyword == "port" && value.contains('-') {
+                    return Err(FilterError::Syntax(format!("端口范围请使用 portrange: {}", value)));
+                }
+                Ok(Expr::Port(dir.unwrap_or(Dir::Any), value.parse()?))
+            }
+            _ if dir.is_some() => Err(FilterError::Syntax(format!("src/dst 后只能跟 host、net、port 或 portrange，而不是 {}", keyword))),
+            "ip" | "ip6" => {
+                let version = if keyword == "ip" { 4 } else { 6 };
+                if self.peek() == Some("proto") {
+                    self.pos += 1;
+                    let proto = self.parse_proto()?;
+                    Ok(Expr::And(Box::new(Expr::IpVersion(version)), Box::new(proto)))
+                } else {
+                    Ok(Expr::IpVersion(version))
+                }
+            }
+            "proto" => self.parse_proto(),
+            "tcp" | "udp" | "icmp" | "icmp6" => Ok(Expr::Proto(proto_by_name(&keyword).unwrap())),
+            "vlan" => match self.peek().and_then(|token| token.parse::<u16>().ok()) {
+                Some(id) => {
+                    self.pos += 1;
+                    Ok(Expr::Vlan(Some(id)))
+                }
+                None => Ok(Expr::Vlan(None)),
+            },
+            "len" => {
+                let op = match self.expect_value("len")?.as_str() {
+                    "<" => CmpOp::Lt,
+                    "<=" => CmpOp::Le,
+                    ">" => CmpOp::Gt,
+                    ">=" => CmpOp::Ge,
+                    "=" | "==" => CmpOp::Eq,
+                    "!=" => CmpOp::Ne,
+                    other => return Err(FilterError::Syntax(format!("len 后需要比较运算符，而不是 {}", other))),
+                };
+                Ok(Expr::Len(op, self.parse_number("len")?))
+            }
+            // tcpdump: less N 等价于 len <= N，greater N 等价于 len >= N
+            "less" => Ok(Expr::Len(CmpOp::Le, self.parse_number("less")?)),
+            "greater" => Ok(Expr::Len(CmpOp::Ge, self.parse_number("greater")?)),
+            "app" => {
+                let value = self.expect_value("app")?;
+                detect::lookup(&value).map(Expr::App).ok_or_else(|| {
+                    FilterError::Syntax(format!("未知的应用协议: {}（可选 {}）", value, detect::names().collect::<Vec<_>>().join(", ")))
+                })
+            }
+            "" => Err(FilterError::Syntax("表达式不完整".to_string())),
+            other => Err(FilterError::Syntax(format!("未知的过滤条件: {}", other))),
+        }
+    }
+
+    fn parse_proto(&mut self) -> Result<Expr, FilterError> {
+        let value = self.expect_value("proto")?;
+        proto_by_name(&value)
+            .map(Expr::Proto)
+            .ok_or_else(|| FilterError::Syntax(format!("未知的协议: {}", value)))
+    }
+
+    fn parse_number(&mut self, what: &str) -> Result<u32, FilterError> {
+        let value = self.expect_value(what)?;
+        value.parse().map_err(|_| FilterError::Syntax(format!("无效的数字: {}", value)))
+    }
+}
+
+/// 过滤参数：与命令行的 --protocol、--src-ip 等单项参数和 --filter 表达式一一对应
+#[derive(Debug, Clone, Default)]
+pub struct FilterSpec {
+    /// IP 协议号，None 表示所有协议
+    pub protocol: Option<u8>,
+    pub src_ip: Option<IpAddr>,
+    pub dst_ip: Option<IpAddr>,
+    /// 源网段，命中任意一个即可
+    pub src_nets: Vec<IpNet>,
+    /// 目标网段，命中任意一个即可
+    pub dst_nets: Vec<IpNet>,
+    pub src_ports: Vec<PortRange>,
+    pub dst_ports: Vec<PortRange>,
+    /// 源端口或目标端口任意一个匹配即可
+    pub ports: Vec<PortRange>,
+    /// 外层或内层 VLAN 标签任意一个匹配即可
+    pub vlan: Option<u16>,
+    /// tcpdump 风格的过滤表达式
+    pub expression: Option<String>,
+}
+
+/// 编译好的过滤条件
+#[derive(Debug, Clone)]
+pub struct Filter {
+    spec: FilterSpec,
+    /// --filter 表达式，实时捕获时编译为 FILTER_PROG
+    filter_expr: Expr,
+    /// 用户空间完整的过滤条件：上面各项参数与 --filter 表达式的 and
+    expr: Expr,
+}
+
+impl Filter {
+    /// 解析表达式并与单项参数合并；表达式有语法错误时返回错误信息
+    pub fn new(spec: FilterSpec) -> Result<Self, FilterError> {
+        let filter_expr = match spec.expression {
+            Some(ref text) => parse(text)?,
+            None => Expr::True,
+        };
+
+        let mut filter = Filter { spec, filter_expr, expr: Expr::True };
+        filter.expr = filter.flags_expr().and(filter.filter_expr.clone());
+
+        Ok(filter)
+    }
+
+    pub fn spec(&self) -> &FilterSpec {
+        &self.spec
+    }
+
+    /// --filter 表达式部分
+    pub fn filter_expr(&self) -> &Expr {
+        &self.filter_expr
+    }
+
+    /// 完整的过滤条件
+    pub fn expr(&self) -> &Expr {
+        &self.expr
+    }
+
+    /// 转换为内核过滤配置，写入 eBPF 的 FILTER_CONFIG map
+    pub fn to_config(&self) -> FilterConfig {
+        let spec = &self.spec;
+        let enabled = spec.protocol.is_some()
+            || spec.src_ip.is_some()
+            || spec.dst_ip.is_some()
+            || !spec.src_nets.is_empty()
+            || !spec.dst_nets.is_empty()
+            || !spec.src_ports.is_empty()
+            || !spec.dst_ports.is_empty()
+            || !spec.ports.is_empty()
+            || spec.vlan.is_some();
+
+        let (src_ip_version, src_ip) = spec.src_ip.map(ip_bytes).unwrap_or_default();
+        let (dst_ip_version, dst_ip) = spec.dst_ip.map(ip_bytes).unwrap_or_default();
+
+        FilterConfig {
+            enabled: enabled as u8,
+            protocol: spec.protocol.unwrap_or(0),
+            src_ip_version,
+            dst_ip_version,
+            src_ip,
+            dst_ip,
+            match_src_port: !spec.src_ports.is_empty() as u8,
+            match_dst_port: !spec.dst_ports.is_empty() as u8,
+            match_any_port: !spec.ports.is_empty() as u8,
+            match_vlan: spec.vlan.is_some() as u8,
+            vlan_id: spec.vlan.unwrap_or(0),
+            match_src_net: !spec.src_nets.is_empty() as u8,
+            match_dst_net: !spec.dst_nets.is_empty() as u8,
+            prog_len: 0,        // 由 compile_filter 的结果填写
+        }
+    }
+
+    /// 单项参数对应的表达式（各项之间为 and）
+    fn flags_expr(&self) -> Expr {
+        let spec = &self.spec;
+        let mut expr = Expr::True;
+        if let Some(protocol) = spec.protocol {
+            expr = expr.and(Expr::Proto(protocol));
+        }
+        if let Some(ip) = spec.src_ip {
+            expr = expr.and(Expr::Host(Dir::Src, ip));
+        }
+        if let Some(ip) = spec.dst_ip {
+            expr = expr.and(Expr::Host(Dir::Dst, ip));
+        }
+        expr = expr
+            .and(Expr::any_of(spec.src_nets.iter().map(|net| Expr::Net(Dir::Src, *net))))
+            .and(Expr::any_of(spec.dst_nets.iter().map(|net| Expr::Net(Dir::Dst, *net))))
+            .and(Expr::any_of(spec.src_ports.iter().map(|range| Expr::Port(Dir::Src, *range))))
+            .and(Expr::any_of(spec.dst_ports.iter().map(|range| Expr::Port(Dir::Dst, *range))))
+            .and(Expr::any_of(spec.ports.iter().map(|range| Expr::Port(Dir::Any, *range))));
+        if let Some(vlan) = spec.vlan {
+            expr = expr.and(Expr::Vlan(Some(vlan)));
+        }
+        expr
+    }
+
+    pub fn matches(&self, event: &CapturedEvent) -> bool {
+        self.expr.matches(event)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use aya_network_monitor_common::NetworkEvent;
+
+    fn and(a: Expr, b: Expr) -> Expr {
+        Expr::And(Box::new(a), Box::new(b))
+    }
+
+    fn or(a: Expr, b: Expr) -> Expr {
+        Expr::Or(Box::new(a), Box::new(b))
+    }
+
+    fn not(e: Expr) -> Expr {
+        Expr::Not(Box::new(e))
+    }
+
+    fn tcp() -> Expr {
+        Expr::Proto(IPPROTO_TCP)
+    }
+
+    fn udp() -> Expr {
+        Expr::Proto(IPPROTO_UDP)
+    }
+
+    fn port(dir: Dir, port: u16) -> Expr {
+        Expr::Port(dir, port.to_string().parse().unwrap())
+    }
+
+    #[test]
+    fn empty_expression_matches_everything() {
+        assert_eq!(parse("").unwrap(), Expr::True);
+        assert_eq!(parse("   ").unwrap(), Expr::True);
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        let expected = or(tcp(), and(udp(), port(Dir::Any, 53)));
+        assert_eq!(parse("tcp or udp and port 53").unwrap(), expected);
+        assert_eq!(parse("tcp || udp && port 53").unwrap(), expected);
+
+        let expected = or(and(tcp(), port(Dir::Any, 80)), udp());
+        assert_eq!(parse("tcp and port 80 or udp").unwrap(), expected);
+    }
+
+    #[test]
+    fn not_applies_to_the_next_operand() {
+        assert_eq!(parse("not tcp and udp").unwrap(), and(not(tcp()), udp()));
+        assert_eq!(parse("! tcp or udp").unwrap(), or(not(tcp()), udp()));
+        assert_eq!(parse("not not tcp").unwrap(), not(not(tcp())));
+        assert_eq!(parse("not (tcp or udp)").unwrap(), not(or(tcp(), udp())));
+    }
+
+    #[test]
+    fn parentheses_override_precedence() {
+        assert_eq!(
+            parse("tcp and (port 80 or port 443)").unwrap(),
+            and(tcp(), or(port(Dir::Any, 80), port(Dir::Any, 443)))
+        );
+        assert_eq!(parse("((tcp))").unwrap(), tcp());
+    }
+
+    #[test]
+    fn juxtaposed_primitives_are_anded() {
+        assert_eq!(parse("tcp port 443").unwrap(), parse("tcp and port 443").unwrap());
+        assert_eq!(parse("udp dst port 53").unwrap(), and(udp(), port(Dir::Dst, 53)));
+        assert_eq!(parse("vlan 100 tcp").unwrap(), and(Expr::Vlan(Some(100)), tcp()));
+        assert_eq!(parse("vlan tcp").unwrap(), and(Expr::Vlan(None), tcp()));
+        assert_eq!(parse("tcp not port 22").unwrap(), and(tcp(), not(port(Dir::Any, 22))));
+        assert_eq!(
+            parse("tcp (port 80 or port 443)").unwrap(),
+            and(tcp(), or(port(Dir::Any, 80), port(Dir::Any, 443)))
+        );
+        // 省略 and 不改变与 or 的优先级
+        assert_eq!(parse("tcp port 80 or udp").unwrap(), or(and(tcp(), port(Dir::Any, 80)), udp()));
+    }
+
+    #[test]
+    fn primitives() {
+        let addr: IpAddr = "10.0.0.5".parse().unwrap();
+        assert_eq!(parse("src host 10.0.0.5").unwrap(), Expr::Host(Dir::Src, addr));
+        assert_eq!(parse("net 10.0.0.0/8").unwrap(), Expr::Net(Dir::Any, "10.0.0.0/8".parse().unwrap()));
+        assert_eq!(parse("portrange 8000-8100").unwrap(), Expr::Port(Dir::Any, "8000-8100".parse().unwrap()));
+        assert_eq!(parse("ip6 proto udp").unwrap(), and(Expr::IpVersion(6), udp()));
+        assert_eq!(parse("proto 47").unwrap(), Expr::Proto(47));
+        assert_eq!(parse("len >= 1000").unwrap(), Expr::Len(CmpOp::Ge, 1000));
+        assert_eq!(parse("less 64").unwrap(), Expr::Len(CmpOp::Le, 64));
+    }
+
+    #[test]
+    fn syntax_errors() {
+        assert_eq!(parse("host example"), Err(FilterError::InvalidAddress("example".to_string())));
+        assert_eq!(
+            parse("net 10.0.0.0/33"),
+            Err(FilterError::InvalidPrefixLen { len: "33".to_string(), max: 32 })
+        );
+        assert_eq!(parse("portrange 90-80"), Err(FilterError::InvalidPortRange("90-80".to_string())));
+
+        for input in [
+            "tcp and",
+            "tcp or",
+            "(tcp",
+            "tcp )",
+            "not",
+            "src tcp",
+            "host",
+            "host example",
+            "port 80-90",
+            "len 5",
+            "tcp $",
+            // 不是 libpcap 的 BPF 语法
+            "ether host 00:11:22:33:44:55",
+            "tcp[13] & 2 != 0",
+        ] {
+            assert!(parse(input).is_err(), "{:?} 应当解析失败", input);
+        }
+    }
+
+    /// 与 eBPF 中 run_filter_prog 相同的求值过程：u64 位栈，最低位为栈顶，端口为主机字节序
+    pub(crate) fn eval_ops(ops: &[FilterOp], event: &NetworkEvent) -> bool {
+        let prefix_eq = |addr: &[u8; 16], net: &[u8; 16], prefix_len: u8| {
+            (0..16).all(|i| {
+                let bits = (prefix_len as usize).saturating_sub(i * 8);
+                let mask = if bits >= 8 { 0xFF } else { !(0xFFu8 >> bits) };
+                (addr[i] ^ net[i]) & mask == 0
+            })
+        };
+        let src_port = u16::from_be(event.src_port) as u32;
+        let dst_port = u16::from_be(event.dst_port) as u32;
+        let vlans = event.vlans();
+
+        let mut stack = 0u64;
+        for op in ops {
+            let (src, dst) = match op.dir {
+                FILTER_DIR_SRC => (true, false),
+                FILTER_DIR_DST => (false, true),
+                _ => (true, true),
+            };
+            let result = match op.kind {
+                FILTER_OP_TRUE => true,
+                FILTER_OP_AND | FILTER_OP_OR => {
+                    let (a, b) = (stack & 1 != 0, (stack >> 1) & 1 != 0);
+                    stack >>= 2;
+                    if op.kind == FILTER_OP_AND { a && b } else { a || b }
+                }
+                FILTER_OP_NOT => {
+                    let a = stack & 1 != 0;
+                    stack >>= 1;
+                    !a
+                }
+                FILTER_OP_PROTO => event.protocol == op.arg,
+                FILTER_OP_IP_VERSION => event.ip_version == op.arg,
+                FILTER_OP_NET => {
+                    event.ip_version == op.ip_version
+                        && ((src && prefix_eq(&event.src_ip, &op.addr, op.arg))
+                            || (dst && prefix_eq(&event.dst_ip, &op.addr, op.arg)))
+                }
+                FILTER_OP_PORT => {
+                    let in_range = |port: u32| op.lo <= port && port <= op.hi;
+                    (event.protocol == IPPROTO_TCP || event.protocol == IPPROTO_UDP)
+                        && ((src && in_range(src_port)) || (dst && in_range(dst_port)))
+                }
+                FILTER_OP_VLAN => {
+                    if op.arg == 0 {
+                        !vlans.is_empty()
+                    } else {
+                        vlans.iter().any(|&id| id as u32 == op.lo)
+                    }
+                }
+                FILTER_OP_LEN => {
+                    let size = event.packet_size;
+                    match op.arg {
+                        FILTER_CMP_LT => size < op.lo,
+                        FILTER_CMP_LE => size <= op.lo,
+                        FILTER_CMP_GT => size > op.lo,
+                        FILTER_CMP_GE => size >= op.lo,
+                        FILTER_CMP_EQ => size == op.lo,
+                        _ => size != op.lo,
+                    }
+                }
+                kind => panic!("未知指令 {}", kind),
+            };
+            stack = (stack << 1) | result as u64;
+        }
+        stack & 1 != 0
+    }
+
+    pub(crate) fn event(
+        protocol: u8,
+        src: &str,
+        dst: &str,
+        ports: (u16, u16),
+        size: u32,
+        vlans: &[u16],
+    ) -> NetworkEvent {
+        // NetworkEvent 只包含整数和数组，全零是合法的初始值
+        let mut event: NetworkEvent = unsafe { core::mem::zeroed() };
+        let src: IpAddr = src.parse().unwrap();
+        (event.ip_version, event.src_ip) = ip_bytes(src);
+        (_, event.dst_ip) = ip_bytes(dst.parse().unwrap());
+        event.protocol = protocol;
+        event.src_port = ports.0.to_be();
+        event.dst_port = ports.1.to_be();
+        event.packet_size = size;
+        event.vlan_count = vlans.len() as u8;
+        event.vlan_ids[..vlans.len()].copy_from_slice(vlans);
+        event
+    }
+
+    /// 只带传输层 payload 的捕获事件（payload_offset 为 0）
+    pub(crate) fn captured(mut header: NetworkEvent, payload: &[u8]) -> CapturedEvent {
+        header.payload_offset = 0;
+        CapturedEvent { header, data: payload.to_vec(), timestamp_ns: 0, iface: "eth0".into() }
+    }
+
+    pub(crate) fn sample_events() -> Vec<CapturedEvent> {
+        [
+            event(IPPROTO_TCP, "10.0.0.5", "192.168.1.10", (40000, 443), 1500, &[]),
+            event(IPPROTO_TCP, "192.168.1.10", "10.0.0.5", (80, 51000), 60, &[100]),
+            event(IPPROTO_UDP, "10.1.2.3", "8.8.8.8", (5353, 53), 90, &[200, 300]),
+            event(IPPROTO_ICMP, "172.16.0.1", "10.0.0.5", (0, 0), 98, &[]),
+            event(IPPROTO_TCP, "2001:db8::1", "2001:db8:1::2", (22, 60000), 1000, &[]),
+            event(IPPROTO_UDP, "fe80::1", "ff02::fb", (5353, 5353), 200, &[100]),
+            event(IPPROTO_ICMPV6, "2001:db8::1", "2001:db8::2", (0, 0), 64, &[]),
+        ]
+        .into_iter()
+        .map(|header| captured(header, &[]))
+        .collect()
+    }
+
+    #[test]
+    fn compile_to_reverse_polish() {
+        let ops = compile(&parse("tcp and not port 22").unwrap()).unwrap();
+        let kinds: Vec<u8> = ops.iter().map(|op| op.kind).collect();
+        assert_eq!(kinds, [FILTER_OP_PROTO, FILTER_OP_PORT, FILTER_OP_NOT, FILTER_OP_AND]);
+        assert_eq!(ops[0].arg, IPPROTO_TCP);
+        assert_eq!((ops[1].dir, ops[1].lo, ops[1].hi), (FILTER_DIR_ANY, 22, 22));
+
+        let ops = compile(&parse("src net 10.0.0.0/8 or dst host 2001:db8::1").unwrap()).unwrap();
+        let net = |op: &FilterOp| (op.kind, op.dir, op.arg, op.ip_version);
+        assert_eq!(net(&ops[0]), (FILTER_OP_NET, FILTER_DIR_SRC, 8, 4));
+        assert_eq!(&ops[0].addr[..4], &[10, 0, 0, 0]);
+        assert_eq!(net(&ops[1]), (FILTER_OP_NET, FILTER_DIR_DST, 128, 6));
+        assert_eq!(ops[2].kind, FILTER_OP_OR);
+
+        let ops = compile(&parse("portrange 8000-8100 and len >= 1000 and vlan").unwrap()).unwrap();
+        assert_eq!((ops[0].lo, ops[0].hi), (8000, 8100));
+        assert_eq!((ops[1].kind, ops[1].arg, ops[1].lo), (FILTER_OP_LEN, FILTER_CMP_GE, 1000));
+        assert_eq!(ops[3].kind, FILTER_OP_VLAN);
+        assert_eq!(ops[3].arg, 0);
+    }
+
+    #[test]
+    fn compiled_program_agrees_with_ast() {
+        let expressions = [
+            "tcp",
+            "udp or icmp",
+            "icmp6",
+            "ip6",
+            "ip and not tcp",
+            "host 10.0.0.5",
+            "src host 10.0.0.5",
+            "dst host 2001:db8:1::2",
+            "net 10.0.0.0/8",
+            "src net 192.168.0.0/16 and dst port 51000",
+            "dst net 2001:db8::/32",
+            "net 0.0.0.0/0",
+            "net 10.0.0.0/15",
+            "port 53",
+            "src port 5353",
+            "dst portrange 50000-65535",
+            "portrange 1-1024 and not tcp",
+            "port 443 or port 22 or port 53",
+            "vlan",
+            "vlan 300",
+            "vlan 100 and udp",
+            "not vlan",
+            "len > 1000",
+            "len <= 90",
+            "len == 98",
+            "len != 60",
+            "less 64",
+            "greater 1000",
+            "proto 58",
+            "tcp and (port 80 or port 443) and not host 10.0.0.5",
+            "not (tcp or udp)",
+            "not not icmp",
+            "(src net 10.0.0.0/8 or src net 2001:db8::/32) and not (port 22 and len < 100)",
+        ];
+        let events = sample_events();
+
+        for text in expressions {
+            let expr = parse(text).unwrap();
+            let ops = compile(&expr).unwrap_or_else(|| panic!("{:?} 超过指令上限", text));
+            let expected: Vec<bool> = events.iter().map(|event| expr.matches(event)).collect();
+            let actual: Vec<bool> = events.iter().map(|event| eval_ops(&ops, event)).collect();
+            assert_eq!(actual, expected, "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn parses_app() {
+        assert_eq!(parse("app tls").unwrap(), Expr::App("tls"));
+        assert_eq!(parse("app HTTP").unwrap(), Expr::App("http"));
+        assert_eq!(parse("tcp and not app ssh").unwrap(), and(tcp(), not(Expr::App("ssh"))));
+        assert_eq!(parse("udp app dns").unwrap(), and(udp(), Expr::App("dns")));
+        assert_eq!(parse("app mqtt").unwrap().to_string(), "app mqtt");
+
+        let err = parse("app gopher").unwrap_err().to_string();
+        assert!(err.contains("gopher") && err.contains("http2"), "{}", err);
+        assert!(parse("app").is_err());
+    }
+
+    #[test]
+    fn app_matches_payload() {
+        let header = event(IPPROTO_TCP, "10.0.0.1", "10.0.0.2", (40000, 8080), 100, &[]);
+        let http = captured(header, b"GET / HTTP/1.1\r\nHost: a\r\n\r\n");
+        let ssh = captured(header, b"SSH-2.0-OpenSSH_9.6\r\n");
+        let empty = captured(header, b"");
+
+        let expr = parse("app http").unwrap();
+        assert!(expr.matches(&http));
+        assert!(!expr.matches(&ssh));
+        assert!(!expr.matches(&empty));
+
+        let expr = parse("tcp and not app http").unwrap();
+        assert!(!expr.matches(&http));
+        assert!(expr.matches(&ssh));
+        assert!(expr.matches(&empty));
+
+        // 与单项参数组合
+        let filter = Filter::new(FilterSpec {
+            dst_ports: vec!["8080".parse().unwrap()],
+            expression: Some("app ssh".to_string()),
+            ..Default::default()
+        })
+        .unwrap();
+        assert!(filter.filter_expr().uses_payload());
+        assert!(filter.matches(&ssh));
+        assert!(!filter.matches(&http));
+    }
+
+    #[test]
+    fn app_compiles_to_true() {
+        let kinds = |text: &str| -> Vec<u8> { compile(&parse(text).unwrap()).unwrap().iter().map(|op| op.kind).collect() };
+        assert_eq!(kinds("app dns"), [FILTER_OP_TRUE]);
+        assert_eq!(kinds("udp and app dns"), [FILTER_OP_PROTO, FILTER_OP_TRUE, FILTER_OP_AND]);
+        // 偶数个 not 之下仍是恒真
+        assert_eq!(kinds("not not app dns"), [FILTER_OP_TRUE, FILTER_OP_NOT, FILTER_OP_NOT]);
+
+        // 内核放过所有包，由用户空间决定
+        let ops = compile(&parse("app dns").unwrap()).unwrap();
+        assert!(sample_events().iter().all(|event| eval_ops(&ops, event)));
+    }
+
+    #[test]
+    fn app_under_not_compiles_to_false() {
+        let kinds = |text: &str| -> Vec<u8> { compile(&parse(text).unwrap()).unwrap().iter().map(|op| op.kind).collect() };
+        // not 之内放宽为恒假（TRUE + NOT），外层的 not 再取反后恒真
+        assert_eq!(kinds("not app dns"), [FILTER_OP_TRUE, FILTER_OP_NOT, FILTER_OP_NOT]);
+        assert_eq!(
+            kinds("not (udp and app dns)"),
+            [FILTER_OP_PROTO, FILTER_OP_TRUE, FILTER_OP_NOT, FILTER_OP_AND, FILTER_OP_NOT]
+        );
+
+        // tcp and not app http 在内核中放宽为 tcp，HTTP 包由用户空间排除
+        let ops = compile(&parse("tcp and not app http").unwrap()).unwrap();
+        for event in sample_events() {
+            assert_eq!(eval_ops(&ops, &event), event.protocol == IPPROTO_TCP);
+        }
+    }
+
+    #[test]
+    fn compiled_app_is_superset_of_ast() {
+        let payloads: [&[u8]; 4] = [
+            b"",
+            b"GET / HTTP/1.1\r\n\r\n",
+            b"SSH-2.0-OpenSSH_9.6\r\n",
+            &[0x23; 48], // NTP 客户端请求
+        ];
+        let events: Vec<CapturedEvent> = sample_events()
+            .iter()
+            .flat_map(|event| payloads.iter().map(|payload| captured(event.header, payload)))
+            .collect();
+
+        for text in [
+            "app http",
+            "not app http",
+            "tcp and not app ssh",
+            "app ntp or (tcp and app http)",
+            "not (udp and app ntp) and not (tcp and not app ssh)",
+            "not not app http",
+        ] {
+            let expr = parse(text).unwrap();
+            let ops = compile(&expr).unwrap();
+            let mut relaxed = 0;
+            for event in &events {
+                let (kernel, user) = (eval_ops(&ops, event), expr.matches(event));
+                assert!(kernel || !user, "{:?} 在内核中漏掉了 {:?}", text, event.header);
+                relaxed += (kernel && !user) as usize;
+            }
+            // 每个表达式都确实有被放宽的包，放宽不是恒等
+            assert!(relaxed > 0, "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn long_expression_is_not_compiled() {
+        // n 个 port 之间 n - 1 个 or，共 2n - 1 条指令
+        let ports = |n: u32| (1..=n).map(|port| format!("port {}", port)).collect::<Vec<_>>().join(" or ");
+
+        let ops = compile(&parse(&ports(MAX_FILTER_OPS / 2)).unwrap()).unwrap();
+        assert_eq!(ops.len(), MAX_FILTER_OPS as usize - 1);
+        assert!(compile(&parse(&ports(MAX_FILTER_OPS / 2 + 1)).unwrap()).is_none());
+    }
+}
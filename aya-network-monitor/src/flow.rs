@@ -0,0 +1,618 @@
+//! 流聚合
+//!
+//! `--mode flows` 不再逐包输出，而是按五元组（协议、源/目标地址、源/目标端口）把事件聚合到流表中，
+//! 记录包数、字节数、首末包时间和 TCP 标志的并集。流在空闲超时或活动超时后输出一条流记录并移出流表，
+//! 退出时剩余的流全部输出。所有读取任务共享一张流表。
+//!
+//! `--kernel-flows` 时聚合在 XDP/TC 程序中完成（`FLOW_TABLE`，LRU 每 CPU 哈希表），
+//! `KernelFlows` 按同样的超时规则遍历内核流表并移出到期的流，输出格式相同。
+//!
+//! 移出的流记录交给 `FlowSink`，发送到订阅通道（命令行 `--mode flows` 时写到终端）
+//! 或通过 `--export` 发送到 NetFlow/IPFIX 采集器。
+
+use std::{
+    collections::HashMap,
+    fmt,
+    net::IpAddr,
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Context as _;
+use aya::maps::{MapData, PerCpuHashMap};
+use aya_network_monitor_common::{ip_addr, FlowCounters, FlowTuple};
+use log::warn;
+use tokio::sync::mpsc;
+
+use crate::{
+    event::{CapturedEvent, KernelClock},
+    export::FlowExporter,
+    format::{format_endpoint, format_ip, format_protocol, format_timestamp},
+    iface::Interfaces,
+};
+
+/// 离线模式下按文件时间每隔多久检查一次超时
+const SWEEP_INTERVAL_NS: u64 = 1_000_000_000;
+
+/// 所有任务共享的流表
+pub type SharedFlowTable = Arc<Mutex<FlowTable>>;
+
+/// 流的键：单向五元组，端口为网络字节序（与 NetworkEvent 一致）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FlowKey {
+    pub protocol: u8,
+    pub ip_version: u8,
+    pub src_ip: [u8; 16],
+    pub dst_ip: [u8; 16],
+    pub src_port: u16,
+    pub dst_port: u16,
+}
+
+impl FlowKey {
+    pub fn from_event(event: &CapturedEvent) -> Self {
+        FlowKey {
+            protocol: event.protocol,
+            ip_version: event.ip_version,
+            src_ip: event.src_ip,
+            dst_ip: event.dst_ip,
+            src_port: event.src_port,
+            dst_port: event.dst_port,
+        }
+    }
+
+    pub fn from_tuple(tuple: &FlowTuple) -> Self {
+        FlowKey {
+            protocol: tuple.protocol,
+            ip_version: tuple.ip_version,
+            src_ip: tuple.src_ip,
+            dst_ip: tuple.dst_ip,
+            src_port: tuple.src_port,
+            dst_port: tuple.dst_port,
+        }
+    }
+
+    pub fn src_addr(&self) -> IpAddr {
+        ip_addr(self.ip_version, &self.src_ip)
+    }
+
+    pub fn dst_addr(&self) -> IpAddr {
+        ip_addr(self.ip_version, &self.dst_ip)
+    }
+}
+
+/// 流的计数
+#[derive(Debug, Clone)]
+pub struct FlowStats {
+    /// 第一个包所在的网卡（离线文件的 ifindex 为 0）
+    pub iface: Arc<str>,
+    pub ifindex: u32,
+    pub packets: u64,
+    pub bytes: u64,
+    /// 首包和末包时间（Unix 时间，纳秒）
+    pub first_seen_ns: u64,
+    pub last_seen_ns: u64,
+    /// 所有包 TCP 标志的并集
+    pub tcp_flags: u8,
+}
+
+/// 流结束的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlowEnd {
+    /// 超过空闲超时没有新的包
+    Idle,
+    /// 持续时间超过活动超时，长连接会分成多条记录
+    Active,
+    /// 程序退出或文件读完
+    Flush,
+}
+
+impl fmt::Display for FlowEnd {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            FlowEnd::Idle => "idle",
+            FlowEnd::Active => "active",
+            FlowEnd::Flush => "flush",
+        })
+    }
+}
+
+/// 一条流记录（流移出流表时输出）
+#[derive(Debug, Clone)]
+pub struct FlowRecord {
+    pub key: FlowKey,
+    pub stats: FlowStats,
+    pub end: FlowEnd,
+}
+
+impl fmt::Display for FlowRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (src, dst) = match self.key.protocol {
+            6 | 17 => (
+                format_endpoint(self.key.src_addr(), self.key.src_port),
+                format_endpoint(self.key.dst_addr(), self.key.dst_port),
+            ),
+            _ => (format_ip(self.key.src_addr()), format_ip(self.key.dst_addr())),
+        };
+        let duration_ns = self.stats.last_seen_ns - self.stats.first_seen_ns;
+
+        write!(
+            f,
+            "{} {} {} {} -> {} {} 包 {} 字节 {:.3}s",
+            format_timestamp(self.stats.first_seen_ns),
+            self.stats.iface,
+            format_protocol(self.key.protocol),
+            src,
+            dst,
+            self.stats.packets,
+            self.stats.bytes,
+            duration_ns as f64 / 1e9,
+        )?;
+        if self.key.protocol == 6 {
+            write!(f, " [{}]", format_tcp_flags(self.stats.tcp_flags))?;
+        }
+        write!(f, " ({})", self.end)
+    }
+}
+
+/// tcpdump 风格的 TCP 标志，如 S.、P.、F.
+fn format_tcp_flags(flags: u8) -> String {
+    const NAMES: [(u8, char); 7] = [
+        (0x02, 'S'),
+        (0x01, 'F'),
+        (0x04, 'R'),
+        (0x08, 'P'),
+        (0x20, 'U'),
+        (0x40, 'E'),
+        (0x80, 'W'),
+    ];
+
+    let mut out: String = NAMES.iter().filter(|(bit, _)| flags & bit != 0).map(|(_, name)| *name).collect();
+    if flags & 0x10 != 0 {
+        out.push('.');
+    }
+    if out.is_empty() {
+        out.push_str("none");
+    }
+    out
+}
+
+/// 流表
+#[derive(Debug)]
+pub struct FlowTable {
+    flows: HashMap<FlowKey, FlowStats>,
+    idle_timeout_ns: u64,
+    active_timeout_ns: u64,
+    last_sweep_ns: u64,
+}
+
+impl FlowTable {
+    pub fn new(idle_timeout_secs: u64, active_timeout_secs: u64) -> Self {
+        FlowTable {
+            flows: HashMap::new(),
+            idle_timeout_ns: idle_timeout_secs * 1_000_000_000,
+            active_timeout_ns: active_timeout_secs * 1_000_000_000,
+            last_sweep_ns: 0,
+        }
+    }
+
+    /// 把一个事件计入它所属的流
+    pub fn update(&mut self, event: &CapturedEvent) {
+        let stats = self.flows.entry(FlowKey::from_event(event)).or_insert_with(|| FlowStats {
+            iface: event.iface.clone(),
+            ifindex: event.ifindex,
+            packets: 0,
+            bytes: 0,
+            first_seen_ns: event.timestamp_ns,
+            last_seen_ns: event.timestamp_ns,
+            tcp_flags: 0,
+        });
+
+        stats.packets += 1;
+        stats.bytes += event.packet_size as u64;
+        // 不同 CPU 的事件可能乱序到达
+        stats.first_seen_ns = stats.first_seen_ns.min(event.timestamp_ns);
+        stats.last_seen_ns = stats.last_seen_ns.max(event.timestamp_ns);
+        stats.tcp_flags |= event.tcp_flags;
+    }
+
+    /// 移出在 now_ns 时已经超时的流
+    pub fn expire(&mut self, now_ns: u64) -> Vec<FlowRecord> {
+        self.last_sweep_ns = now_ns;
+
+        let (idle_timeout_ns, active_timeout_ns) = (self.idle_timeout_ns, self.active_timeout_ns);
+        let mut records = Vec::new();
+        self.flows.retain(|key, stats| {
+            let end = match expiry(stats, now_ns, idle_timeout_ns, active_timeout_ns) {
+                Some(end) => end,
+                None => return true,
+            };
+            records.push(FlowRecord { key: *key, stats: stats.clone(), end });
+            false
+        });
+
+        sort_records(&mut records);
+        records
+    }
+
+    /// 按数据包时间推进的超时检查（离线模式没有墙上时钟），每秒最多检查一次
+    pub fn tick(&mut self, now_ns: u64) -> Vec<FlowRecord> {
+        if now_ns < self.last_sweep_ns + SWEEP_INTERVAL_NS {
+            return Vec::new();
+        }
+        self.expire(now_ns)
+    }
+
+    /// 移出所有流
+    pub fn flush(&mut self) -> Vec<FlowRecord> {
+        let mut records: Vec<_> = self
+            .flows
+            .drain()
+            .map(|(key, stats)| FlowRecord { key, stats, end: FlowEnd::Flush })
+            .collect();
+
+        sort_records(&mut records);
+        records
+    }
+}
+
+/// 流在 now_ns 时是否超时：空闲超时优先于活动超时
+fn expiry(stats: &FlowStats, now_ns: u64, idle_timeout_ns: u64, active_timeout_ns: u64) -> Option<FlowEnd> {
+    if now_ns.saturating_sub(stats.last_seen_ns) >= idle_timeout_ns {
+        Some(FlowEnd::Idle)
+    } else if now_ns.saturating_sub(stats.first_seen_ns) >= active_timeout_ns {
+        Some(FlowEnd::Active)
+    } else {
+        None
+    }
+}
+
+/// 内核流表（FLOW_TABLE）
+///
+/// 每个 CPU 各有一份计数，读取时合并。移出流时先读后删，两次系统调用之间到达的包会丢失，
+/// 对已经空闲的流影响可以忽略；活动超时的长连接可能少计几个包。
+pub struct KernelFlows {
+    map: PerCpuHashMap<MapData, FlowTuple, FlowCounters>,
+    clock: KernelClock,
+    ifaces: Interfaces,
+    idle_timeout_ns: u64,
+    active_timeout_ns: u64,
+}
+
+impl KernelFlows {
+    pub fn new(
+        map: PerCpuHashMap<MapData, FlowTuple, FlowCounters>,
+        clock: KernelClock,
+        ifaces: Interfaces,
+        idle_timeout_secs: u64,
+        active_timeout_secs: u64,
+    ) -> Self {
+        KernelFlows {
+            map,
+            clock,
+            ifaces,
+            idle_timeout_ns: idle_timeout_secs * 1_000_000_000,
+            active_timeout_ns: active_timeout_secs * 1_000_000_000,
+        }
+    }
+
+    /// 合并后的内核计数换算为流统计：时间戳换算为 Unix 时间，ifindex 换算为网卡名
+    fn stats(&self, counters: &FlowCounters) -> FlowStats {
+        FlowStats {
+            iface: self.ifaces.name(counters.ifindex),
+            ifindex: counters.ifindex,
+            packets: counters.packets,
+            bytes: counters.bytes,
+            first_seen_ns: self.clock.to_unix_ns(counters.first_seen_ns),
+            last_seen_ns: self.clock.to_unix_ns(counters.last_seen_ns),
+            tcp_flags: counters.tcp_flags,
+        }
+    }
+
+    /// 遍历内核流表，移出在 now_ns 时已经超时的流；now_ns 为 None 时移出所有流
+    fn drain(&mut self, now_ns: Option<u64>) -> anyhow::Result<Vec<FlowRecord>> {
+        // 先收集键再逐个删除，避免边遍历边删除打乱遍历顺序
+        let tuples = self.map.keys().collect::<Result<Vec<_>, _>>().context("遍历 FLOW_TABLE 失败")?;
+
+        let mut records = Vec::new();
+        for tuple in tuples {
+            // 已被 LRU 淘汰的流直接跳过
+            let values = match self.map.get(&tuple, 0) {
+                Ok(values) => values,
+                Err(_) => continue,
+            };
+            let stats = match merge_counters(values.iter()) {
+                Some(counters) => self.stats(&counters),
+                None => continue,
+            };
+
+            let end = match now_ns {
+                Some(now_ns) => match expiry(&stats, now_ns, self.idle_timeout_ns, self.active_timeout_ns) {
+                    Some(end) => end,
+                    None => continue,
+                },
+                None => FlowEnd::Flush,
+            };
+
+            let _ = self.map.remove(&tuple);
+            records.push(FlowRecord { key: FlowKey::from_tuple(&tuple), stats, end });
+        }
+
+        sort_records(&mut records);
+        Ok(records)
+    }
+
+    pub fn expire(&mut self, now_ns: u64) -> anyhow::Result<Vec<FlowRecord>> {
+        self.drain(Some(now_ns))
+    }
+
+    pub fn flush(&mut self) -> anyhow::Result<Vec<FlowRecord>> {
+        self.drain(None)
+    }
+}
+
+/// 合并同一个流在各 CPU 上的计数
+///
+/// 包数和字节数求和，首包时间取最早、末包时间取最晚，TCP 标志取并集；
+/// 网卡取第一个有包的 CPU 上记录的网卡。所有 CPU 都没有包时返回 None。
+fn merge_counters<'a>(values: impl IntoIterator<Item = &'a FlowCounters>) -> Option<FlowCounters> {
+    let mut merged: Option<FlowCounters> = None;
+    for counters in values.into_iter().filter(|counters| counters.packets > 0) {
+        match merged {
+            Some(ref mut total) => {
+                total.packets += counters.packets;
+                total.bytes += counters.bytes;
+                total.first_seen_ns = total.first_seen_ns.min(counters.first_seen_ns);
+                total.last_seen_ns = total.last_seen_ns.max(counters.last_seen_ns);
+                total.tcp_flags |= counters.tcp_flags;
+            }
+            None => merged = Some(*counters),
+        }
+    }
+    merged
+}
+
+/// 按首包时间排序，使输出顺序与流的开始顺序一致
+fn sort_records(records: &mut [FlowRecord]) {
+    records.sort_by_key(|record| record.stats.first_seen_ns);
+}
+
+/// 当前 Unix 时间（纳秒）
+pub fn now_ns() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos() as u64).unwrap_or(0)
+}
+
+/// 流记录的去向：发送到订阅通道（`--mode flows` 时由命令行写到终端），`--export` 时发送到采集器，
+/// 两者可以同时启用
+pub struct FlowSink {
+    records: Option<mpsc::UnboundedSender<FlowRecord>>,
+    exporter: Option<FlowExporter>,
+}
+
+/// 所有任务共享的流记录去向
+pub type SharedFlowSink = Arc<Mutex<FlowSink>>;
+
+impl FlowSink {
+    pub fn new(records: Option<mpsc::UnboundedSender<FlowRecord>>, exporter: Option<FlowExporter>) -> Self {
+        FlowSink { records, exporter }
+    }
+
+    /// 离线模式下把数据包时间传给导出器，见 `FlowExporter::set_packet_clock`
+    pub fn set_packet_clock(&mut self, timestamp_ns: u64) {
+        if let Some(ref mut exporter) = self.exporter {
+            exporter.set_packet_clock(timestamp_ns);
+        }
+    }
+
+    pub fn emit(&mut self, records: &[FlowRecord]) {
+        if records.is_empty() {
+            return;
+        }
+        if let Some(ref tx) = self.records {
+            for record in records {
+                // 接收端已关闭（正在退出）时丢弃
+                let _ = tx.send(record.clone());
+            }
+        }
+        if let Some(ref mut exporter) = self.exporter {
+            // 采集器不可达（ICMP 端口不可达）时 send 会报错，不影响继续监控
+            if let Err(e) = exporter.export(records) {
+                warn!("导出流记录到 {} 失败: {}", exporter.target(), e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::tests::event;
+    use aya_network_monitor_common::{IPPROTO_TCP, IPPROTO_UDP};
+
+    const SEC: u64 = 1_000_000_000;
+
+    /// 10.0.0.1:<src_port> -> 10.0.0.2:80 的 TCP 包
+    fn tcp(src_port: u16, timestamp_ns: u64, size: u32, tcp_flags: u8) -> CapturedEvent {
+        let mut header = event(IPPROTO_TCP, "10.0.0.1", "10.0.0.2", (src_port, 80), size, &[]);
+        header.tcp_flags = tcp_flags;
+        CapturedEvent { header, data: Vec::new(), timestamp_ns, iface: Arc::from("eth0") }
+    }
+
+    #[test]
+    fn aggregates_by_five_tuple() {
+        let mut table = FlowTable::new(30, 300);
+        table.update(&tcp(40000, 10 * SEC, 60, 0x02));
+        table.update(&tcp(40000, 11 * SEC, 1500, 0x10));
+        table.update(&tcp(40001, 12 * SEC, 60, 0x02));
+        let mut udp = tcp(40000, 13 * SEC, 100, 0);
+        udp.header.protocol = IPPROTO_UDP;
+        table.update(&udp);
+
+        let records = table.flush();
+        assert_eq!(records.len(), 3);
+        assert!(records.iter().all(|record| record.end == FlowEnd::Flush));
+
+        let first = &records[0];
+        assert_eq!(first.key.src_addr().to_string(), "10.0.0.1");
+        assert_eq!(u16::from_be(first.key.src_port), 40000);
+        assert_eq!(first.key.protocol, IPPROTO_TCP);
+        assert_eq!(first.stats.packets, 2);
+        assert_eq!(first.stats.bytes, 1560);
+        assert_eq!(&*first.stats.iface, "eth0");
+
+        // flush 之后流表为空
+        assert!(table.flush().is_empty());
+    }
+
+    #[test]
+    fn tracks_first_and_last_seen_out_of_order() {
+        let mut table = FlowTable::new(30, 300);
+        table.update(&tcp(40000, 20 * SEC, 60, 0));
+        table.update(&tcp(40000, 15 * SEC, 60, 0));
+        table.update(&tcp(40000, 25 * SEC, 60, 0));
+        table.update(&tcp(40000, 18 * SEC, 60, 0));
+
+        let records = table.flush();
+        assert_eq!(records[0].stats.first_seen_ns, 15 * SEC);
+        assert_eq!(records[0].stats.last_seen_ns, 25 * SEC);
+    }
+
+    #[test]
+    fn unions_tcp_flags() {
+        let mut table = FlowTable::new(30, 300);
+        for flags in [0x02, 0x10, 0x18, 0x11] {
+            table.update(&tcp(40000, SEC, 60, flags));
+        }
+
+        let records = table.flush();
+        assert_eq!(records[0].stats.tcp_flags, 0x1B);
+        assert_eq!(format_tcp_flags(records[0].stats.tcp_flags), "SFP.");
+        assert_eq!(format_tcp_flags(0), "none");
+    }
+
+    #[test]
+    fn idle_timeout() {
+        let mut table = FlowTable::new(30, 300);
+        table.update(&tcp(40000, 100 * SEC, 60, 0));
+        table.update(&tcp(40001, 120 * SEC, 60, 0));
+
+        // 末包之后不到 30 秒
+        assert!(table.expire(129 * SEC).is_empty());
+
+        let records = table.expire(130 * SEC);
+        assert_eq!(records.len(), 1);
+        assert_eq!(u16::from_be(records[0].key.src_port), 40000);
+        assert_eq!(records[0].end, FlowEnd::Idle);
+
+        let records = table.expire(150 * SEC);
+        assert_eq!(u16::from_be(records[0].key.src_port), 40001);
+        assert!(table.flush().is_empty());
+    }
+
+    #[test]
+    fn active_timeout_splits_long_flows() {
+        let mut table = FlowTable::new(30, 60);
+        for secs in (0..=60).step_by(10) {
+            table.update(&tcp(40000, (100 + secs) * SEC, 60, 0));
+        }
+
+        // 一直有包，空闲超时不会触发，持续 60 秒后按活动超时输出
+        assert!(table.expire(159 * SEC).is_empty());
+        let records = table.expire(160 * SEC);
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].end, FlowEnd::Active);
+        assert_eq!(records[0].stats.packets, 7);
+
+        // 后续的包开始一条新记录
+        table.update(&tcp(40000, 161 * SEC, 60, 0));
+        let records = table.flush();
+        assert_eq!(records[0].stats.packets, 1);
+        assert_eq!(records[0].stats.first_seen_ns, 161 * SEC);
+    }
+
+    #[test]
+    fn tick_sweeps_at_most_once_per_second() {
+        let mut table = FlowTable::new(1, 300);
+        table.update(&tcp(40000, 10 * SEC, 60, 0));
+
+        assert_eq!(table.tick(12 * SEC).len(), 1);
+
+        table.update(&tcp(40001, 12 * SEC, 60, 0));
+        // 距上次检查不到 1 秒，即使已经超时也不检查
+        assert!(table.tick(13 * SEC - 1).is_empty());
+        assert_eq!(table.tick(13 * SEC).len(), 1);
+    }
+
+    #[test]
+    fn records_sorted_by_first_seen() {
+        let mut table = FlowTable::new(30, 300);
+        table.update(&tcp(40002, 3 * SEC, 60, 0));
+        table.update(&tcp(40000, SEC, 60, 0));
+        table.update(&tcp(40001, 2 * SEC, 60, 0));
+
+        let ports: Vec<_> = table.flush().iter().map(|record| u16::from_be(record.key.src_port)).collect();
+        assert_eq!(ports, [40000, 40001, 40002]);
+    }
+
+    fn counters(packets: u64, bytes: u64, seen: (u64, u64), ifindex: u32, tcp_flags: u8) -> FlowCounters {
+        FlowCounters {
+            packets,
+            bytes,
+            first_seen_ns: seen.0,
+            last_seen_ns: seen.1,
+            ifindex,
+            tcp_flags,
+            _pad: [0; 3],
+        }
+    }
+
+    #[test]
+    fn sink_sends_records_to_channel() {
+        let mut table = FlowTable::new(30, 300);
+        table.update(&tcp(40000, 10 * SEC, 60, 0x02));
+        table.update(&tcp(40001, 11 * SEC, 60, 0x02));
+
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let mut sink = FlowSink::new(Some(tx), None);
+        sink.emit(&table.flush());
+        sink.emit(&[]);
+        drop(sink);
+
+        let ports: Vec<_> = std::iter::from_fn(|| rx.try_recv().ok()).map(|record| u16::from_be(record.key.src_port)).collect();
+        assert_eq!(ports, [40000, 40001]);
+
+        // 没有订阅方也没有导出器时直接丢弃
+        FlowSink::new(None, None).emit(&[]);
+    }
+
+    #[test]
+    fn merge_per_cpu_counters() {
+        let per_cpu = [
+            FlowCounters::default(),
+            counters(3, 300, (500, 900), 3, 0x02),
+            counters(2, 3000, (400, 700), 2, 0x10),
+            FlowCounters::default(),
+            counters(1, 60, (800, 1200), 2, 0x01),
+        ];
+
+        let merged = merge_counters(per_cpu.iter()).unwrap();
+        assert_eq!(merged.packets, 6);
+        assert_eq!(merged.bytes, 3360);
+        assert_eq!(merged.first_seen_ns, 400);
+        assert_eq!(merged.last_seen_ns, 1200);
+        assert_eq!(merged.tcp_flags, 0x13);
+        // 第一个有包的 CPU 上的网卡
+        assert_eq!(merged.ifindex, 3);
+    }
+
+    #[test]
+    fn merge_skips_empty_cpus() {
+        // 没有包的 CPU 上时间戳为 0，不能参与取最早时间
+        let per_cpu = [FlowCounters::default(), counters(1, 60, (500, 500), 2, 0)];
+        let merged = merge_counters(per_cpu.iter()).unwrap();
+        assert_eq!(merged.first_seen_ns, 500);
+        assert_eq!(merged.packets, 1);
+
+        assert!(merge_counters([FlowCounters::default(); 4].iter()).is_none());
+        assert!(merge_counters(core::iter::empty()).is_none());
+    }
+}
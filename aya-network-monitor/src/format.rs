@@ -0,0 +1,412 @@
+//! 事件格式化
+//!
+//! 各显示模式（basic/hex/text/protocol/json）的文本输出。命令行和嵌入方共用这些函数，
+//! 嵌入方订阅到事件后也可以用 `Formatter` 得到与命令行完全相同的输出。
+
+use std::net::{IpAddr, SocketAddr};
+
+use aya_network_monitor_common::{NetworkEvent, DIRECTION_EGRESS};
+use serde::Serialize;
+
+use crate::{
+    detect::detect,
+    dissect::{Dissection, Packet, Registry},
+    event::CapturedEvent,
+};
+
+/// 显示模式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DisplayMode {
+    /// 基础模式：只显示头部信息
+    #[default]
+    Basic,
+    /// 十六进制模式：显示 hex dump + ASCII
+    Hex,
+    /// 文本模式：智能文本检测
+    Text,
+    /// 协议模式：解析常见协议
+    Protocol,
+    /// JSON 模式：为 Web 界面提供结构化数据
+    Json,
+    /// 流模式：按五元组聚合，流超时后输出一条流记录
+    Flows,
+}
+
+pub fn format_ip(ip: IpAddr) -> String {
+    // IPv6 使用 RFC 5952 压缩格式
+    ip.to_string()
+}
+
+/// 格式化 IP:端口，IPv6 地址加方括号，如 [2001:db8::1]:443
+pub fn format_endpoint(ip: IpAddr, port: u16) -> String {
+    // 端口在网络上是大端序，需要转换为主机字节序
+    SocketAddr::new(ip, u16::from_be(port)).to_string()
+}
+
+pub fn format_protocol(protocol: u8) -> &'static str {
+    match protocol {
+        6 => "TCP",
+        17 => "UDP",
+        1 => "ICMP",
+        58 => "ICMPv6",
+        _ => "UNKNOWN",
+    }
+}
+
+/// 格式化 VLAN 标签，如 " [vlan 100/200]"；未打标签时返回空字符串
+pub fn format_vlan(event: &NetworkEvent) -> String {
+    let vlans = event.vlans();
+    if vlans.is_empty() {
+        return String::new();
+    }
+
+    let ids = vlans.iter()
+        .map(|id| id.to_string())
+        .collect::<Vec<_>>()
+        .join("/");
+    format!(" [vlan {}]", ids)
+}
+
+/// 格式化捕获时间（本地时间，纳秒精度），如 14:03:27.123456789
+pub fn format_timestamp(timestamp_ns: u64) -> String {
+    let secs = (timestamp_ns / 1_000_000_000) as libc::time_t;
+    let nanos = timestamp_ns % 1_000_000_000;
+
+    let mut tm: libc::tm = unsafe { core::mem::zeroed() };
+    if unsafe { libc::localtime_r(&secs, &mut tm) }.is_null() {
+        return format!("{}.{:09}", secs, nanos);
+    }
+
+    format!("{:02}:{:02}:{:02}.{:09}", tm.tm_hour, tm.tm_min, tm.tm_sec, nanos)
+}
+
+pub fn format_direction(direction: u8) -> &'static str {
+    match direction {
+        DIRECTION_EGRESS => "egress",
+        _ => "ingress",
+    }
+}
+
+/// 对端地址始终在左侧、本机地址在右侧：收到的包为 `src -> dst`，发出的包为 `dst <- src`
+pub fn format_event(event: &NetworkEvent) -> String {
+    let proto = format_protocol(event.protocol);
+    let vlan = format_vlan(event);
+
+    let (src, dst) = match event.protocol {
+        6 | 17 => (
+            format_endpoint(event.src_addr(), event.src_port),
+            format_endpoint(event.dst_addr(), event.dst_port),
+        ),
+        _ => (format_ip(event.src_addr()), format_ip(event.dst_addr())),
+    };
+
+    if event.direction == DIRECTION_EGRESS {
+        format!("{} {} <- {} ({}b){}", proto, dst, src, event.packet_size, vlan)
+    } else {
+        format!("{} {} -> {} ({}b){}", proto, src, dst, event.packet_size, vlan)
+    }
+}
+
+/// 带捕获时间和网卡名的事件头部，所有文本显示模式共用
+pub fn format_header(event: &CapturedEvent) -> String {
+    format!("{} {} {}", format_timestamp(event.timestamp_ns), event.iface, format_event(event))
+}
+
+// ========== 显示模式相关函数 ==========
+
+/// 解析显示模式
+pub fn parse_display_mode(mode: &str) -> DisplayMode {
+    match mode.to_lowercase().as_str() {
+        "hex" => DisplayMode::Hex,
+        "text" => DisplayMode::Text,
+        "protocol" => DisplayMode::Protocol,
+        "json" => DisplayMode::Json,
+        "flows" => DisplayMode::Flows,
+        _ => DisplayMode::Basic,
+    }
+}
+
+/// 十六进制转储
+pub fn format_hex_dump(payload: &[u8], bytes_to_show: usize) -> String {
+    let mut output = String::new();
+    let bytes_to_show = core::cmp::min(bytes_to_show, payload.len());
+
+    for (i, chunk) in payload[..bytes_to_show].chunks(16).enumerate() {
+        let offset = i * 16;
+        output.push_str(&format!("{:04x}: ", offset));
+
+        // 十六进制部分
+        for (j, byte) in chunk.iter().enumerate() {
+            output.push_str(&format!("{:02x} ", byte));
+            if j == 7 {
+                output.push(' ');
+            }
+        }
+
+        // 填充空格
+        for j in chunk.len()..16 {
+            output.push_str("   ");
+            if j == 7 {
+                output.push(' ');
+            }
+        }
+
+        output.push_str("  ");
+
+        // ASCII 部分
+        for byte in chunk {
+            if byte.is_ascii_graphic() || *byte == b' ' {
+                output.push(*byte as char);
+            } else {
+                output.push('.');
+            }
+        }
+        output.push('\n');
+    }
+
+    output
+}
+
+/// 十六进制转储（带分页）
+pub fn format_hex_dump_paged(payload: &[u8], bytes_to_show: usize, page_lines: usize) -> String {
+    let mut output = String::new();
+    let bytes_to_show = core::cmp::min(bytes_to_show, payload.len());
+
+    if page_lines == 0 {
+        // 不分页，显示全部
+        return format_hex_dump(payload, bytes_to_show);
+    }
+
+    let total_lines = bytes_to_show.div_ceil(16);
+    let pages = total_lines.div_ceil(page_lines);
+
+    for page in 0..pages {
+        let start_line = page * page_lines;
+        let end_line = core::cmp::min((page + 1) * page_lines, total_lines);
+        let start_byte = start_line * 16;
+        let end_byte = core::cmp::min(end_line * 16, bytes_to_show);
+
+        output.push_str(&format!("--- 页 {} ({}-{} 字节) ---\n", page + 1, start_byte, end_byte - 1));
+
+        for line in start_line..end_line {
+            let line_start = line * 16;
+            let line_end = core::cmp::min(line_start + 16, bytes_to_show);
+            let chunk = &payload[line_start..line_end];
+
+            output.push_str(&format!("{:04x}: ", line_start));
+
+            // 十六进制部分
+            for (j, byte) in chunk.iter().enumerate() {
+                output.push_str(&format!("{:02x} ", byte));
+                if j == 7 {
+                    output.push(' ');
+                }
+            }
+
+            // 填充空格
+            for j in chunk.len()..16 {
+                output.push_str("   ");
+                if j == 7 {
+                    output.push(' ');
+                }
+            }
+
+            output.push_str("  ");
+
+            // ASCII 部分
+            for byte in chunk {
+                if byte.is_ascii_graphic() || *byte == b' ' {
+                    output.push(*byte as char);
+                } else {
+                    output.push('.');
+                }
+            }
+            output.push('\n');
+        }
+
+        if page < pages - 1 {
+            output.push('\n');
+        }
+    }
+
+    output
+}
+
+/// 检测并显示文本内容
+pub fn format_text_payload(payload: &[u8]) -> String {
+    // 检查是否主要是可打印 ASCII
+    let printable_count = payload.iter()
+        .filter(|&&b| b.is_ascii_graphic() || b.is_ascii_whitespace())
+        .count();
+
+    let ratio = printable_count as f64 / payload.len() as f64;
+
+    // 如果超过 80% 是可打印字符，显示为文本
+    if ratio > 0.8 && !payload.is_empty() {
+        let text = String::from_utf8_lossy(payload);
+        return text.lines()
+            .take(10) // 最多显示 10 行
+            .map(|line| format!("  {}", line))
+            .collect::<Vec<_>>()
+            .join("\n");
+    }
+
+    // 否则显示十六进制
+    format_hex_dump(payload, payload.len())
+}
+
+/// 协议解析
+pub fn format_protocol_parse(event: &CapturedEvent, registry: &Registry) -> String {
+    // 头部与基础模式一致
+    let header = format!("{}\n", format_header(event));
+
+    match registry.dissect_event(event) {
+        Some(dissection) => format!("{}{}", header, dissection.to_text()),
+        // 无法解析，显示按内容识别的协议和文本或十六进制
+        None => match detect(&Packet::from_event(event)) {
+            Some(app) => format!("{}应用协议: {}\n{}", header, app, format_text_payload(event.payload())),
+            None => format!("{}{}", header, format_text_payload(event.payload())),
+        },
+    }
+}
+
+/// JSON 输出的结构体
+#[derive(Debug, Serialize)]
+pub struct JsonEvent {
+    pub timestamp: i64,
+    pub timestamp_ns: u64,
+    pub iface: String,
+    pub ifindex: u32,
+    pub rx_queue: u32,
+    pub direction: &'static str,
+    pub protocol: String,
+    /// 按 payload 内容识别的应用层协议，无法识别时为 null
+    pub app_protocol: Option<&'static str>,
+    pub ip_version: u8,
+    pub vlan_ids: Vec<u16>,
+    pub src_ip: String,
+    pub dst_ip: String,
+    pub src_port: u16,
+    pub dst_port: u16,
+    pub packet_size: u32,
+    pub tcp_flags: u8,
+    pub payload_len: usize,
+    pub payload_hex: String,
+    /// 协议解析树，以解析器名为键，如 "tls": {...}
+    #[serde(flatten)]
+    pub dissection: serde_json::Map<String, serde_json::Value>,
+}
+
+impl JsonEvent {
+    pub fn new(event: &CapturedEvent, dissection: Option<&Dissection>) -> Self {
+        JsonEvent {
+            timestamp: (event.timestamp_ns / 1_000_000_000) as i64,
+            timestamp_ns: event.timestamp_ns,
+            iface: event.iface.to_string(),
+            ifindex: event.ifindex,
+            rx_queue: event.rx_queue,
+            direction: format_direction(event.direction),
+            protocol: format_protocol(event.protocol).to_string(),
+            app_protocol: detect(&Packet::from_event(event)),
+            ip_version: event.ip_version,
+            vlan_ids: event.vlans().to_vec(),
+            src_ip: format_ip(event.src_addr()),
+            dst_ip: format_ip(event.dst_addr()),
+            src_port: u16::from_be(event.src_port),
+            dst_port: u16::from_be(event.dst_port),
+            packet_size: event.packet_size,
+            tcp_flags: event.tcp_flags,
+            payload_len: event.payload().len(),
+            payload_hex: {
+                let bytes = event.payload();
+                bytes.iter()
+                    .map(|b| format!("{:02x}", b))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            },
+            dissection: dissection
+                .map(|dissection| {
+                    let mut map = serde_json::Map::new();
+                    map.insert(dissection.protocol.to_string(), dissection.to_json());
+                    map
+                })
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// 转换为 JSON
+pub fn format_json(event: &CapturedEvent, registry: &Registry) -> String {
+    let json_event = JsonEvent::new(event, registry.dissect_event(event).as_ref());
+    serde_json::to_string(&json_event).unwrap_or_else(|_| "{}".to_string())
+}
+
+/// hex/text 模式：头部之后附上协议解析树（能解析时）
+pub fn format_header_with_dissection(event: &CapturedEvent, registry: &Registry) -> String {
+    let mut output = format_header(event);
+    if let Some(dissection) = registry.dissect_event(event) {
+        output.push('\n');
+        output.push_str(dissection.to_text().trim_end());
+    }
+    output
+}
+
+/// 按显示模式格式化事件，对应命令行的 --mode 和 payload 显示参数
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Formatter {
+    pub mode: DisplayMode,
+    /// 显示 payload 的最大字节数（用于 hex/text 模式）
+    pub payload_bytes: usize,
+    /// 显示完整捕获的 payload，忽略 payload_bytes
+    pub payload_full: bool,
+    /// 分页显示，每页显示的行数（用于 hex 模式），0 表示不分页
+    pub page_lines: usize,
+}
+
+impl Formatter {
+    /// 根据显示模式格式化事件
+    pub fn format(&self, event: &CapturedEvent, registry: &Registry) -> String {
+        let payload = event.payload();
+
+        // 确定 payload 显示大小
+        let effective_bytes = if self.payload_full {
+            payload.len()
+        } else {
+            core::cmp::min(self.payload_bytes, payload.len())
+        };
+
+        match self.mode {
+            DisplayMode::Basic | DisplayMode::Flows => format_header(event),
+            DisplayMode::Hex => {
+                let mut output = format_header_with_dissection(event, registry);
+                output.push_str(&format!("\nPayload ({} bytes, 显示 {} bytes):\n", payload.len(), effective_bytes));
+
+                // 根据是否分页选择格式化函数
+                if self.page_lines > 0 && effective_bytes > self.page_lines * 16 {
+                    output.push_str(&format_hex_dump_paged(
+                        payload,
+                        effective_bytes,
+                        self.page_lines,
+                    ));
+                } else {
+                    output.push_str(&format_hex_dump(
+                        payload,
+                        effective_bytes,
+                    ));
+                }
+                output
+            }
+            DisplayMode::Text => {
+                let mut output = format_header_with_dissection(event, registry);
+                if !payload.is_empty() {
+                    output.push_str("\nContent:\n");
+                    let bytes = &payload[..effective_bytes];
+                    output.push_str(&format_text_payload(bytes));
+                }
+                output
+            }
+            DisplayMode::Protocol => format_protocol_parse(event, registry),
+            DisplayMode::Json => format_json(event, registry),
+        }
+    }
+}
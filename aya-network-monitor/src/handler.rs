@@ -0,0 +1,261 @@
+//! 事件处理
+//!
+//! 每个读取任务（每 CPU 的 perf buffer、ring buffer 或离线文件）各持有一个 `EventHandler`：
+//! 用户空间过滤、计入流表和指标、写入 pcapng，然后把匹配的事件交给 `Output`——
+//! 命令行按显示模式格式化为文本行，嵌入方订阅解析后的事件，两者都通过通道送出，
+//! 库本身不写 stdout。
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::BufWriter,
+    sync::{Arc, Mutex},
+};
+
+use log::debug;
+use tokio::sync::mpsc;
+
+use crate::{
+    detect::detect,
+    dissect::{Dissection, Packet, Registry},
+    event::CapturedEvent,
+    filter::Filter,
+    flow::SharedFlowTable,
+    format::{format_endpoint, Formatter},
+    metrics::ReaderMetrics,
+    pcap::PcapngWriter,
+    stats::ReaderSummary,
+};
+
+/// 所有任务共享的 pcapng 输出
+pub type SharedPcapWriter = Arc<Mutex<PcapngWriter<BufWriter<File>>>>;
+
+/// 发送给订阅方的事件：原始事件 + 按内容识别的应用层协议 + 协议解析结果
+#[derive(Debug, Clone)]
+pub struct DecodedEvent {
+    pub event: CapturedEvent,
+    pub app_protocol: Option<&'static str>,
+    pub dissection: Option<Dissection>,
+}
+
+/// 匹配过滤条件的事件去向
+#[derive(Clone)]
+pub enum Output {
+    /// 按显示模式格式化为文本行，发送到通道（命令行由单独的任务写到 stdout）
+    Print(Formatter, mpsc::UnboundedSender<String>),
+    /// 解析后发送到通道；订阅方处理不过来时丢弃事件，不阻塞读取任务
+    Channel(mpsc::Sender<DecodedEvent>),
+    /// 不逐包输出（只计入流表、指标或写入 pcapng）
+    Discard,
+}
+
+impl Output {
+    /// 创建容量为 capacity 的订阅通道
+    pub fn channel(capacity: usize) -> (Self, mpsc::Receiver<DecodedEvent>) {
+        let (tx, rx) = mpsc::channel(capacity);
+        (Output::Channel(tx), rx)
+    }
+
+    /// 按显示模式格式化输出；文本行不能丢，通道不设上限
+    pub fn print(formatter: Formatter) -> (Self, mpsc::UnboundedReceiver<String>) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        (Output::Print(formatter, tx), rx)
+    }
+}
+
+/// 事件处理：过滤、输出、写入 pcapng 和统计
+pub struct EventHandler {
+    filter: Filter,
+    registry: Arc<Registry>,
+    output: Output,
+    debug: bool,
+    pcap_writer: Option<SharedPcapWriter>,
+    flows: Option<SharedFlowTable>,
+    metrics: Option<ReaderMetrics>,
+    counters: HashMap<u8, usize>,
+    total: usize,
+    filtered: usize,
+    bytes: u64,
+}
+
+impl EventHandler {
+    pub fn new(filter: Filter, registry: Arc<Registry>, output: Output) -> Self {
+        EventHandler {
+            filter,
+            registry,
+            output,
+            debug: false,
+            pcap_writer: None,
+            flows: None,
+            metrics: None,
+            counters: HashMap::new(),
+            total: 0,
+            filtered: 0,
+            bytes: 0,
+        }
+    }
+
+    /// 把匹配的事件写入 pcapng
+    pub fn pcap_writer(mut self, pcap_writer: Option<SharedPcapWriter>) -> Self {
+        self.pcap_writer = pcap_writer;
+        self
+    }
+
+    /// 把匹配的事件计入流表
+    pub fn flows(mut self, flows: Option<SharedFlowTable>) -> Self {
+        self.flows = flows;
+        self
+    }
+
+    /// 把事件计入 Prometheus 指标
+    pub fn metrics(mut self, metrics: Option<ReaderMetrics>) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    /// 在 stderr 上输出每个事件的调试信息
+    pub fn debug(mut self, debug: bool) -> Self {
+        self.debug = debug;
+        self
+    }
+
+    /// 该读取任务的流表
+    pub fn flow_table(&self) -> Option<&SharedFlowTable> {
+        self.flows.as_ref()
+    }
+
+    /// 该读取任务的计数，用于退出时的汇总
+    pub fn summary(&self, label: impl Into<String>) -> ReaderSummary {
+        ReaderSummary {
+            label: label.into(),
+            total: self.total,
+            filtered: self.filtered,
+            bytes: self.bytes,
+            protocols: self.counters.clone(),
+        }
+    }
+
+    /// 处理一个事件；只有写入 pcapng 失败时返回错误
+    pub fn handle(&mut self, event: &CapturedEvent) -> std::io::Result<()> {
+        self.total += 1;
+        if let Some(ref metrics) = self.metrics {
+            metrics.observe(event);
+        }
+
+        // 调试输出（如果启用）
+        if self.debug {
+            eprintln!("[DEBUG] Total events: {}", self.total);
+            eprintln!("[DEBUG] Event: {} -> {} ({}b)",
+                format_endpoint(event.src_addr(), event.src_port),
+                format_endpoint(event.dst_addr(), event.dst_port),
+                event.packet_size
+            );
+            eprintln!("[DEBUG] Filter: {}", self.filter.expr());
+        }
+
+        // 应用过滤
+        if !self.filter.matches(event) {
+            return Ok(());
+        }
+        self.filtered += 1;
+        if let Some(ref metrics) = self.metrics {
+            metrics.matched(event);
+        }
+
+        // 流模式或导出时计入流表，流记录由超时检查统一输出
+        if let Some(ref flows) = self.flows {
+            flows.lock().unwrap().update(event);
+        }
+        match self.output {
+            Output::Print(ref formatter, ref tx) => {
+                // 接收端已关闭（正在退出）时不再输出
+                let _ = tx.send(formatter.format(event, &self.registry));
+            }
+            Output::Channel(ref tx) => {
+                let decoded = DecodedEvent {
+                    event: event.clone(),
+                    app_protocol: detect(&Packet::from_event(event)),
+                    dissection: self.registry.dissect_event(event),
+                };
+                if tx.try_send(decoded).is_err() {
+                    debug!("订阅通道已满或已关闭，丢弃事件");
+                }
+            }
+            Output::Discard => {}
+        }
+
+        // 统计
+        *self.counters.entry(event.protocol).or_insert(0) += 1;
+        self.bytes += event.packet_size as u64;
+
+        // 写入 pcapng
+        if let Some(ref writer) = self.pcap_writer {
+            writer.lock().unwrap().write_packet(
+                &event.iface,
+                event.timestamp_ns,
+                event.captured(),
+                event.packet_size,
+                event.direction,
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::{tests::event, FilterSpec};
+    use aya_network_monitor_common::{IPPROTO_TCP, IPPROTO_UDP};
+
+    fn captured(protocol: u8, dst_port: u16) -> CapturedEvent {
+        let header = event(protocol, "10.0.0.1", "10.0.0.2", (40000, dst_port), 100, &[]);
+        CapturedEvent { header, data: Vec::new(), timestamp_ns: 0, iface: Arc::from("eth0") }
+    }
+
+    /// 只匹配 TCP 的处理器
+    fn tcp_handler(output: Output) -> EventHandler {
+        let filter = Filter::new(FilterSpec { protocol: Some(IPPROTO_TCP), ..Default::default() }).unwrap();
+        EventHandler::new(filter, Arc::new(Registry::new()), output)
+    }
+
+    #[test]
+    fn prints_matching_events_as_lines() {
+        let (output, mut lines) = Output::print(Formatter::default());
+        let mut handler = tcp_handler(output);
+        handler.handle(&captured(IPPROTO_TCP, 443)).unwrap();
+        handler.handle(&captured(IPPROTO_UDP, 53)).unwrap();
+        assert_eq!(handler.summary("cpu0").filtered, 1);
+        drop(handler);
+
+        let line = lines.try_recv().unwrap();
+        assert!(line.ends_with(" eth0 TCP 10.0.0.1:40000 -> 10.0.0.2:443 (100b)"), "{}", line);
+        // 发送端已丢弃，通道关闭
+        assert!(lines.try_recv().is_err());
+    }
+
+    #[test]
+    fn sends_decoded_events_to_subscriber() {
+        let (output, mut events) = Output::channel(1);
+        let mut handler = tcp_handler(output);
+        handler.handle(&captured(IPPROTO_TCP, 443)).unwrap();
+        // 通道已满时丢弃，不阻塞读取任务
+        handler.handle(&captured(IPPROTO_TCP, 80)).unwrap();
+
+        let decoded = events.try_recv().unwrap();
+        assert_eq!(u16::from_be(decoded.event.dst_port), 443);
+        assert!(decoded.app_protocol.is_none());
+        assert!(decoded.dissection.is_none());
+        assert!(events.try_recv().is_err());
+    }
+
+    #[test]
+    fn closed_output_is_not_an_error() {
+        let (output, lines) = Output::print(Formatter::default());
+        drop(lines);
+        let mut handler = tcp_handler(output);
+        assert!(handler.handle(&captured(IPPROTO_TCP, 443)).is_ok());
+        assert_eq!(handler.summary("file").filtered, 1);
+    }
+}
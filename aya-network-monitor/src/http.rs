@@ -0,0 +1,139 @@
+//! HTTP/1.x 解析
+//!
+//! 只看 payload 开头的起始行和头部（最多 20 行），不重组跨包的报文。
+
+use crate::dissect::{Dissector, Node, Packet};
+
+const METHODS: [&str; 9] = ["GET", "POST", "PUT", "DELETE", "HEAD", "OPTIONS", "PATCH", "CONNECT", "TRACE"];
+
+/// 最多解析的头部行数
+const MAX_HEADERS: usize = 20;
+
+/// 是否以 HTTP 请求方法或响应版本开头
+pub fn looks_like_http(payload: &[u8]) -> bool {
+    payload.starts_with(b"HTTP/1.")
+        || METHODS.iter().any(|method| {
+            payload.len() > method.len()
+                && payload.starts_with(method.as_bytes())
+                && payload[method.len()] == b' '
+        })
+}
+
+/// 解析起始行和头部
+fn decode(payload: &[u8]) -> Option<Node> {
+    if !looks_like_http(payload) {
+        return None;
+    }
+
+    let text = String::from_utf8_lossy(payload);
+    let mut lines = text.lines();
+    let first_line = lines.next()?.trim();
+    let mut parts = first_line.splitn(3, ' ');
+    let (first, second, third) = (parts.next()?, parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+
+    let node = if first.starts_with("HTTP/") {
+        Node::new("HTTP Response")
+            .field("version", first)
+            .field("status", second.parse::<u16>().ok()?)
+            .field("reason", third)
+    } else {
+        Node::new("HTTP Request").field("method", first).field("uri", second).field("version", third)
+    };
+
+    let mut headers = Node::default();
+    for line in lines.take(MAX_HEADERS).map(str::trim).take_while(|line| !line.is_empty()) {
+        let (name, value) = line.split_once(':').unwrap_or((line, ""));
+        headers = headers.field(name.trim(), value.trim());
+    }
+
+    Some(node.field("headers", headers))
+}
+
+/// HTTP/1.x 解析器
+pub struct HttpDissector;
+
+impl Dissector for HttpDissector {
+    fn name(&self) -> &'static str {
+        "http"
+    }
+
+    fn ports(&self) -> &[(u8, u16)] {
+        &[(6, 80), (6, 8080)]
+    }
+
+    fn probe(&self, packet: &Packet) -> bool {
+        packet.protocol == 6 && looks_like_http(packet.payload)
+    }
+
+    fn decode(&self, packet: &Packet) -> Option<Node> {
+        decode(packet.payload)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dissect::Value;
+
+    fn field<'a>(node: &'a Node, name: &str) -> Option<&'a Value> {
+        node.fields.iter().find(|(field, _)| field == name).map(|(_, value)| value)
+    }
+
+    fn text(node: &Node, name: &str) -> String {
+        field(node, name).map(|value| value.to_string()).unwrap_or_default()
+    }
+
+    #[test]
+    fn decodes_request() {
+        let node = decode(b"GET /index.html HTTP/1.1\r\nHost: example.com\r\nAccept:  */*\r\n\r\nbody").unwrap();
+        assert_eq!(node.summary.as_deref(), Some("HTTP Request"));
+        assert_eq!(text(&node, "method"), "GET");
+        assert_eq!(text(&node, "uri"), "/index.html");
+        assert_eq!(text(&node, "version"), "HTTP/1.1");
+
+        let headers = match field(&node, "headers") {
+            Some(Value::Node(headers)) => headers,
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(text(headers, "Host"), "example.com");
+        assert_eq!(text(headers, "Accept"), "*/*");
+        // 空行之后是 body
+        assert_eq!(headers.fields.len(), 2);
+    }
+
+    #[test]
+    fn decodes_response() {
+        let node = decode(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n").unwrap();
+        assert_eq!(node.summary.as_deref(), Some("HTTP Response"));
+        assert_eq!(text(&node, "status"), "404");
+        assert_eq!(text(&node, "reason"), "Not Found");
+
+        // 状态码不是数字
+        assert!(decode(b"HTTP/1.1 abc OK\r\n\r\n").is_none());
+    }
+
+    #[test]
+    fn limits_header_lines() {
+        let mut payload = b"POST / HTTP/1.1\r\n".to_vec();
+        for i in 0..30 {
+            payload.extend_from_slice(format!("X-{}: {}\r\n", i, i).as_bytes());
+        }
+        let node = decode(&payload).unwrap();
+        match field(&node, "headers") {
+            Some(Value::Node(headers)) => assert_eq!(headers.fields.len(), MAX_HEADERS),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn rejects_non_http() {
+        for payload in [&b""[..], b"GET", b"GETX / HTTP/1.1", b"get / HTTP/1.1", b"HTTP/2 200", b"\x16\x03\x01"] {
+            assert!(!looks_like_http(payload), "{:?}", payload);
+            assert!(decode(payload).is_none());
+        }
+        assert!(looks_like_http(b"CONNECT example.com:443 HTTP/1.1"));
+
+        let udp = Packet { protocol: 17, src_port: 1, dst_port: 80, payload: b"GET / HTTP/1.1\r\n" };
+        assert!(!HttpDissector.probe(&udp));
+    }
+}
@@ -0,0 +1,93 @@
+//! 网卡列表
+//!
+//! `--iface` 接受逗号分隔的多个网卡名，或者 `all`（除 lo 以外的所有网卡）。
+//! eBPF 事件中只有 ifindex，用户空间通过这里的映射换算回网卡名。
+
+use std::{ffi::CString, fs, sync::Arc};
+
+use anyhow::Context as _;
+
+const SYS_CLASS_NET: &str = "/sys/class/net";
+
+/// 要监控的网卡（ifindex, 名称）
+#[derive(Debug, Clone)]
+pub struct Interfaces {
+    list: Vec<(u32, Arc<str>)>,
+}
+
+impl Interfaces {
+    /// 把网卡名解析为 ifindex；`all` 展开为系统中除 lo 以外的所有网卡
+    pub fn resolve(names: &[String]) -> anyhow::Result<Self> {
+        let all = names.iter().any(|name| name.eq_ignore_ascii_case("all"));
+        let names = if all {
+            let mut found = Vec::new();
+            for entry in fs::read_dir(SYS_CLASS_NET).context(format!("读取 {} 失败", SYS_CLASS_NET))? {
+                let name = entry?.file_name().to_string_lossy().into_owned();
+                if name != "lo" {
+                    found.push(name);
+                }
+            }
+            found
+        } else {
+            names.to_vec()
+        };
+
+        let mut list: Vec<(u32, Arc<str>)> = Vec::new();
+        for name in names {
+            let ifindex = if_nametoindex(&name).context(format!("找不到网卡: {}", name))?;
+            if !list.iter().any(|(index, _)| *index == ifindex) {
+                list.push((ifindex, Arc::from(name)));
+            }
+        }
+
+        if list.is_empty() {
+            anyhow::bail!("没有可监控的网卡");
+        }
+        if all {
+            // read_dir 的顺序不固定，按 ifindex 排序
+            list.sort_by_key(|(ifindex, _)| *ifindex);
+        }
+
+        Ok(Interfaces { list })
+    }
+
+    /// 直接给出 (ifindex, 名称) 列表，用于测试
+    #[cfg(test)]
+    pub(crate) fn from_list(list: &[(u32, &str)]) -> Self {
+        Interfaces { list: list.iter().map(|&(ifindex, name)| (ifindex, Arc::from(name))).collect() }
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.list.iter().map(|(_, name)| &**name)
+    }
+
+    /// ifindex 对应的网卡名；不在列表中时（如重定向来的包）显示为 if<ifindex>
+    pub fn name(&self, ifindex: u32) -> Arc<str> {
+        self.list
+            .iter()
+            .find(|(index, _)| *index == ifindex)
+            .map(|(_, name)| name.clone())
+            .unwrap_or_else(|| Arc::from(format!("if{}", ifindex)))
+    }
+}
+
+fn if_nametoindex(name: &str) -> Option<u32> {
+    let c_name = CString::new(name).ok()?;
+    match unsafe { libc::if_nametoindex(c_name.as_ptr()) } {
+        0 => None,
+        ifindex => Some(ifindex),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn name_falls_back_to_ifindex() {
+        let ifaces = Interfaces::from_list(&[(2, "eth0"), (3, "eth1")]);
+        assert_eq!(ifaces.names().collect::<Vec<_>>(), ["eth0", "eth1"]);
+        assert_eq!(&*ifaces.name(3), "eth1");
+        assert_eq!(&*ifaces.name(7), "if7");
+    }
+}
@@ -0,0 +1,72 @@
+//! Aya eBPF 网络流量监控
+//!
+//! 命令行工具 `aya-network-monitor` 只是这个库的一层外壳，其他程序可以直接嵌入捕获流水线：
+//!
+//! - `Capture`：加载 eBPF 程序并附加到网卡，为每个读取任务创建一个 `EventHandler`；
+//!   `run_offline` 从 pcap/pcapng 文件读取
+//! - `Filter` / `FilterSpec`：单项过滤参数和 tcpdump 风格表达式（`filter`）
+//! - `detect`：按 payload 特征识别应用层协议，不依赖端口
+//! - `EventHandler`：用户空间过滤、流表、指标、pcapng，然后交给 `Output`
+//! - `Output::channel`：订阅解析后的事件（`DecodedEvent`），不需要解析 stdout
+//! - `Registry` / `Dissector`：协议解析器（`dissect`、`http`、`dns`、`tls`）
+//! - `Formatter` 和 `format` 中的函数：与命令行相同的文本和 JSON 输出
+//!
+//! ```no_run
+//! use std::sync::Arc;
+//!
+//! use aya_network_monitor::{Capture, CaptureOptions, EventHandler, Filter, FilterSpec, Hook, Output, Registry};
+//! use aya_network_monitor_common::{DEFAULT_CAPTURE_SIZE, TRANSPORT_PERF};
+//!
+//! # #[tokio::main]
+//! # async fn main() -> anyhow::Result<()> {
+//! let filter = Filter::new(FilterSpec { expression: Some("tcp port 443".into()), ..Default::default() })?;
+//! let options = CaptureOptions {
+//!     ifaces: vec!["eth0".into()],
+//!     hook: Hook::Xdp,
+//!     xdp_skb_mode: false,
+//!     transport: TRANSPORT_PERF,
+//!     snaplen: DEFAULT_CAPTURE_SIZE,
+//!     kernel_flows: false,
+//! };
+//! let mut capture = Capture::open(&options, &filter)?;
+//!
+//! let registry = Arc::new(Registry::builtin());
+//! let (output, mut events) = Output::channel(1024);
+//! let (_shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+//! capture.spawn_readers(|_| EventHandler::new(filter.clone(), registry.clone(), output.clone()), shutdown_rx)?;
+//!
+//! while let Some(decoded) = events.recv().await {
+//!     if let Some(ref dissection) = decoded.dissection {
+//!         println!("{} {}", decoded.event.iface, dissection.protocol);
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod capture;
+pub mod decode;
+pub mod detect;
+pub mod dissect;
+pub mod dns;
+pub mod event;
+pub mod export;
+pub mod filter;
+pub mod flow;
+pub mod format;
+pub mod handler;
+pub mod http;
+pub mod iface;
+pub mod metrics;
+pub mod net;
+pub mod pcap;
+pub mod ports;
+pub mod stats;
+pub mod tls;
+
+pub use capture::{run_offline, Capture, CaptureOptions, Hook};
+pub use dissect::{Dissection, Dissector, Registry};
+pub use event::CapturedEvent;
+pub use filter::{Filter, FilterError, FilterSpec};
+pub use format::{DisplayMode, Formatter};
+pub use handler::{DecodedEvent, EventHandler, Output};
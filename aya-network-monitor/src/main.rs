@@ -1,794 +1,740 @@
+use std::{
+    fs::File,
+    io::BufWriter,
+    net::{IpAddr, SocketAddr},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
 use anyhow::Context as _;
-use aya::{
-    maps::perf::PerfEventArray,
-    programs::{Xdp, XdpFlags},
-    util::online_cpus,
-    Ebpf,
+use aya_network_monitor::{
+    capture::{self, Capture, CaptureOptions, Hook},
+    dissect::Registry,
+    export::{ExportTarget, FlowExporter},
+    filter::{Filter, FilterSpec},
+    flow::{self, FlowSink, FlowTable, SharedFlowSink, SharedFlowTable},
+    format::{parse_display_mode, DisplayMode, Formatter},
+    handler::{EventHandler, Output, SharedPcapWriter},
+    metrics::{self, KernelSource, Metrics, ReaderMetrics},
+    net::IpNet,
+    pcap::PcapngWriter,
+    ports::{format_ports, PortRange},
+    stats::{self, KernelSummary, Summary},
 };
-use aya_network_monitor_common::{NetworkEvent, MAX_PAYLOAD_SIZE};
-use bytes::BytesMut;
+use aya_network_monitor_common::{DEFAULT_CAPTURE_SIZE, MAX_CAPTURE_SIZE, TRANSPORT_PERF, TRANSPORT_RINGBUF};
 use clap::Parser;
-use log::{debug, info, warn};
-use serde::Serialize;
-use std::net::Ipv4Addr;
-use tokio::{signal, task};
-
-/// 显示模式
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
-pub enum DisplayMode {
-    /// 基础模式：只显示头部信息
-    #[default]
-    Basic,
-    /// 十六进制模式：显示 hex dump + ASCII
-    Hex,
-    /// 文本模式：智能文本检测
-    Text,
-    /// 协议模式：解析常见协议
-    Protocol,
-    /// JSON 模式：为 Web 界面提供结构化数据
-    Json,
-}
+use log::{info, warn};
+use tokio::{signal, sync::mpsc, task};
 
 #[derive(Debug, Parser, Clone)]
 #[command(author, version, about, long_about = None)]
 struct Opt {
-    /// 网络接口名称
-    #[clap(short, long, default_value = "eth0")]
-    iface: String,
+    /// 网络接口名称，多个网卡用逗号分隔（如 eth0,eth1），all 表示除 lo 以外的所有网卡
+    #[clap(short, long, value_delimiter = ',', default_value = "eth0")]
+    iface: Vec<String>,
 
-    /// XDP 模式: drv (驱动模式) 或 skb (SKB 模式)
+    /// 挂载点: xdp (只有收到的包), tc-ingress, tc-egress 或 tc-both (clsact，可以看到本机发出的包)
+    #[clap(long, default_value = "xdp")]
+    hook: String,
+
+    /// XDP 模式: drv (驱动模式) 或 skb (SKB 模式)，仅用于 --hook xdp
     #[clap(long, default_value = "drv")]
     xdp_mode: String,
 
-    /// 过滤协议: tcp, udp, icmp 或 all
+    /// 事件传输方式: perf (每 CPU perf buffer) 或 ringbuf (共享 ring buffer，需要内核 >= 5.8)
+    #[clap(long, default_value = "perf")]
+    transport: String,
+
+    /// 过滤协议: tcp, udp, icmp, icmp6 或 all
     #[clap(long, default_value = "all")]
     protocol: String,
 
-    /// 过滤源 IP 地址
+    /// 过滤源 IP 地址（IPv4 或 IPv6）
     #[clap(long)]
-    src_ip: Option<String>,
+    src_ip: Option<IpAddr>,
 
-    /// 过滤目标 IP 地址
+    /// 过滤目标 IP 地址（IPv4 或 IPv6）
     #[clap(long)]
-    dst_ip: Option<String>,
+    dst_ip: Option<IpAddr>,
 
-    /// 过滤源端口
-    #[clap(long)]
-    src_port: Option<u16>,
+    /// 过滤源网段（CIDR，如 10.0.0.0/8），可重复或用逗号分隔，命中任意一个即可
+    #[clap(long, value_delimiter = ',')]
+    src_net: Vec<IpNet>,
+
+    /// 过滤目标网段（CIDR，如 2001:db8::/32），可重复或用逗号分隔，命中任意一个即可
+    #[clap(long, value_delimiter = ',')]
+    dst_net: Vec<IpNet>,
+
+    /// 过滤源端口，支持范围和逗号分隔的组合，如 8000-8100,443
+    #[clap(long, value_delimiter = ',')]
+    src_port: Vec<PortRange>,
+
+    /// 过滤目标端口，支持范围和逗号分隔的组合，如 8000-8100,443,53
+    #[clap(long, value_delimiter = ',')]
+    dst_port: Vec<PortRange>,
 
-    /// 过滤目标端口
+    /// 过滤端口（源端口或目标端口任意一个匹配即可），格式同 --dst-port
+    #[clap(long, value_delimiter = ',')]
+    port: Vec<PortRange>,
+
+    /// tcpdump 风格的过滤表达式，如 "tcp and (port 80 or port 443) and not host 10.0.0.5"，
+    /// 与其他过滤参数同时使用时各条件之间为 and；实时捕获时编译到 XDP 程序中执行。
+    /// --bpf-filter 只是别名，语法见 FILTERING.md，不是 tcpdump/libpcap 的 BPF 语法
+    #[clap(short, long, visible_alias = "bpf-filter")]
+    filter: Option<String>,
+
+    /// 过滤 VLAN ID（外层或内层标签任意一个匹配即可）
     #[clap(long)]
-    dst_port: Option<u16>,
+    vlan: Option<u16>,
 
-    /// 显示模式：basic, hex, text, protocol, json
+    /// 显示模式：basic, hex, text, protocol, json, flows
     #[clap(long, default_value = "basic")]
     mode: String,
 
-    /// 显示 payload 的最大字节数（用于 hex/text 模式，最大512）
+    /// 流模式的空闲超时（秒）：超过该时间没有新包的流输出并移出流表
+    #[clap(long, default_value = "15")]
+    flow_idle_timeout: u64,
+
+    /// 流模式的活动超时（秒）：持续时间超过该值的流先输出一条记录，之后重新计数
+    #[clap(long, default_value = "60")]
+    flow_active_timeout: u64,
+
+    /// 在内核中按五元组统计流（LRU 哈希表），不上送逐包事件，适合高速链路；输出格式同 --mode flows
+    #[clap(long)]
+    kernel_flows: bool,
+
+    /// 把流记录导出到 NetFlow/IPFIX 采集器（UDP），如 ipfix://collector:4739、netflow9://10.0.0.1:2055、
+    /// netflow5://10.0.0.1；不带 --mode flows 时逐包输出照常，流记录只发送到采集器
+    #[clap(long)]
+    export: Option<ExportTarget>,
+
+    /// 显示 payload 的最大字节数（用于 hex/text 模式）
     #[clap(long, default_value = "128")]
     payload_bytes: usize,
 
-    /// 捕获完整 payload（512字节），忽略 --payload-bytes 设置
+    /// 显示完整捕获的 payload（受 --snaplen 限制），忽略 --payload-bytes 设置
     #[clap(long)]
     payload_full: bool,
 
+    /// 每个包最多捕获的字节数（从以太网头开始，最大 1536）
+    #[clap(short, long, default_value_t = DEFAULT_CAPTURE_SIZE)]
+    snaplen: usize,
+
     /// 分页显示，每页显示的行数（用于 hex 模式）
     #[clap(long, default_value = "0")]
     page_lines: usize,
 
+    /// 将匹配的数据包写入 pcapng 文件（可用 Wireshark 打开）
+    #[clap(short, long)]
+    write: Option<PathBuf>,
+
+    /// 离线模式：读取 pcap/pcapng 文件而不加载 XDP 程序
+    #[clap(short, long)]
+    read: Option<PathBuf>,
+
+    /// 每隔 N 秒输出一次捕获统计（收到/匹配/上送/丢弃/丢失），0 表示只在退出时输出
+    #[clap(long, default_value = "10")]
+    stats_interval: u64,
+
+    /// 退出时的统计汇总格式: text (表格，输出到 stderr) 或 json (一行 JSON，输出到 stdout)
+    #[clap(long, default_value = "text")]
+    summary: String,
+
+    /// 在该地址上提供 Prometheus 指标（/metrics），如 127.0.0.1:9100
+    #[clap(long)]
+    metrics_listen: Option<SocketAddr>,
+
     /// 显示调试信息
     #[clap(long)]
     debug: bool,
 }
 
-#[derive(Debug, Clone)]
-struct Filter {
-    protocol: Option<u8>,
-    src_ip: Option<u32>,
-    dst_ip: Option<u32>,
-    src_port: Option<u16>,
-    dst_port: Option<u16>,
-}
-
-impl Filter {
-    fn from_opt(opt: &Opt) -> Self {
-        let protocol = match opt.protocol.to_lowercase().as_str() {
+impl Opt {
+    /// 命令行上的过滤参数
+    fn filter_spec(&self) -> FilterSpec {
+        let protocol = match self.protocol.to_lowercase().as_str() {
             "tcp" => Some(6),
             "udp" => Some(17),
             "icmp" => Some(1),
-            "all" | _ => None,
+            "icmp6" | "icmpv6" => Some(58),
+            _ => None,
         };
 
-        let src_ip = opt.src_ip.as_ref().and_then(|ip| {
-            ip.parse::<Ipv4Addr>()
-                .ok()
-                .map(|addr| u32::to_be(addr.into())) // 转换为网络字节序
-        });
-
-        let dst_ip = opt.dst_ip.as_ref().and_then(|ip| {
-            ip.parse::<Ipv4Addr>()
-                .ok()
-                .map(|addr| u32::to_be(addr.into())) // 转换为网络字节序
-        });
-
-        Filter {
+        FilterSpec {
             protocol,
-            src_ip,
-            dst_ip,
-            src_port: opt.src_port.map(|p| p.to_be()), // 转换为网络字节序
-            dst_port: opt.dst_port.map(|p| p.to_be()), // 转换为网络字节序
-        }
-    }
-
-    fn matches(&self, event: &NetworkEvent) -> bool {
-        if let Some(proto) = self.protocol {
-            if event.protocol != proto {
-                return false;
-            }
+            src_ip: self.src_ip,
+            dst_ip: self.dst_ip,
+            src_nets: self.src_net.clone(),
+            dst_nets: self.dst_net.clone(),
+            src_ports: self.src_port.clone(),
+            dst_ports: self.dst_port.clone(),
+            ports: self.port.clone(),
+            vlan: self.vlan,
+            expression: self.filter.clone(),
         }
-
-        if let Some(src_ip) = self.src_ip {
-            if event.src_ip != src_ip {
-                return false;
-            }
-        }
-
-        if let Some(dst_ip) = self.dst_ip {
-            if event.dst_ip != dst_ip {
-                return false;
-            }
-        }
-
-        if let Some(src_port) = self.src_port {
-            if event.src_port != src_port {
-                return false;
-            }
-        }
-
-        if let Some(dst_port) = self.dst_port {
-            if event.dst_port != dst_port {
-                return false;
-            }
-        }
-
-        true
     }
 }
 
-fn format_ip(ip: u32) -> String {
-    // IP 地址在网络上是大端序，需要转换为主机字节序
-    let ip = u32::from_be(ip);
-    let a = (ip >> 24) as u8;
-    let b = (ip >> 16) as u8;
-    let c = (ip >> 8) as u8;
-    let d = ip as u8;
-    format!("{}.{}.{}.{}", a, b, c, d)
+fn format_nets(nets: &[IpNet]) -> String {
+    nets.iter().map(|net| net.to_string()).collect::<Vec<_>>().join(", ")
 }
 
-fn format_protocol(protocol: u8) -> &'static str {
-    match protocol {
-        6 => "TCP",
-        17 => "UDP",
-        1 => "ICMP",
-        _ => "UNKNOWN",
-    }
+/// 把通道中的文本行或流记录写到 stdout，发送端全部关闭后任务结束
+fn spawn_printer<T: std::fmt::Display + Send + 'static>(mut rx: mpsc::UnboundedReceiver<T>) -> task::JoinHandle<()> {
+    task::spawn(async move {
+        while let Some(line) = rx.recv().await {
+            println!("{}", line);
+        }
+    })
 }
 
-fn format_event(event: &NetworkEvent) -> String {
-    let proto = format_protocol(event.protocol);
-    let src_ip = format_ip(event.src_ip);
-    let dst_ip = format_ip(event.dst_ip);
-
-    match event.protocol {
-        6 | 17 => {
-            format!(
-                "{} {}:{} -> {}:{} ({}b)",
-                proto,
-                src_ip,
-                u16::from_be(event.src_port),
-                dst_ip,
-                u16::from_be(event.dst_port),
-                event.packet_size
-            )
+/// 等待输出任务写完剩余内容；调用前必须先丢弃所有发送端
+async fn drain_printers(printers: Vec<task::JoinHandle<()>>) {
+    for printer in printers {
+        if let Err(e) = printer.await {
+            warn!("输出任务异常退出: {}", e);
         }
-        1 => {
-            format!(
-                "{} {} -> {} ({}b)",
-                proto, src_ip, dst_ip, event.packet_size
-            )
-        }
-        _ => format!("{} {} -> {} ({}b)", proto, src_ip, dst_ip, event.packet_size),
     }
 }
 
-// ========== 显示模式相关函数 ==========
-
-/// 解析显示模式
-fn parse_display_mode(mode: &str) -> DisplayMode {
-    match mode.to_lowercase().as_str() {
-        "hex" => DisplayMode::Hex,
-        "text" => DisplayMode::Text,
-        "protocol" => DisplayMode::Protocol,
-        "json" => DisplayMode::Json,
-        "basic" | _ => DisplayMode::Basic,
+/// 输出退出汇总：文本表格写到 stderr，JSON 写到 stdout 方便脚本读取
+fn print_summary(summary: &Summary, json: bool) {
+    if json {
+        println!("{}", summary.to_json());
+    } else {
+        eprintln!("{}", summary.to_table());
     }
 }
 
-/// 十六进制转储
-fn format_hex_dump(payload: &[u8], bytes_to_show: usize) -> String {
-    let mut output = String::new();
-    let bytes_to_show = core::cmp::min(bytes_to_show, payload.len());
-
-    for (i, chunk) in payload[..bytes_to_show].chunks(16).enumerate() {
-        let offset = i * 16;
-        output.push_str(&format!("{:04x}: ", offset));
-
-        // 十六进制部分
-        for (j, byte) in chunk.iter().enumerate() {
-            output.push_str(&format!("{:02x} ", byte));
-            if j == 7 {
-                output.push(' ');
-            }
-        }
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let opt = Opt::parse();
 
-        // 填充空格
-        for j in chunk.len()..16 {
-            output.push_str("   ");
-            if j == 7 {
-                output.push(' ');
-            }
-        }
+    env_logger::Builder::from_default_env()
+        .filter_level(log::LevelFilter::Info)
+        .init();
 
-        output.push_str("  ");
+    let filter = Filter::new(opt.filter_spec()).context("过滤表达式解析失败")?;
+    let display_mode = parse_display_mode(&opt.mode);
+    // 流模式、内核流统计和流导出都需要按五元组聚合
+    let aggregate = display_mode == DisplayMode::Flows || opt.kernel_flows || opt.export.is_some();
 
-        // ASCII 部分
-        for byte in chunk {
-            if byte.is_ascii_graphic() || *byte == b' ' {
-                output.push(*byte as char);
-            } else {
-                output.push('.');
-            }
-        }
-        output.push('\n');
+    info!("═══════════════════════════════════════");
+    info!("     Aya eBPF 网络流量监控工具");
+    info!("═══════════════════════════════════════");
+    if let Some(ref path) = opt.read {
+        info!("离线文件: {}", path.display());
+        info!("架构: pcap 文件 → 用户空间解析 → Rust 过滤");
+    } else {
+        info!("网卡: {}", opt.iface.join(", "));
+        info!("挂载点: {}", opt.hook);
+        let transport_name = if opt.transport.eq_ignore_ascii_case("ringbuf") {
+            "Ring Buffer"
+        } else {
+            "Perf Event"
+        };
+        info!("架构: eBPF (内核过滤) → {} → 用户空间 Rust 处理", transport_name);
     }
-
-    output
-}
-
-/// 十六进制转储（带分页）
-fn format_hex_dump_paged(payload: &[u8], bytes_to_show: usize, page_lines: usize) -> String {
-    let mut output = String::new();
-    let bytes_to_show = core::cmp::min(bytes_to_show, payload.len());
-
-    if page_lines == 0 {
-        // 不分页，显示全部
-        return format_hex_dump(payload, bytes_to_show);
+    info!("");
+    if opt.kernel_flows {
+        info!("显示模式: flows（内核流统计，不上送逐包事件）");
+    } else {
+        info!("显示模式: {}", opt.mode);
     }
-
-    let total_lines = (bytes_to_show + 15) / 16;
-    let pages = (total_lines + page_lines - 1) / page_lines;
-
-    for page in 0..pages {
-        let start_line = page * page_lines;
-        let end_line = core::cmp::min((page + 1) * page_lines, total_lines);
-        let start_byte = start_line * 16;
-        let end_byte = core::cmp::min(end_line * 16, bytes_to_show);
-
-        output.push_str(&format!("--- 页 {} ({}-{} 字节) ---\n", page + 1, start_byte, end_byte - 1));
-
-        for line in start_line..end_line {
-            let line_start = line * 16;
-            let line_end = core::cmp::min(line_start + 16, bytes_to_show);
-            let chunk = &payload[line_start..line_end];
-
-            output.push_str(&format!("{:04x}: ", line_start));
-
-            // 十六进制部分
-            for (j, byte) in chunk.iter().enumerate() {
-                output.push_str(&format!("{:02x} ", byte));
-                if j == 7 {
-                    output.push(' ');
-                }
-            }
-
-            // 填充空格
-            for j in chunk.len()..16 {
-                output.push_str("   ");
-                if j == 7 {
-                    output.push(' ');
-                }
-            }
-
-            output.push_str("  ");
-
-            // ASCII 部分
-            for byte in chunk {
-                if byte.is_ascii_graphic() || *byte == b' ' {
-                    output.push(*byte as char);
-                } else {
-                    output.push('.');
-                }
-            }
-            output.push('\n');
+    info!("过滤配置:");
+    info!("  协议: {}", opt.protocol);
+    if let Some(ref ip) = opt.src_ip {
+        info!("  源 IP: {}", ip);
+    }
+    if let Some(ref ip) = opt.dst_ip {
+        info!("  目标 IP: {}", ip);
+    }
+    if !opt.src_net.is_empty() {
+        info!("  源网段: {}", format_nets(&opt.src_net));
+    }
+    if !opt.dst_net.is_empty() {
+        info!("  目标网段: {}", format_nets(&opt.dst_net));
+    }
+    if !opt.src_port.is_empty() {
+        info!("  源端口: {}", format_ports(&opt.src_port));
+    }
+    if !opt.dst_port.is_empty() {
+        info!("  目标端口: {}", format_ports(&opt.dst_port));
+    }
+    if !opt.port.is_empty() {
+        info!("  端口: {}", format_ports(&opt.port));
+    }
+    if let Some(vlan) = opt.vlan {
+        info!("  VLAN: {}", vlan);
+    }
+    if let Some(ref text) = opt.filter {
+        info!("  表达式: {}", text);
+    }
+    if aggregate {
+        info!("  流超时: 空闲 {} 秒, 活动 {} 秒", opt.flow_idle_timeout, opt.flow_active_timeout);
+    } else if opt.mode != "basic" {
+        if opt.payload_full {
+            info!("  Payload 显示: 完整 (捕获长度 {} 字节，含协议头)", opt.snaplen);
+        } else {
+            info!("  Payload 显示: {} 字节", opt.payload_bytes);
         }
-
-        if page < pages - 1 {
-            output.push('\n');
+        if opt.page_lines > 0 {
+            info!("  分页显示: 每页 {} 行", opt.page_lines);
         }
     }
-
-    output
-}
-
-/// 检测并显示文本内容
-fn format_text_payload(payload: &[u8]) -> String {
-    // 检查是否主要是可打印 ASCII
-    let printable_count = payload.iter()
-        .filter(|&&b| b.is_ascii_graphic() || b.is_ascii_whitespace())
-        .count();
-
-    let ratio = printable_count as f64 / payload.len() as f64;
-
-    // 如果超过 80% 是可打印字符，显示为文本
-    if ratio > 0.8 && payload.len() > 0 {
-        let text = String::from_utf8_lossy(payload);
-        return text.lines()
-            .take(10) // 最多显示 10 行
-            .map(|line| format!("  {}", line))
-            .collect::<Vec<_>>()
-            .join("\n");
+    if let Some(ref path) = opt.write {
+        info!("写入 pcapng: {}", path.display());
     }
+    if let Some(ref target) = opt.export {
+        info!("导出流记录: {}", target);
+    }
+    info!("═══════════════════════════════════════");
+    info!("");
 
-    // 否则显示十六进制
-    format_hex_dump(payload, payload.len())
-}
-
-/// 解析 HTTP 请求
-fn parse_http(payload: &[u8]) -> Option<String> {
-    let text = String::from_utf8_lossy(payload);
-    let lines: Vec<&str> = text.lines().collect();
-
-    if lines.is_empty() {
-        return None;
+    if opt.snaplen == 0 || opt.snaplen > MAX_CAPTURE_SIZE {
+        anyhow::bail!("--snaplen 必须在 1 到 {} 之间", MAX_CAPTURE_SIZE);
     }
 
-    let first_line = lines[0].trim();
+    let transport = match opt.transport.to_lowercase().as_str() {
+        "perf" => TRANSPORT_PERF,
+        "ringbuf" => TRANSPORT_RINGBUF,
+        other => anyhow::bail!("未知的传输方式: {}（可选 perf, ringbuf）", other),
+    };
 
-    // 检查是否是 HTTP 请求
-    if first_line.starts_with("GET ") || first_line.starts_with("POST ") ||
-       first_line.starts_with("PUT ") || first_line.starts_with("DELETE ") ||
-       first_line.starts_with("HEAD ") || first_line.starts_with("OPTIONS ") ||
-       first_line.starts_with("PATCH ") {
-        let mut output = String::from("HTTP Request:\n");
-        output.push_str(&format!("  {}\n", first_line));
+    // XDP 只能看到收到的包；TC clsact 的 ingress/egress 两个方向各有一个分类器程序
+    let hook = match opt.hook.to_lowercase().as_str() {
+        "xdp" => Hook::Xdp,
+        "tc-ingress" => Hook::TcIngress,
+        "tc-egress" => Hook::TcEgress,
+        "tc-both" => Hook::TcBoth,
+        other => anyhow::bail!("未知的挂载点: {}（可选 xdp, tc-ingress, tc-egress, tc-both）", other),
+    };
 
-        // 解析头部
-        for line in lines.iter().skip(1).take(20) {
-            let line = line.trim();
-            if line.is_empty() {
-                break;
-            }
-            output.push_str(&format!("  {}\n", line));
-        }
+    let summary_json = match opt.summary.to_lowercase().as_str() {
+        "text" => false,
+        "json" => true,
+        other => anyhow::bail!("未知的汇总格式: {}（可选 text, json）", other),
+    };
 
-        return Some(output);
+    if aggregate && (opt.flow_idle_timeout == 0 || opt.flow_active_timeout == 0) {
+        anyhow::bail!("--flow-idle-timeout 和 --flow-active-timeout 必须大于 0");
     }
-
-    // 检查是否是 HTTP 响应
-    if first_line.starts_with("HTTP/") {
-        let mut output = String::from("HTTP Response:\n");
-        output.push_str(&format!("  {}\n", first_line));
-
-        for line in lines.iter().skip(1).take(20) {
-            let line = line.trim();
-            if line.is_empty() {
-                break;
-            }
-            output.push_str(&format!("  {}\n", line));
+    if opt.kernel_flows {
+        if opt.read.is_some() {
+            anyhow::bail!("--kernel-flows 只能用于实时捕获");
+        }
+        if opt.write.is_some() {
+            anyhow::bail!("--kernel-flows 不上送数据包，不能与 --write 同时使用");
         }
-
-        return Some(output);
     }
-
-    None
-}
-
-/// 解析 DNS 查询/响应
-fn parse_dns(payload: &[u8]) -> Option<String> {
-    if payload.len() < 12 {
-        return None;
+    if opt.metrics_listen.is_some() && opt.read.is_some() {
+        anyhow::bail!("--metrics-listen 只能用于实时捕获");
     }
 
-    let flags = u16::from_be_bytes([payload[2], payload[3]]);
-    let is_response = (flags & 0x8000) != 0;
-    let question_count = u16::from_be_bytes([payload[4], payload[5]]) as usize;
-
-    let mut output = String::new();
-    output.push_str(if is_response { "DNS Response" } else { "DNS Query" });
-    output.push_str(&format!(" ({} questions)\n", question_count));
+    // 协议解析器（所有读取任务共享）
+    let registry = Arc::new(Registry::builtin());
 
-    // 简单的域名解析（跳过复杂的压缩指针处理）
-    let mut pos = 12;
-    for i in 0..question_count {
-        if pos >= payload.len() {
-            break;
-        }
-
-        output.push_str(&format!("  Query {}: ", i + 1));
-
-        // 解析域名
-        let mut domain = String::new();
-        loop {
-            if pos >= payload.len() {
-                break;
-            }
-            let len = payload[pos] as usize;
-            pos += 1;
-            if len == 0 {
-                break;
-            }
-            if pos + len > payload.len() {
-                break;
-            }
-            if !domain.is_empty() {
-                domain.push('.');
-            }
-            let label = String::from_utf8_lossy(&payload[pos..pos + len]);
-            domain.push_str(&label);
-            pos += len;
-        }
+    // 流模式或导出时的流表（所有读取任务共享）；--kernel-flows 时流表在内核中
+    let flows: Option<SharedFlowTable> = if aggregate && !opt.kernel_flows {
+        Some(Arc::new(Mutex::new(FlowTable::new(opt.flow_idle_timeout, opt.flow_active_timeout))))
+    } else {
+        None
+    };
 
-        output.push_str(&domain);
+    // 事件文本和流记录由单独的任务写到 stdout，退出时先等它们写完再输出汇总
+    let mut printers = Vec::new();
 
-        // 跳过 QTYPE 和 QCLASS
-        if pos + 4 > payload.len() {
-            break;
-        }
-        let qtype = u16::from_be_bytes([payload[pos], payload[pos + 1]]);
-        pos += 4;
-
-        let type_str = match qtype {
-            1 => "A",
-            2 => "NS",
-            5 => "CNAME",
-            28 => "AAAA",
-            _ => "UNKNOWN",
+    // 流记录的去向：流模式输出到终端（--kernel-flows 只导出时除外），--export 时发送到采集器
+    let flow_sink: Option<SharedFlowSink> = if aggregate {
+        let exporter = match opt.export {
+            Some(ref target) => Some(
+                FlowExporter::new(target.clone()).context(format!("创建导出套接字失败: {}", target))?,
+            ),
+            None => None,
         };
-        output.push_str(&format!(" (type: {})\n", type_str));
-    }
-
-    Some(output)
-}
+        let records = if display_mode == DisplayMode::Flows || (opt.kernel_flows && exporter.is_none()) {
+            let (tx, rx) = mpsc::unbounded_channel();
+            printers.push(spawn_printer(rx));
+            Some(tx)
+        } else {
+            None
+        };
+        Some(Arc::new(Mutex::new(FlowSink::new(records, exporter))))
+    } else {
+        None
+    };
 
-/// 协议解析
-fn format_protocol_parse(event: &NetworkEvent) -> String {
-    let proto = format_protocol(event.protocol);
-    let src_ip = format_ip(event.src_ip);
-    let dst_ip = format_ip(event.dst_ip);
-
-    let header = match event.protocol {
-        6 | 17 => {
-            format!(
-                "{} {}:{} -> {}:{} ({}b)\n",
-                proto,
-                src_ip,
-                u16::from_be(event.src_port),
-                dst_ip,
-                u16::from_be(event.dst_port),
-                event.packet_size
-            )
-        }
-        _ => {
-            format!(
-                "{} {} -> {} ({}b)\n",
-                proto, src_ip, dst_ip, event.packet_size
-            )
+    // 打开 pcapng 输出文件（所有 CPU 任务共享）
+    let pcap_writer: Option<SharedPcapWriter> = match opt.write {
+        Some(ref path) => {
+            let file = File::create(path)
+                .context(format!("创建 pcapng 文件失败: {}", path.display()))?;
+            // 离线读取时写入完整的原始帧，不受 --snaplen 限制，接口描述块中的 snaplen 写 0（不限制）
+            let snaplen = if opt.read.is_some() { 0 } else { opt.snaplen as u32 };
+            let writer = PcapngWriter::new(BufWriter::new(file), snaplen)?;
+            Some(Arc::new(Mutex::new(writer)))
         }
+        None => None,
     };
 
-    let payload = &event.payload[..event.payload_len as usize];
+    // 匹配的事件按显示模式输出到终端；流模式只输出流记录
+    let output = if display_mode == DisplayMode::Flows {
+        Output::Discard
+    } else {
+        let (output, lines) = Output::print(Formatter {
+            mode: display_mode,
+            payload_bytes: opt.payload_bytes,
+            payload_full: opt.payload_full,
+            page_lines: opt.page_lines,
+        });
+        printers.push(spawn_printer(lines));
+        output
+    };
+    let new_handler = |metrics: Option<ReaderMetrics>| {
+        EventHandler::new(filter.clone(), registry.clone(), output.clone())
+            .pcap_writer(pcap_writer.clone())
+            .flows(flows.clone())
+            .metrics(metrics)
+            .debug(opt.debug)
+    };
 
-    // 尝试解析协议
-    if event.protocol == 6 && (u16::from_be(event.dst_port) == 80 || u16::from_be(event.src_port) == 80) {
-        // HTTP
-        if let Some(http) = parse_http(payload) {
-            return format!("{}{}", header, http);
+    // 离线模式不需要 root 权限，也不加载 eBPF 程序
+    if let Some(ref path) = opt.read {
+        let started = std::time::Instant::now();
+        let mut handler = new_handler(None);
+        capture::run_offline(path, &mut handler, flow_sink.as_ref())?;
+        if let (Some(flows), Some(sink)) = (flows, &flow_sink) {
+            sink.lock().unwrap().emit(&flows.lock().unwrap().flush());
         }
-    }
-
-    if event.protocol == 17 && (u16::from_be(event.dst_port) == 53 || u16::from_be(event.src_port) == 53) {
-        // DNS
-        if let Some(dns) = parse_dns(payload) {
-            return format!("{}{}", header, dns);
+        if let Some(writer) = pcap_writer {
+            writer.lock().unwrap().flush().context("刷新 pcapng 文件失败")?;
         }
+
+        let summary = Summary::new(vec![handler.summary("file")], started.elapsed(), None);
+        drop((handler, output, flow_sink));
+        drain_printers(printers).await;
+        print_summary(&summary, summary_json);
+        return Ok(());
     }
 
-    // 无法解析，显示文本或十六进制
-    format!("{}{}", header, format_text_payload(payload))
-}
+    let options = CaptureOptions {
+        ifaces: opt.iface.clone(),
+        hook,
+        xdp_skb_mode: opt.xdp_mode == "skb",
+        transport,
+        snaplen: opt.snaplen,
+        kernel_flows: opt.kernel_flows,
+    };
+    let mut capture = Capture::open(&options, &filter)?;
 
-/// JSON 输出的结构体
-#[derive(Serialize)]
-struct JsonEvent {
-    timestamp: i64,
-    protocol: String,
-    src_ip: String,
-    dst_ip: String,
-    src_port: u16,
-    dst_port: u16,
-    packet_size: u32,
-    tcp_flags: u8,
-    payload_len: usize,
-    payload_hex: String,
-}
+    info!("开始监控...");
+    info!("按 Ctrl-C 停止");
+    info!("");
 
-/// 转换为 JSON
-fn format_json(event: &NetworkEvent) -> String {
-    let json_event = JsonEvent {
-        timestamp: std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_secs() as i64,
-        protocol: format_protocol(event.protocol).to_string(),
-        src_ip: format_ip(event.src_ip),
-        dst_ip: format_ip(event.dst_ip),
-        src_port: u16::from_be(event.src_port),
-        dst_port: u16::from_be(event.dst_port),
-        packet_size: event.packet_size,
-        tcp_flags: event.tcp_flags,
-        payload_len: event.payload_len as usize,
-        payload_hex: {
-            let bytes = &event.payload[..event.payload_len as usize];
-            bytes.iter()
-                .map(|b| format!("{:02x}", b))
-                .collect::<Vec<_>>()
-                .join(" ")
-        },
+    // eBPF 每 CPU 计数 + perf 丢失样本计数
+    let capture_stats = capture.capture_stats();
+    let lost = capture.lost();
+
+    // Prometheus 指标：读取任务累加用户空间计数，内核计数在抓取时读取
+    let metrics = opt.metrics_listen.map(|_| Metrics::default());
+    let metrics_handle = match (opt.metrics_listen, &metrics) {
+        (Some(addr), Some(metrics)) => {
+            let kernel = KernelSource { capture_stats: capture_stats.clone(), lost: lost.clone() };
+            let handle = metrics::serve(addr, metrics.clone(), kernel)
+                .await
+                .context(format!("监听 {} 失败", addr))?;
+            info!("Prometheus 指标: http://{}/metrics", addr);
+            Some(handle)
+        }
+        _ => None,
     };
 
-    serde_json::to_string(&json_event).unwrap_or_else(|_| "{}".to_string())
-}
+    // 退出信号：读取任务收到后停止读取并返回各自的计数
+    let (shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+    let started = std::time::Instant::now();
 
-/// 根据显示模式格式化事件
-fn format_event_with_mode(
-    event: &NetworkEvent,
-    mode: DisplayMode,
-    payload_bytes: usize,
-    payload_full: bool,
-    page_lines: usize,
-) -> String {
-    // 确定 payload 显示大小
-    let effective_bytes = if payload_full {
-        event.payload_len as usize
+    let handles = capture.spawn_readers(
+        |label| new_handler(metrics.as_ref().map(|metrics| metrics.reader(label))),
+        shutdown_rx,
+    )?;
+
+    // 定期输出捕获统计
+    let stats_handle = if opt.stats_interval > 0 {
+        let capture_stats = capture_stats.clone();
+        let lost = lost.clone();
+        let period = std::time::Duration::from_secs(opt.stats_interval);
+
+        Some(task::spawn(async move {
+            let mut interval = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+            loop {
+                interval.tick().await;
+                match stats::snapshot(&capture_stats, &lost) {
+                    Ok(rows) => stats::log_stats(&rows),
+                    Err(e) => warn!("{:#}", e),
+                }
+            }
+        }))
     } else {
-        core::cmp::min(payload_bytes, event.payload_len as usize)
+        None
     };
 
-    match mode {
-        DisplayMode::Basic => format_event(event),
-        DisplayMode::Hex => {
-            let mut output = format_event(event);
-            output.push_str(&format!("\nPayload ({} bytes, 显示 {} bytes):\n", event.payload_len, effective_bytes));
-
-            // 根据是否分页选择格式化函数
-            if page_lines > 0 && effective_bytes > page_lines * 16 {
-                output.push_str(&format_hex_dump_paged(
-                    &event.payload[..event.payload_len as usize],
-                    effective_bytes,
-                    page_lines,
-                ));
-            } else {
-                output.push_str(&format_hex_dump(
-                    &event.payload[..event.payload_len as usize],
-                    effective_bytes,
-                ));
-            }
-            output
-        }
-        DisplayMode::Text => {
-            let mut output = format_event(event);
-            if event.payload_len > 0 {
-                output.push_str("\nContent:\n");
-                let bytes = &event.payload[..effective_bytes];
-                output.push_str(&format_text_payload(bytes));
+    // 流模式：每秒按墙上时间检查一次流超时，输出到期的流记录
+    let flow_handle = flows.clone().zip(flow_sink.clone()).map(|(flows, sink)| {
+        task::spawn(async move {
+            let mut interval = tokio::time::interval(std::time::Duration::from_secs(1));
+            loop {
+                interval.tick().await;
+                let records = flows.lock().unwrap().expire(flow::now_ns());
+                sink.lock().unwrap().emit(&records);
             }
-            output
-        }
-        DisplayMode::Protocol => format_protocol_parse(event),
-        DisplayMode::Json => format_json(event),
-    }
-}
+        })
+    });
 
-#[tokio::main]
-async fn main() -> anyhow::Result<()> {
-    let opt = Opt::parse();
+    // 内核流统计：每秒遍历一次 FLOW_TABLE，移出并输出到期的流
+    let kernel_flows = if opt.kernel_flows {
+        let kernel_flows = Arc::new(Mutex::new(capture.kernel_flows(opt.flow_idle_timeout, opt.flow_active_timeout)?));
 
-    env_logger::Builder::from_default_env()
-        .filter_level(log::LevelFilter::Info)
-        .init();
+        let task_flows = kernel_flows.clone();
+        let sink = flow_sink.clone().unwrap();
+        let handle = task::spawn(async move {
+            let mut interval = tokio::time::interval(std::time::Duration::from_secs(1));
+            loop {
+                interval.tick().await;
+                let records = task_flows.lock().unwrap().expire(flow::now_ns());
+                match records {
+                    Ok(records) => sink.lock().unwrap().emit(&records),
+                    Err(e) => warn!("{:#}", e),
+                }
+            }
+        });
+        Some((kernel_flows, handle))
+    } else {
+        None
+    };
 
-    let filter = Filter::from_opt(&opt);
-    let display_mode = parse_display_mode(&opt.mode);
+    // 等待 Ctrl-C
+    let ctrl_c = signal::ctrl_c();
+    ctrl_c.await?;
 
-    info!("═══════════════════════════════════════");
-    info!("     Aya eBPF 网络流量监控工具");
-    info!("═══════════════════════════════════════");
-    info!("网卡: {}", opt.iface);
-    info!("架构: eBPF (内核) → Perf Event → 用户空间 Rust 过滤");
-    info!("");
-    info!("显示模式: {}", opt.mode);
-    info!("过滤配置:");
-    info!("  协议: {}", opt.protocol);
-    if let Some(ref ip) = opt.src_ip {
-        info!("  源 IP: {}", ip);
-    }
-    if let Some(ref ip) = opt.dst_ip {
-        info!("  目标 IP: {}", ip);
+    // 通知读取任务停止，并收集它们的计数
+    let _ = shutdown_tx.send(true);
+    if let Some(handle) = stats_handle {
+        handle.abort();
     }
-    if let Some(port) = opt.src_port {
-        info!("  源端口: {}", port);
+    if let Some(handle) = flow_handle {
+        handle.abort();
+        // 等任务真正结束，释放它持有的 flow_sink
+        let _ = handle.await;
     }
-    if let Some(port) = opt.dst_port {
-        info!("  目标端口: {}", port);
+    if let Some(handle) = metrics_handle {
+        handle.abort();
     }
-    if opt.mode != "basic" {
-        if opt.payload_full {
-            info!("  Payload 显示: 完整 (192 字节)");
-        } else {
-            info!("  Payload 显示: {} 字节", opt.payload_bytes);
-        }
-        if opt.page_lines > 0 {
-            info!("  分页显示: 每页 {} 行", opt.page_lines);
+
+    let mut readers = Vec::with_capacity(handles.len());
+    for handle in handles {
+        match handle.await {
+            Ok(summary) => readers.push(summary),
+            Err(e) => warn!("读取任务异常退出: {}", e),
         }
     }
-    info!("═══════════════════════════════════════");
-    info!("");
 
-    // Bump the memlock rlimit
-    let rlim = libc::rlimit {
-        rlim_cur: libc::RLIM_INFINITY,
-        rlim_max: libc::RLIM_INFINITY,
-    };
-    let ret = unsafe { libc::setrlimit(libc::RLIMIT_MEMLOCK, &rlim) };
-    if ret != 0 {
-        debug!("remove limit on locked memory failed, ret is: {ret}");
+    // 读取任务都已停止，输出流表中剩余的流
+    if let (Some(flows), Some(sink)) = (flows, &flow_sink) {
+        sink.lock().unwrap().emit(&flows.lock().unwrap().flush());
+    }
+    if let (Some((kernel_flows, handle)), Some(sink)) = (kernel_flows, &flow_sink) {
+        handle.abort();
+        let _ = handle.await;
+        let records = kernel_flows.lock().unwrap().flush()?;
+        sink.lock().unwrap().emit(&records);
     }
 
-    let mut ebpf = Ebpf::load(aya::include_bytes_aligned!(concat!(
-        env!("OUT_DIR"),
-        "/aya-network-monitor"
-    )))?;
+    if let Some(writer) = pcap_writer {
+        writer.lock().unwrap().flush().context("刷新 pcapng 文件失败")?;
+    }
 
-    let program: &mut Xdp = ebpf.program_mut("aya_network_monitor").unwrap().try_into()?;
-    program.load()?;
+    drop((output, flow_sink));
+    drain_printers(printers).await;
 
-    // 根据 XDP 模式选择标志
-    let xdp_flags = match opt.xdp_mode.as_str() {
-        "skb" => XdpFlags::SKB_MODE,
-        "drv" | _ => XdpFlags::default(),
-    };
+    // 退出前输出最终统计，判断本次捕获是否完整
+    eprintln!();
+    let cpu_stats = capture.stats()?;
+    stats::log_stats(&cpu_stats);
 
-    program.attach(&opt.iface, xdp_flags)
-        .context(format!("failed to attach the XDP program with {} mode - try the other mode (drv/skb)", opt.xdp_mode))?;
+    let kernel = KernelSummary::from(stats::total(&cpu_stats));
+    print_summary(&Summary::new(readers, started.elapsed(), Some(kernel)), summary_json);
 
-    info!("开始监控...");
-    info!("按 Ctrl-C 停止");
-    info!("");
+    Ok(())
+}
 
-    // 获取 Perf Event Array
-    let mut perf_array = PerfEventArray::try_from(ebpf.take_map("EVENTS").unwrap())?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aya_network_monitor::{
+        decode::decode_frame,
+        format::{format_direction, format_event},
+        pcap::PcapReader,
+    };
+    use aya_network_monitor_common::{DIRECTION_EGRESS, DIRECTION_INGRESS};
+
+    /// 以太网 + IPv4 + TCP/UDP 帧
+    fn ipv4_frame(protocol: u8, src: [u8; 4], dst: [u8; 4], src_port: u16, dst_port: u16) -> Vec<u8> {
+        let mut frame = vec![0u8; 12];
+        frame.extend_from_slice(&[0x08, 0x00]);
+        frame.extend_from_slice(&[0x45, 0, 0, 0, 0, 0, 0, 0, 64, protocol, 0, 0]);
+        frame.extend_from_slice(&src);
+        frame.extend_from_slice(&dst);
+        frame.extend_from_slice(&src_port.to_be_bytes());
+        frame.extend_from_slice(&dst_port.to_be_bytes());
+        // TCP 头补齐到 20 字节（数据偏移 5），UDP 头补齐到 8 字节
+        if protocol == 6 {
+            frame.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 0, 0x50, 0x02, 0, 0, 0, 0, 0, 0]);
+        } else {
+            frame.extend_from_slice(&[0, 0, 0, 0]);
+        }
+        frame
+    }
 
-    // 为每个 CPU 创建处理任务
-    let online_cpus = online_cpus().map_err(|(_, e)| e).context("获取在线 CPU 失败")?;
+    /// 把帧写入内存中的 pcapng 文件，再读出、解析、过滤并格式化
+    fn filter_pcap(args: &[&str], frames: &[Vec<u8>]) -> Vec<String> {
+        let mut file = Vec::new();
+        {
+            let mut writer = PcapngWriter::new(&mut file, 0).unwrap();
+            for (i, frame) in frames.iter().enumerate() {
+                writer.write_packet("eth0", i as u64 * 1_000_000, frame, frame.len() as u32 + 100, DIRECTION_INGRESS).unwrap();
+            }
+        }
 
-    let mut handles = vec![];
+        let opt = Opt::parse_from(std::iter::once("aya-network-monitor").chain(args.iter().copied()));
+        let filter = Filter::new(opt.filter_spec()).unwrap();
 
-    for cpu_id in online_cpus {
-        let buf = perf_array.open(cpu_id, None)?;
+        let mut reader = PcapReader::new(file.as_slice()).unwrap();
+        let mut lines = Vec::new();
+        while let Some(packet) = reader.next_packet().unwrap() {
+            if let Some(event) = decode_frame(&packet) {
+                if filter.matches(&event) {
+                    lines.push(format_event(&event.header));
+                }
+            }
+        }
+        lines
+    }
 
-        let mut buf = tokio::io::unix::AsyncFd::with_interest(
-            buf,
-            tokio::io::Interest::READABLE,
-        )?;
+    fn sample_frames() -> Vec<Vec<u8>> {
+        vec![
+            ipv4_frame(6, [10, 0, 0, 1], [10, 0, 0, 2], 40000, 443),
+            ipv4_frame(17, [10, 0, 0, 1], [10, 0, 0, 53], 5353, 53),
+            ipv4_frame(6, [10, 0, 0, 3], [10, 0, 0, 2], 40001, 80),
+            // ARP 帧不会被解析
+            [vec![0u8; 12], vec![0x08, 0x06], vec![0u8; 28]].concat(),
+        ]
+    }
 
-        let filter_clone = filter.clone();
-        let display_mode_clone = display_mode;
-        let payload_bytes_clone = opt.payload_bytes;
-        let payload_full_clone = opt.payload_full;
-        let page_lines_clone = opt.page_lines;
-        let opt_clone = opt.clone(); // Clone for debug use
+    #[test]
+    fn offline_without_filter_shows_ip_packets() {
+        assert_eq!(
+            filter_pcap(&[], &sample_frames()),
+            [
+                "TCP 10.0.0.1:40000 -> 10.0.0.2:443 (154b)",
+                "UDP 10.0.0.1:5353 -> 10.0.0.53:53 (142b)",
+                "TCP 10.0.0.3:40001 -> 10.0.0.2:80 (154b)",
+            ]
+        );
+    }
 
-        let handle = task::spawn(async move {
-            let mut counters = std::collections::HashMap::new();
-            let mut total = 0usize;
-            let mut filtered = 0usize;
-            let mut buffers = (0..10)
-                .map(|_| BytesMut::with_capacity(core::mem::size_of::<NetworkEvent>()))
-                .collect::<Vec<_>>();
+    #[test]
+    fn offline_keeps_direction_and_interface() {
+        let frame = ipv4_frame(6, [10, 0, 0, 2], [10, 0, 0, 1], 443, 40000);
+        let mut file = Vec::new();
+        {
+            let mut writer = PcapngWriter::new(&mut file, 0).unwrap();
+            writer.write_packet("eth0", 0, &frame, 154, DIRECTION_INGRESS).unwrap();
+            writer.write_packet("eth1", 0, &frame, 154, DIRECTION_EGRESS).unwrap();
+        }
 
-            loop {
-                match buf.readable_mut().await {
-                    Ok(mut guard) => {
-                        let events = guard.get_inner_mut().read_events(&mut buffers);
-
-                        match events {
-                            Ok(events) => {
-                                for buf in buffers.iter_mut().take(events.read) {
-                                    if buf.len() >= core::mem::size_of::<NetworkEvent>() {
-                                        let ptr = buf.as_ptr() as *const NetworkEvent;
-                                        let network_event = unsafe {
-                                            ptr.read_unaligned()
-                                        };
-
-                                        total += 1;
-
-                                        // 调试输出（如果启用）
-                                        if opt_clone.debug {
-                                            eprintln!("[DEBUG] Total events: {}", total);
-                                            eprintln!("[DEBUG] Event: {}:{} -> {}:{} ({}b)",
-                                                format_ip(network_event.src_ip),
-                                                u16::from_be(network_event.src_port),
-                                                format_ip(network_event.dst_ip),
-                                                u16::from_be(network_event.dst_port),
-                                                network_event.packet_size
-                                            );
-                                            eprintln!("[DEBUG] Filter: src_port={:?}, dst_port={:?}",
-                                                filter_clone.src_port, filter_clone.dst_port);
-                                        }
-
-                                        // 应用过滤
-                                        if filter_clone.matches(&network_event) {
-                                            filtered += 1;
-
-                                            // 根据显示模式格式化输出
-                                            let output = format_event_with_mode(
-                                                &network_event,
-                                                display_mode_clone,
-                                                payload_bytes_clone,
-                                                payload_full_clone,
-                                                page_lines_clone
-                                            );
-                                            println!("{}", output);
-
-                                            // 统计
-                                            *counters.entry(network_event.protocol).or_insert(0) += 1;
-                                        }
-                                    }
-                                }
-
-                                if events.read != buffers.len() {
-                                    guard.clear_ready();
-                                }
-                            }
-                            Err(e) => {
-                                warn!("CPU {}: 读取事件失败: {}", cpu_id, e);
-                                guard.clear_ready();
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        warn!("CPU {}: 等待可读失败: {}", cpu_id, e);
-                        break;
-                    }
-                }
-            }
+        let mut reader = PcapReader::new(file.as_slice()).unwrap();
+        let mut events = Vec::new();
+        while let Some(packet) = reader.next_packet().unwrap() {
+            events.push(decode_frame(&packet).unwrap());
+        }
 
-            (cpu_id, total, filtered, counters)
-        });
+        // 发出的包对端地址仍在左侧
+        let lines: Vec<_> = events.iter().map(|event| format!("{} {}", event.iface, format_event(&event.header))).collect();
+        assert_eq!(
+            lines,
+            [
+                "eth0 TCP 10.0.0.2:443 -> 10.0.0.1:40000 (154b)",
+                "eth1 TCP 10.0.0.1:40000 <- 10.0.0.2:443 (154b)",
+            ]
+        );
+        assert_eq!(format_direction(events[1].header.direction), "egress");
+    }
 
-        handles.push(handle);
+    #[test]
+    fn offline_filter_by_protocol_and_port() {
+        assert_eq!(
+            filter_pcap(&["--protocol", "tcp", "--dst-port", "443"], &sample_frames()),
+            ["TCP 10.0.0.1:40000 -> 10.0.0.2:443 (154b)"]
+        );
+        assert_eq!(
+            filter_pcap(&["--protocol", "udp"], &sample_frames()),
+            ["UDP 10.0.0.1:5353 -> 10.0.0.53:53 (142b)"]
+        );
     }
 
-    // 等待 Ctrl-C
-    let ctrl_c = signal::ctrl_c();
-    ctrl_c.await?;
+    #[test]
+    fn offline_filter_by_port_set() {
+        assert_eq!(
+            filter_pcap(&["--dst-port", "53,400-450"], &sample_frames()),
+            [
+                "TCP 10.0.0.1:40000 -> 10.0.0.2:443 (154b)",
+                "UDP 10.0.0.1:5353 -> 10.0.0.53:53 (142b)",
+            ]
+        );
+        // --port 匹配源端口或目标端口
+        assert_eq!(
+            filter_pcap(&["--port", "40001-40010"], &sample_frames()),
+            ["TCP 10.0.0.3:40001 -> 10.0.0.2:80 (154b)"]
+        );
+    }
 
-    // 取消所有任务
-    for handle in handles {
-        handle.abort();
+    #[test]
+    fn offline_filter_expression() {
+        assert_eq!(
+            filter_pcap(&["--filter", "tcp dst port 80 or udp"], &sample_frames()),
+            [
+                "UDP 10.0.0.1:5353 -> 10.0.0.53:53 (142b)",
+                "TCP 10.0.0.3:40001 -> 10.0.0.2:80 (154b)",
+            ]
+        );
+        // 单项参数与表达式做 and
+        assert_eq!(
+            filter_pcap(&["--protocol", "tcp", "--bpf-filter", "not host 10.0.0.3"], &sample_frames()),
+            ["TCP 10.0.0.1:40000 -> 10.0.0.2:443 (154b)"]
+        );
     }
 
-    println!("\n退出...");
+    #[test]
+    fn offline_filter_by_address() {
+        assert_eq!(
+            filter_pcap(&["--dst-ip", "10.0.0.2", "--src-ip", "10.0.0.3"], &sample_frames()),
+            ["TCP 10.0.0.3:40001 -> 10.0.0.2:80 (154b)"]
+        );
+        assert!(filter_pcap(&["--vlan", "100"], &sample_frames()).is_empty());
+    }
 
-    Ok(())
+    #[test]
+    fn offline_filter_by_network() {
+        assert_eq!(
+            filter_pcap(&["--dst-net", "10.0.0.0/30"], &sample_frames()),
+            [
+                "TCP 10.0.0.1:40000 -> 10.0.0.2:443 (154b)",
+                "TCP 10.0.0.3:40001 -> 10.0.0.2:80 (154b)",
+            ]
+        );
+        assert_eq!(
+            filter_pcap(&["--src-net", "10.0.0.2/31", "--dst-net", "10.0.0.0/24"], &sample_frames()),
+            ["TCP 10.0.0.3:40001 -> 10.0.0.2:80 (154b)"]
+        );
+    }
 }
@@ -0,0 +1,362 @@
+//! Prometheus 指标
+//!
+//! `--metrics-listen 127.0.0.1:9100` 在该地址上提供 `/metrics`（Prometheus 文本格式）。
+//! 每个读取任务在自己的 `ReaderMetrics` 中累加事件数、按（协议, 网卡, 方向）分组的匹配包数，
+//! 并记录读取延迟，热路径上不与其他任务争用；抓取时再把各任务的计数合并。内核端的 `CAPTURE_STATS` 和 perf 丢失样本数在每次抓取时现读。
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Write as _,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use aya::maps::{MapData, PerCpuArray};
+use aya_network_monitor_common::CaptureStats;
+use log::{debug, warn};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    task, time,
+};
+
+use crate::{
+    event::CapturedEvent,
+    flow::now_ns,
+    format::{format_direction, format_protocol},
+    stats::{self, LostCounters},
+};
+
+/// 请求头的最大长度，超过后不再读取
+const MAX_REQUEST: usize = 8192;
+/// 读取请求头的超时时间，超时后关闭连接
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 按（协议, 网卡, 方向）分组的包数和字节数
+type TrafficKey = (u8, Arc<str>, u8);
+
+/// 指标名、HELP 说明和取值函数
+type Column<T> = (&'static str, &'static str, fn(&T) -> u64);
+
+/// 单个读取任务的计数；只有该任务写入，抓取时读取
+#[derive(Debug, Default)]
+struct ReaderCounters {
+    /// 用户空间收到的事件数（过滤前）
+    events: AtomicU64,
+    /// 用户空间匹配过滤条件的事件数
+    filter_hits: AtomicU64,
+    /// 锁只在抓取时有竞争
+    traffic: Mutex<HashMap<TrafficKey, (u64, u64)>>,
+    /// 最近一个事件的延迟（纳秒）
+    lag_ns: AtomicU64,
+}
+
+/// 所有读取任务的指标
+#[derive(Debug, Clone, Default)]
+pub struct Metrics {
+    /// 键为读取任务名（CPU N / ringbuf）
+    readers: Arc<Mutex<BTreeMap<String, Arc<ReaderCounters>>>>,
+}
+
+impl Metrics {
+    /// 为一个读取任务登记计数；同名的任务共用一份
+    pub fn reader(&self, label: impl Into<String>) -> ReaderMetrics {
+        let counters = self.readers.lock().unwrap().entry(label.into()).or_default().clone();
+        ReaderMetrics { counters }
+    }
+}
+
+/// 单个读取任务持有的指标句柄
+#[derive(Debug, Clone)]
+pub struct ReaderMetrics {
+    counters: Arc<ReaderCounters>,
+}
+
+impl ReaderMetrics {
+    /// 收到一个事件：记录事件时间戳到处理时刻的延迟
+    pub fn observe(&self, event: &CapturedEvent) {
+        self.counters.events.fetch_add(1, Ordering::Relaxed);
+        self.counters.lag_ns.store(now_ns().saturating_sub(event.timestamp_ns), Ordering::Relaxed);
+    }
+
+    /// 事件匹配过滤条件
+    pub fn matched(&self, event: &CapturedEvent) {
+        self.counters.filter_hits.fetch_add(1, Ordering::Relaxed);
+
+        let mut traffic = self.counters.traffic.lock().unwrap();
+        let entry = traffic.entry((event.protocol, event.iface.clone(), event.direction)).or_insert((0, 0));
+        entry.0 += 1;
+        entry.1 += event.packet_size as u64;
+    }
+}
+
+/// 抓取时读取的内核端计数
+pub struct KernelSource {
+    pub capture_stats: Arc<PerCpuArray<MapData, CaptureStats>>,
+    pub lost: LostCounters,
+}
+
+/// 在 addr 上提供 /metrics；绑定失败时返回错误，之后的连接在后台任务中处理
+pub async fn serve(addr: SocketAddr, metrics: Metrics, kernel: KernelSource) -> anyhow::Result<task::JoinHandle<()>> {
+    let listener = TcpListener::bind(addr).await?;
+    let kernel = Arc::new(kernel);
+
+    Ok(task::spawn(async move {
+        loop {
+            let (stream, peer) = match listener.accept().await {
+                Ok(conn) => conn,
+                Err(e) => {
+                    warn!("metrics: 接受连接失败: {}", e);
+                    continue;
+                }
+            };
+
+            let metrics = metrics.clone();
+            let kernel = kernel.clone();
+            task::spawn(async move {
+                if let Err(e) = handle_connection(stream, &metrics, &kernel).await {
+                    debug!("metrics: {} 连接出错: {}", peer, e);
+                }
+            });
+        }
+    }))
+}
+
+/// 处理一个 HTTP 请求：只支持 GET /metrics，响应后关闭连接
+async fn handle_connection(mut stream: TcpStream, metrics: &Metrics, kernel: &KernelSource) -> std::io::Result<()> {
+    let mut request = Vec::with_capacity(1024);
+    let read_request = async {
+        let mut buf = [0u8; 1024];
+        while !request.windows(4).any(|w| w == b"\r\n\r\n") && request.len() < MAX_REQUEST {
+            let n = stream.read(&mut buf).await?;
+            if n == 0 {
+                break;
+            }
+            request.extend_from_slice(&buf[..n]);
+        }
+        Ok::<_, std::io::Error>(())
+    };
+    // 迟迟不发完请求头的连接直接关闭，不占用任务
+    match time::timeout(REQUEST_TIMEOUT, read_request).await {
+        Ok(result) => result?,
+        Err(_) => return Err(std::io::Error::new(std::io::ErrorKind::TimedOut, "读取请求超时")),
+    }
+
+    let request_line = String::from_utf8_lossy(&request);
+    let mut parts = request_line.lines().next().unwrap_or("").split_whitespace();
+    let (method, path) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+
+    let (status, content_type, body) = match (method, path.split('?').next().unwrap_or("")) {
+        ("GET", "/metrics") => ("200 OK", "text/plain; version=0.0.4; charset=utf-8", render(metrics, kernel)),
+        ("GET", _) => ("404 Not Found", "text/plain; charset=utf-8", "not found\n".to_string()),
+        _ => ("405 Method Not Allowed", "text/plain; charset=utf-8", "method not allowed\n".to_string()),
+    };
+
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+/// 输出一个指标的 HELP 和 TYPE 行
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+/// 转义标签值中的反斜杠、双引号和换行
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// 生成 Prometheus 文本格式；内核计数读取失败时只输出用户空间的指标
+fn render(metrics: &Metrics, kernel: &KernelSource) -> String {
+    match stats::snapshot(&kernel.capture_stats, &kernel.lost) {
+        Ok(rows) => format_metrics(metrics, Some(&rows)),
+        Err(e) => {
+            warn!("metrics: {:#}", e);
+            format_metrics(metrics, None)
+        }
+    }
+}
+
+/// 按已经读出的每 CPU 内核计数生成文本
+fn format_metrics(metrics: &Metrics, kernel_rows: Option<&[stats::CpuStats]>) -> String {
+    let readers: Vec<(String, Arc<ReaderCounters>)> =
+        metrics.readers.lock().unwrap().iter().map(|(label, counters)| (label.clone(), counters.clone())).collect();
+    let mut out = String::new();
+
+    let sum = |counter: fn(&ReaderCounters) -> &AtomicU64| -> u64 {
+        readers.iter().map(|(_, counters)| counter(counters).load(Ordering::Relaxed)).sum()
+    };
+    header(&mut out, "aya_monitor_events_total", "counter", "Events received in user space before filtering.");
+    let _ = writeln!(out, "aya_monitor_events_total {}", sum(|counters| &counters.events));
+
+    header(&mut out, "aya_monitor_filter_hits_total", "counter", "Events matching the user space filter.");
+    let _ = writeln!(out, "aya_monitor_filter_hits_total {}", sum(|counters| &counters.filter_hits));
+
+    // 合并各读取任务的计数；BTreeMap 让输出顺序稳定
+    let mut traffic: BTreeMap<TrafficKey, (u64, u64)> = BTreeMap::new();
+    for (_, counters) in &readers {
+        for (key, (packets, bytes)) in counters.traffic.lock().unwrap().iter() {
+            let entry = traffic.entry(key.clone()).or_insert((0, 0));
+            entry.0 += packets;
+            entry.1 += bytes;
+        }
+    }
+
+    let columns: [Column<(u64, u64)>; 2] = [
+        ("aya_monitor_packets_total", "Matched packets by protocol, interface and direction.", |counts| counts.0),
+        ("aya_monitor_bytes_total", "Matched bytes by protocol, interface and direction.", |counts| counts.1),
+    ];
+    for (name, help, value) in columns {
+        header(&mut out, name, "counter", help);
+        for ((protocol, iface, direction), counts) in &traffic {
+            let _ = writeln!(
+                out,
+                "{}{{protocol=\"{}\",iface=\"{}\",direction=\"{}\"}} {}",
+                name,
+                format_protocol(*protocol),
+                escape(iface),
+                format_direction(*direction),
+                value(counts),
+            );
+        }
+    }
+
+    header(&mut out, "aya_monitor_reader_lag_seconds", "gauge", "Delay between the kernel timestamp and user space handling of the latest event, per reader.");
+    for (reader, counters) in &readers {
+        let _ = writeln!(
+            out,
+            "aya_monitor_reader_lag_seconds{{reader=\"{}\"}} {:.6}",
+            escape(reader),
+            counters.lag_ns.load(Ordering::Relaxed) as f64 / 1e9
+        );
+    }
+
+    if let Some(rows) = kernel_rows {
+        let columns: [Column<stats::CpuStats>; 5] = [
+            ("aya_monitor_kernel_seen_total", "Packets seen by the eBPF program, per CPU.", |row| row.kernel.seen),
+            ("aya_monitor_kernel_filter_hits_total", "Packets matching the in-kernel filter, per CPU.", |row| row.kernel.matched),
+            ("aya_monitor_kernel_emitted_total", "Events submitted to user space, per CPU.", |row| row.kernel.emitted),
+            ("aya_monitor_kernel_dropped_total", "Matched events that could not be submitted to user space, per CPU.", |row| row.kernel.dropped),
+            ("aya_monitor_perf_lost_total", "Perf samples lost because the perf buffer was full, per CPU.", |row| row.lost),
+        ];
+        for (name, help, value) in columns {
+            header(&mut out, name, "counter", help);
+            for row in rows {
+                let _ = writeln!(out, "{}{{cpu=\"{}\"}} {}", name, row.cpu_id, value(row));
+            }
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::tests::event;
+    use aya_network_monitor_common::{DIRECTION_EGRESS, DIRECTION_INGRESS, IPPROTO_TCP, IPPROTO_UDP};
+
+    /// 时间戳在未来，延迟固定为 0
+    fn captured(protocol: u8, iface: &str, direction: u8, size: u32) -> CapturedEvent {
+        let mut header = event(protocol, "10.0.0.1", "10.0.0.2", (1234, 80), size, &[]);
+        header.direction = direction;
+        CapturedEvent { header, data: Vec::new(), timestamp_ns: u64::MAX, iface: Arc::from(iface) }
+    }
+
+    fn row(cpu_id: u32, seen: u64, matched: u64, emitted: u64, dropped: u64, lost: u64) -> stats::CpuStats {
+        stats::CpuStats { cpu_id, kernel: CaptureStats { seen, matched, emitted, dropped }, lost }
+    }
+
+    #[test]
+    fn render_text_format() {
+        let metrics = Metrics::default();
+        let cpu0 = metrics.reader("CPU 0");
+        let cpu1 = metrics.reader("CPU 1");
+
+        let tcp_in = captured(IPPROTO_TCP, "eth0", DIRECTION_INGRESS, 100);
+        let udp_out = captured(IPPROTO_UDP, "eth0", DIRECTION_EGRESS, 60);
+        for event in [&tcp_in, &udp_out, &tcp_in] {
+            cpu0.observe(event);
+        }
+        cpu0.matched(&tcp_in);
+        cpu0.matched(&udp_out);
+        cpu1.observe(&tcp_in);
+        cpu1.matched(&tcp_in);
+
+        let text = format_metrics(&metrics, Some(&[row(0, 10, 5, 4, 1, 0), row(1, 7, 3, 3, 0, 2)]));
+        let lines: Vec<&str> = text.lines().collect();
+        let sample = |prefix: &str| -> Vec<&str> {
+            lines.iter().copied().filter(|line| line.starts_with(prefix)).collect()
+        };
+
+        assert!(lines.contains(&"# TYPE aya_monitor_events_total counter"));
+        assert!(lines.contains(&"# TYPE aya_monitor_reader_lag_seconds gauge"));
+        assert_eq!(sample("aya_monitor_events_total "), ["aya_monitor_events_total 4"]);
+        assert_eq!(sample("aya_monitor_filter_hits_total "), ["aya_monitor_filter_hits_total 3"]);
+
+        // 两个读取任务的计数合并为一行
+        assert_eq!(
+            sample("aya_monitor_packets_total{"),
+            [
+                "aya_monitor_packets_total{protocol=\"TCP\",iface=\"eth0\",direction=\"ingress\"} 2",
+                "aya_monitor_packets_total{protocol=\"UDP\",iface=\"eth0\",direction=\"egress\"} 1",
+            ]
+        );
+        assert_eq!(
+            sample("aya_monitor_bytes_total{"),
+            [
+                "aya_monitor_bytes_total{protocol=\"TCP\",iface=\"eth0\",direction=\"ingress\"} 200",
+                "aya_monitor_bytes_total{protocol=\"UDP\",iface=\"eth0\",direction=\"egress\"} 60",
+            ]
+        );
+        assert_eq!(
+            sample("aya_monitor_reader_lag_seconds{"),
+            [
+                "aya_monitor_reader_lag_seconds{reader=\"CPU 0\"} 0.000000",
+                "aya_monitor_reader_lag_seconds{reader=\"CPU 1\"} 0.000000",
+            ]
+        );
+
+        assert_eq!(
+            sample("aya_monitor_kernel_seen_total{"),
+            ["aya_monitor_kernel_seen_total{cpu=\"0\"} 10", "aya_monitor_kernel_seen_total{cpu=\"1\"} 7"]
+        );
+        assert_eq!(
+            sample("aya_monitor_perf_lost_total{"),
+            ["aya_monitor_perf_lost_total{cpu=\"0\"} 0", "aya_monitor_perf_lost_total{cpu=\"1\"} 2"]
+        );
+
+        // 每个指标的 HELP 和 TYPE 各一行
+        for line in lines.iter().filter(|line| line.starts_with("# TYPE ")) {
+            let name = line.split_whitespace().nth(2).unwrap();
+            assert_eq!(lines.iter().filter(|l| l.starts_with(&format!("# HELP {} ", name))).count(), 1, "{}", name);
+        }
+    }
+
+    #[test]
+    fn render_escapes_labels() {
+        let metrics = Metrics::default();
+        let reader = metrics.reader("ring\"buf\\");
+        reader.matched(&captured(IPPROTO_TCP, "we\"ird\\if\nx", DIRECTION_INGRESS, 10));
+
+        let text = format_metrics(&metrics, None);
+        assert!(text.contains("aya_monitor_packets_total{protocol=\"TCP\",iface=\"we\\\"ird\\\\if\\nx\",direction=\"ingress\"} 1\n"));
+        assert!(text.contains("aya_monitor_reader_lag_seconds{reader=\"ring\\\"buf\\\\\"} 0.000000\n"));
+
+        // 读取内核计数失败时不输出内核指标
+        assert!(!text.contains("aya_monitor_kernel_"));
+        assert!(!text.contains("aya_monitor_perf_lost_total"));
+    }
+}
@@ -0,0 +1,163 @@
+//! CIDR 网段
+//!
+//! `--src-net` / `--dst-net` 的参数类型。用户空间用 `contains` 过滤离线事件，
+//! 实时捕获时通过 `lpm_key` 写入 eBPF 的 `SRC_NETS` / `DST_NETS` LPM trie。
+
+use std::{fmt, net::IpAddr, str::FromStr};
+
+use aya_network_monitor_common::{ip_addr, ip_bytes, net_key, NET_KEY_LEN};
+
+use crate::filter::FilterError;
+
+/// IP 网段，如 10.0.0.0/8 或 2001:db8::/32；不带前缀长度时为单个地址
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IpNet {
+    addr: IpAddr,
+    prefix_len: u8,
+}
+
+impl IpNet {
+    fn max_prefix_len(addr: IpAddr) -> u8 {
+        match addr {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        }
+    }
+
+    /// 网段是否包含该地址（IPv4 网段不匹配 IPv6 地址，反之亦然）
+    pub fn contains(&self, addr: IpAddr) -> bool {
+        let (version, net) = ip_bytes(self.addr);
+        let (addr_version, addr) = ip_bytes(addr);
+        version == addr_version && mask(addr, self.prefix_len) == net
+    }
+
+    /// (IP 版本, 16 字节网络地址, 前缀长度)
+    pub fn parts(&self) -> (u8, [u8; 16], u8) {
+        let (version, addr) = ip_bytes(self.addr);
+        (version, addr, self.prefix_len)
+    }
+
+    /// LPM trie 的 (前缀长度, 键)，前缀长度包含键开头的 IP 版本字节
+    pub fn lpm_key(&self) -> (u32, [u8; NET_KEY_LEN]) {
+        let (version, addr) = ip_bytes(self.addr);
+        (8 + self.prefix_len as u32, net_key(version, &addr))
+    }
+}
+
+/// 清除前缀之后的主机位
+fn mask(mut addr: [u8; 16], prefix_len: u8) -> [u8; 16] {
+    for (i, byte) in addr.iter_mut().enumerate() {
+        let bits = (prefix_len as usize).saturating_sub(i * 8).min(8);
+        *byte &= !(0xFFu8.checked_shr(bits as u32).unwrap_or(0));
+    }
+    addr
+}
+
+impl FromStr for IpNet {
+    type Err = FilterError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, prefix_len) = match s.split_once('/') {
+            Some((addr, len)) => (addr, Some(len)),
+            None => (s, None),
+        };
+
+        let addr: IpAddr = addr.trim().parse().map_err(|_| FilterError::InvalidAddress(addr.to_string()))?;
+        let max = Self::max_prefix_len(addr);
+        let prefix_len = match prefix_len {
+            Some(len) => match len.trim().parse::<u8>() {
+                Ok(len) if len <= max => len,
+                _ => return Err(FilterError::InvalidPrefixLen { len: len.to_string(), max }),
+            },
+            None => max,
+        };
+
+        // 保存网络地址，使 10.1.2.3/8 与 10.0.0.0/8 等价
+        let (version, bytes) = ip_bytes(addr);
+        let bytes = mask(bytes, prefix_len);
+        let addr = ip_addr(version, &bytes);
+
+        Ok(IpNet { addr, prefix_len })
+    }
+}
+
+impl fmt::Display for IpNet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix_len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_cidr() {
+        let cases = [
+            ("10.0.0.0/8", "10.0.0.0/8"),
+            ("10.1.2.3/8", "10.0.0.0/8"),
+            ("192.168.1.77/27", "192.168.1.64/27"),
+            ("172.16.5.4", "172.16.5.4/32"),
+            ("0.0.0.0/0", "0.0.0.0/0"),
+            (" 10.0.0.1 / 24 ", "10.0.0.0/24"),
+            ("2001:db8::1/32", "2001:db8::/32"),
+            ("2001:db8:abcd:12ff::/62", "2001:db8:abcd:12fc::/62"),
+            ("::1", "::1/128"),
+            ("::/0", "::/0"),
+        ];
+        for (input, expected) in cases {
+            let net: IpNet = input.parse().unwrap_or_else(|e| panic!("{}: {}", input, e));
+            assert_eq!(net.to_string(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn reject_invalid_cidr() {
+        let cases = [
+            "", "10.0.0/8", "10.0.0.0/33", "10.0.0.0/-1", "10.0.0.0/", "2001:db8::/129", "host/8", "10.0.0.0/8/8",
+        ];
+        for input in cases {
+            assert!(input.parse::<IpNet>().is_err(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn contains_respects_prefix_and_family() {
+        let cases = [
+            ("10.0.0.0/8", "10.255.1.2", true),
+            ("10.0.0.0/8", "11.0.0.1", false),
+            ("192.168.1.64/27", "192.168.1.95", true),
+            ("192.168.1.64/27", "192.168.1.96", false),
+            ("0.0.0.0/0", "8.8.8.8", true),
+            // IPv4 网段不匹配 IPv6 地址，包括 IPv4 映射地址
+            ("0.0.0.0/0", "::ffff:8.8.8.8", false),
+            ("::/0", "8.8.8.8", false),
+            ("2001:db8::/32", "2001:db8:1::1", true),
+            ("2001:db8::/32", "2001:db9::1", false),
+        ];
+        for (net, addr, expected) in cases {
+            let net: IpNet = net.parse().unwrap();
+            assert_eq!(net.contains(addr.parse().unwrap()), expected, "{} contains {}", net, addr);
+        }
+    }
+
+    #[test]
+    fn lpm_key_prefix_encoding() {
+        let (prefix_len, key) = "10.1.0.0/16".parse::<IpNet>().unwrap().lpm_key();
+        assert_eq!(prefix_len, 8 + 16);
+        assert_eq!(key[0], 4);
+        assert_eq!(&key[1..5], &[10, 1, 0, 0]);
+        assert!(key[5..].iter().all(|&b| b == 0));
+
+        let (prefix_len, key) = "2001:db8::/32".parse::<IpNet>().unwrap().lpm_key();
+        assert_eq!(prefix_len, 8 + 32);
+        assert_eq!(key[0], 6);
+        assert_eq!(&key[1..5], &[0x20, 0x01, 0x0d, 0xb8]);
+
+        // 单个地址的前缀覆盖版本字节和整个地址
+        assert_eq!("1.2.3.4".parse::<IpNet>().unwrap().lpm_key().0, 40);
+        assert_eq!("::1".parse::<IpNet>().unwrap().lpm_key().0, 136);
+        // /0 只匹配版本字节
+        assert_eq!("0.0.0.0/0".parse::<IpNet>().unwrap().lpm_key(), (8, net_key(4, &[0; 16])));
+    }
+}
@@ -0,0 +1,640 @@
+//! pcap / pcapng 文件读写
+//!
+//! 写入的文件由一个 Section Header Block、每个网卡一个 Interface Description Block
+//! 和若干 Enhanced Packet Block 组成，可以直接用 Wireshark 打开。
+//! 所有字段按小端序写入，读取方通过 byte-order magic 识别。
+//!
+//! 读取同时支持经典 pcap（微秒/纳秒，任意字节序）和 pcapng。
+
+use std::io::{self, Read, Write};
+
+use aya_network_monitor_common::{DIRECTION_EGRESS, DIRECTION_INGRESS};
+
+// 块类型
+const BLOCK_SHB: u32 = 0x0A0D_0D0A;
+const BLOCK_IDB: u32 = 0x0000_0001;
+const BLOCK_SPB: u32 = 0x0000_0003;
+const BLOCK_EPB: u32 = 0x0000_0006;
+
+const BYTE_ORDER_MAGIC: u32 = 0x1A2B_3C4D;
+
+// 经典 pcap 文件头 magic
+const PCAP_MAGIC_USEC: u32 = 0xA1B2_C3D4;
+const PCAP_MAGIC_NSEC: u32 = 0xA1B2_3C4D;
+
+// 链路类型：以太网
+pub const LINKTYPE_ETHERNET: u16 = 1;
+
+// 读取时单个数据包的最大长度（与 libpcap 的 MAXIMUM_SNAPLEN 相同）
+const MAX_SNAPLEN: usize = 262_144;
+// 读取时需要整块读入的 pcapng 块（IDB、EPB、SPB）的最大长度：数据包加上块头和选项
+const MAX_BLOCK_LEN: usize = MAX_SNAPLEN + 4096;
+
+// 选项代码
+const OPT_ENDOFOPT: u16 = 0;
+const OPT_SHB_USERAPPL: u16 = 4;
+const OPT_IF_NAME: u16 = 2;
+const OPT_IF_TSRESOL: u16 = 9;
+const OPT_EPB_FLAGS: u16 = 2;
+
+// epb_flags 的方向位（bit 0-1）
+const EPB_FLAGS_INBOUND: u32 = 0b01;
+const EPB_FLAGS_OUTBOUND: u32 = 0b10;
+
+/// pcapng 写入器
+///
+/// 时间戳分辨率固定为纳秒（if_tsresol = 9）。
+/// 每个网卡第一次出现时写入它的接口描述块，接口 ID 按出现顺序分配。
+pub struct PcapngWriter<W: Write> {
+    writer: W,
+    snaplen: u32,
+    interfaces: Vec<String>,
+}
+
+impl<W: Write> PcapngWriter<W> {
+    /// 写入文件头；snaplen 写入之后的每个接口描述块，0 表示不限制
+    pub fn new(mut writer: W, snaplen: u32) -> io::Result<Self> {
+        write_section_header(&mut writer)?;
+        Ok(PcapngWriter { writer, snaplen, interfaces: Vec::new() })
+    }
+
+    /// 网卡对应的接口 ID，第一次出现时先写入接口描述块
+    fn interface_id(&mut self, iface: &str) -> io::Result<u32> {
+        if let Some(id) = self.interfaces.iter().position(|name| name == iface) {
+            return Ok(id as u32);
+        }
+
+        write_interface_description(&mut self.writer, iface, self.snaplen)?;
+        self.interfaces.push(iface.to_string());
+        Ok(self.interfaces.len() as u32 - 1)
+    }
+
+    /// 写入一个数据包
+    ///
+    /// `data` 为捕获到的字节，`orig_len` 为数据包在线路上的原始长度，
+    /// `direction` 写入 epb_flags 选项，Wireshark 据此区分收发。
+    pub fn write_packet(
+        &mut self,
+        iface: &str,
+        timestamp_ns: u64,
+        data: &[u8],
+        orig_len: u32,
+        direction: u8,
+    ) -> io::Result<()> {
+        let interface_id = self.interface_id(iface)?;
+        let flags = if direction == DIRECTION_EGRESS { EPB_FLAGS_OUTBOUND } else { EPB_FLAGS_INBOUND };
+        let mut options = Vec::new();
+        push_option(&mut options, OPT_EPB_FLAGS, &flags.to_le_bytes());
+        push_end_of_options(&mut options);
+
+        let padded_len = pad4(data.len());
+        // 块头 8 + 接口 ID 4 + 时间戳 8 + 两个长度 8 + 数据 + 选项 + 块尾 4
+        let total_len = (32 + padded_len + options.len()) as u32;
+
+        let mut block = Vec::with_capacity(total_len as usize);
+        block.extend_from_slice(&BLOCK_EPB.to_le_bytes());
+        block.extend_from_slice(&total_len.to_le_bytes());
+        block.extend_from_slice(&interface_id.to_le_bytes());
+        block.extend_from_slice(&((timestamp_ns >> 32) as u32).to_le_bytes());
+        block.extend_from_slice(&(timestamp_ns as u32).to_le_bytes());
+        block.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        block.extend_from_slice(&orig_len.to_le_bytes());
+        block.extend_from_slice(data);
+        block.resize(block.len() + padded_len - data.len(), 0);
+        block.extend_from_slice(&options);
+        block.extend_from_slice(&total_len.to_le_bytes());
+
+        self.writer.write_all(&block)
+    }
+
+    /// 刷新缓冲区
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+fn write_section_header<W: Write>(writer: &mut W) -> io::Result<()> {
+    let mut options = Vec::new();
+    push_option(&mut options, OPT_SHB_USERAPPL, b"aya-network-monitor");
+    push_end_of_options(&mut options);
+
+    // 块头 8 + magic 4 + 版本 4 + section 长度 8 + 选项 + 块尾 4
+    let total_len = (28 + options.len()) as u32;
+
+    let mut block = Vec::with_capacity(total_len as usize);
+    block.extend_from_slice(&BLOCK_SHB.to_le_bytes());
+    block.extend_from_slice(&total_len.to_le_bytes());
+    block.extend_from_slice(&BYTE_ORDER_MAGIC.to_le_bytes());
+    block.extend_from_slice(&1u16.to_le_bytes()); // 主版本
+    block.extend_from_slice(&0u16.to_le_bytes()); // 次版本
+    block.extend_from_slice(&(-1i64).to_le_bytes()); // section 长度未知
+    block.extend_from_slice(&options);
+    block.extend_from_slice(&total_len.to_le_bytes());
+
+    writer.write_all(&block)
+}
+
+fn write_interface_description<W: Write>(writer: &mut W, iface: &str, snaplen: u32) -> io::Result<()> {
+    let mut options = Vec::new();
+    push_option(&mut options, OPT_IF_NAME, iface.as_bytes());
+    push_option(&mut options, OPT_IF_TSRESOL, &[9]); // 10^-9 秒
+    push_end_of_options(&mut options);
+
+    // 块头 8 + 链路类型 2 + 保留 2 + snaplen 4 + 选项 + 块尾 4
+    let total_len = (20 + options.len()) as u32;
+
+    let mut block = Vec::with_capacity(total_len as usize);
+    block.extend_from_slice(&BLOCK_IDB.to_le_bytes());
+    block.extend_from_slice(&total_len.to_le_bytes());
+    block.extend_from_slice(&LINKTYPE_ETHERNET.to_le_bytes());
+    block.extend_from_slice(&0u16.to_le_bytes());
+    block.extend_from_slice(&snaplen.to_le_bytes());
+    block.extend_from_slice(&options);
+    block.extend_from_slice(&total_len.to_le_bytes());
+
+    writer.write_all(&block)
+}
+
+/// 追加一个选项（代码 + 长度 + 值，按 4 字节对齐）
+fn push_option(buf: &mut Vec<u8>, code: u16, value: &[u8]) {
+    buf.extend_from_slice(&code.to_le_bytes());
+    buf.extend_from_slice(&(value.len() as u16).to_le_bytes());
+    buf.extend_from_slice(value);
+    buf.resize(buf.len() + pad4(value.len()) - value.len(), 0);
+}
+
+fn push_end_of_options(buf: &mut Vec<u8>) {
+    buf.extend_from_slice(&OPT_ENDOFOPT.to_le_bytes());
+    buf.extend_from_slice(&0u16.to_le_bytes());
+}
+
+/// 向上对齐到 4 字节
+fn pad4(len: usize) -> usize {
+    (len + 3) & !3
+}
+
+/// 从文件中读出的一个数据包
+pub struct PcapPacket {
+    /// Unix 纪元以来的纳秒数
+    pub timestamp_ns: u64,
+    /// 捕获到的字节
+    pub data: Vec<u8>,
+    /// 线路上的原始长度
+    pub orig_len: u32,
+    /// 收发方向，取自 epb_flags；没有记录时按收到处理
+    pub direction: u8,
+    /// 网卡名，取自接口描述块的 if_name；经典 pcap 没有
+    pub iface: Option<String>,
+}
+
+/// 文件格式及其状态
+enum Format {
+    /// 经典 pcap：字节序和时间戳单位（每秒的计数）
+    Pcap { big_endian: bool, ticks_per_sec: u64 },
+    /// pcapng：当前 section 的字节序，以及每个接口的时间戳单位和名称
+    Pcapng { big_endian: bool, if_ticks_per_sec: Vec<u64>, if_names: Vec<Option<String>> },
+}
+
+/// pcap / pcapng 读取器，只支持以太网链路类型
+pub struct PcapReader<R: Read> {
+    reader: R,
+    format: Format,
+}
+
+impl<R: Read> PcapReader<R> {
+    /// 读取文件头并识别格式
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+
+        if u32::from_le_bytes(magic) == BLOCK_SHB {
+            // pcapng：Section Header Block 的类型字段是回文，字节序由后面的 magic 决定
+            let mut raw_total_len = [0u8; 4];
+            reader.read_exact(&mut raw_total_len)?;
+            let mut reader = PcapReader {
+                reader,
+                format: Format::Pcapng { big_endian: false, if_ticks_per_sec: Vec::new(), if_names: Vec::new() },
+            };
+            reader.read_section_header(raw_total_len)?;
+            return Ok(reader);
+        }
+
+        let (big_endian, ticks_per_sec) = match (u32::from_le_bytes(magic), u32::from_be_bytes(magic)) {
+            (PCAP_MAGIC_USEC, _) => (false, 1_000_000),
+            (PCAP_MAGIC_NSEC, _) => (false, 1_000_000_000),
+            (_, PCAP_MAGIC_USEC) => (true, 1_000_000),
+            (_, PCAP_MAGIC_NSEC) => (true, 1_000_000_000),
+            _ => return Err(invalid_data("不是 pcap 或 pcapng 文件")),
+        };
+
+        // 版本 4 + 时区 4 + 精度 4 + snaplen 4 + 链路类型 4
+        let mut header = [0u8; 20];
+        reader.read_exact(&mut header)?;
+        let linktype = read_u32(&header[16..20], big_endian);
+        check_linktype(linktype)?;
+
+        Ok(PcapReader {
+            reader,
+            format: Format::Pcap { big_endian, ticks_per_sec },
+        })
+    }
+
+    /// 读取下一个数据包，文件结束时返回 None
+    pub fn next_packet(&mut self) -> io::Result<Option<PcapPacket>> {
+        match self.format {
+            Format::Pcap { big_endian, ticks_per_sec } => self.next_pcap_packet(big_endian, ticks_per_sec),
+            Format::Pcapng { .. } => self.next_pcapng_packet(),
+        }
+    }
+
+    fn next_pcap_packet(&mut self, big_endian: bool, ticks_per_sec: u64) -> io::Result<Option<PcapPacket>> {
+        let mut header = [0u8; 16];
+        if !read_exact_or_eof(&mut self.reader, &mut header)? {
+            return Ok(None);
+        }
+
+        let ts_sec = read_u32(&header[0..4], big_endian) as u64;
+        let ts_frac = read_u32(&header[4..8], big_endian) as u64;
+        let incl_len = read_u32(&header[8..12], big_endian) as usize;
+        let orig_len = read_u32(&header[12..16], big_endian);
+        if incl_len > MAX_SNAPLEN {
+            return Err(invalid_data("pcap 数据包长度超过上限"));
+        }
+
+        let mut data = vec![0u8; incl_len];
+        self.reader.read_exact(&mut data)?;
+
+        Ok(Some(PcapPacket {
+            timestamp_ns: ts_sec * 1_000_000_000 + ts_frac * (1_000_000_000 / ticks_per_sec),
+            data,
+            orig_len,
+            direction: DIRECTION_INGRESS,
+            iface: None,
+        }))
+    }
+
+    fn next_pcapng_packet(&mut self) -> io::Result<Option<PcapPacket>> {
+        loop {
+            let mut header = [0u8; 8];
+            if !read_exact_or_eof(&mut self.reader, &mut header)? {
+                return Ok(None);
+            }
+
+            let raw_block_type = [header[0], header[1], header[2], header[3]];
+            if u32::from_le_bytes(raw_block_type) == BLOCK_SHB {
+                // 新的 section，重新读取字节序和接口列表
+                self.read_section_header([header[4], header[5], header[6], header[7]])?;
+                continue;
+            }
+
+            let big_endian = self.big_endian();
+            let block_type = read_u32(&header[0..4], big_endian);
+            let total_len = read_u32(&header[4..8], big_endian) as usize;
+            if total_len < 12 || !total_len.is_multiple_of(4) {
+                return Err(invalid_data("pcapng 块长度无效"));
+            }
+            // 统计、名称解析等其他块直接跳过，不读入内存
+            if !matches!(block_type, BLOCK_IDB | BLOCK_EPB | BLOCK_SPB) {
+                skip(&mut self.reader, total_len - 8)?;
+                continue;
+            }
+            if total_len > MAX_BLOCK_LEN {
+                return Err(invalid_data("pcapng 块长度超过上限"));
+            }
+
+            // 块体（不含块头 8 字节和块尾 4 字节）
+            let mut body = vec![0u8; total_len - 12];
+            self.reader.read_exact(&mut body)?;
+            let mut trailer = [0u8; 4];
+            self.reader.read_exact(&mut trailer)?;
+
+            match block_type {
+                BLOCK_IDB => self.add_interface(&body)?,
+                BLOCK_EPB => {
+                    if body.len() < 20 {
+                        return Err(invalid_data("Enhanced Packet Block 过短"));
+                    }
+                    let interface_id = read_u32(&body[0..4], big_endian) as usize;
+                    let ts_high = read_u32(&body[4..8], big_endian) as u64;
+                    let ts_low = read_u32(&body[8..12], big_endian) as u64;
+                    let cap_len = read_u32(&body[12..16], big_endian) as usize;
+                    let orig_len = read_u32(&body[16..20], big_endian);
+                    let data = body
+                        .get(20..20 + cap_len)
+                        .ok_or_else(|| invalid_data("Enhanced Packet Block 数据越界"))?
+                        .to_vec();
+                    let options = body.get(20 + pad4(cap_len)..).unwrap_or(&[]);
+
+                    let ticks = (ts_high << 32) | ts_low;
+                    return Ok(Some(PcapPacket {
+                        timestamp_ns: ticks_to_ns(ticks, self.ticks_per_sec(interface_id)),
+                        data,
+                        orig_len,
+                        direction: epb_direction(options, big_endian),
+                        iface: self.interface_name(interface_id),
+                    }));
+                }
+                BLOCK_SPB => {
+                    if body.len() < 4 {
+                        return Err(invalid_data("Simple Packet Block 过短"));
+                    }
+                    let orig_len = read_u32(&body[0..4], big_endian);
+                    let cap_len = core::cmp::min(orig_len as usize, body.len() - 4);
+                    return Ok(Some(PcapPacket {
+                        timestamp_ns: 0,
+                        data: body[4..4 + cap_len].to_vec(),
+                        orig_len,
+                        direction: DIRECTION_INGRESS,
+                        iface: self.interface_name(0),
+                    }));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// 读取 Section Header Block 的剩余部分（块头 8 字节已被读取）
+    ///
+    /// 块长度字段的字节序要等读到 byte-order magic 之后才能确定。
+    fn read_section_header(&mut self, raw_total_len: [u8; 4]) -> io::Result<()> {
+        let mut magic = [0u8; 4];
+        self.reader.read_exact(&mut magic)?;
+        let big_endian = match u32::from_le_bytes(magic) {
+            BYTE_ORDER_MAGIC => false,
+            m if m.swap_bytes() == BYTE_ORDER_MAGIC => true,
+            _ => return Err(invalid_data("pcapng byte-order magic 无效")),
+        };
+        let total_len = read_u32(&raw_total_len, big_endian) as usize;
+        if total_len < 28 || !total_len.is_multiple_of(4) {
+            return Err(invalid_data("Section Header Block 长度无效"));
+        }
+
+        // 跳过版本、section 长度、选项和块尾
+        skip(&mut self.reader, total_len - 12)?;
+
+        self.format = Format::Pcapng { big_endian, if_ticks_per_sec: Vec::new(), if_names: Vec::new() };
+        Ok(())
+    }
+
+    /// 解析 Interface Description Block，记录时间戳分辨率
+    fn add_interface(&mut self, body: &[u8]) -> io::Result<()> {
+        let big_endian = self.big_endian();
+        if body.len() < 8 {
+            return Err(invalid_data("Interface Description Block 过短"));
+        }
+        let linktype = read_u16(&body[0..2], big_endian) as u32;
+        check_linktype(linktype)?;
+
+        // 默认分辨率为微秒
+        let mut ticks_per_sec = 1_000_000u64;
+        let mut name = None;
+        let mut options = &body[8..];
+        while options.len() >= 4 {
+            let code = read_u16(&options[0..2], big_endian);
+            let len = read_u16(&options[2..4], big_endian) as usize;
+            if code == OPT_ENDOFOPT || options.len() < 4 + len {
+                break;
+            }
+            if code == OPT_IF_NAME {
+                name = Some(String::from_utf8_lossy(&options[4..4 + len]).into_owned());
+            }
+            if code == OPT_IF_TSRESOL && len >= 1 {
+                let resol = options[4];
+                let exp = (resol & 0x7F) as u32;
+                ticks_per_sec = if resol & 0x80 == 0 {
+                    10u64.checked_pow(exp).unwrap_or(1_000_000)
+                } else {
+                    2u64.checked_pow(exp).unwrap_or(1_000_000)
+                };
+            }
+            options = &options[4 + pad4(len).min(options.len() - 4)..];
+        }
+
+        if let Format::Pcapng { ref mut if_ticks_per_sec, ref mut if_names, .. } = self.format {
+            if_ticks_per_sec.push(ticks_per_sec);
+            if_names.push(name);
+        }
+        Ok(())
+    }
+
+    fn big_endian(&self) -> bool {
+        match self.format {
+            Format::Pcap { big_endian, .. } | Format::Pcapng { big_endian, .. } => big_endian,
+        }
+    }
+
+    fn interface_name(&self, interface_id: usize) -> Option<String> {
+        match self.format {
+            Format::Pcapng { ref if_names, .. } => if_names.get(interface_id).cloned().flatten(),
+            Format::Pcap { .. } => None,
+        }
+    }
+
+    fn ticks_per_sec(&self, interface_id: usize) -> u64 {
+        match self.format {
+            Format::Pcapng { ref if_ticks_per_sec, .. } => {
+                if_ticks_per_sec.get(interface_id).copied().unwrap_or(1_000_000)
+            }
+            Format::Pcap { ticks_per_sec, .. } => ticks_per_sec,
+        }
+    }
+}
+
+/// 从 Enhanced Packet Block 的选项中取出 epb_flags 记录的方向
+fn epb_direction(mut options: &[u8], big_endian: bool) -> u8 {
+    while options.len() >= 4 {
+        let code = read_u16(&options[0..2], big_endian);
+        let len = read_u16(&options[2..4], big_endian) as usize;
+        if code == OPT_ENDOFOPT || options.len() < 4 + len {
+            break;
+        }
+        if code == OPT_EPB_FLAGS && len >= 4 && read_u32(&options[4..8], big_endian) & 0b11 == EPB_FLAGS_OUTBOUND {
+            return DIRECTION_EGRESS;
+        }
+        options = &options[4 + pad4(len).min(options.len() - 4)..];
+    }
+    DIRECTION_INGRESS
+}
+
+fn check_linktype(linktype: u32) -> io::Result<()> {
+    if linktype != LINKTYPE_ETHERNET as u32 {
+        return Err(invalid_data(&format!("不支持的链路类型 {}，只支持以太网", linktype)));
+    }
+    Ok(())
+}
+
+/// 时间戳计数换算为纳秒；分辨率很粗（如 if_tsresol = 0）时可能超出 u64，按上限截断
+fn ticks_to_ns(ticks: u64, ticks_per_sec: u64) -> u64 {
+    let ns = ticks as u128 * 1_000_000_000 / ticks_per_sec as u128;
+    u64::try_from(ns).unwrap_or(u64::MAX)
+}
+
+fn read_u16(bytes: &[u8], big_endian: bool) -> u16 {
+    let bytes = [bytes[0], bytes[1]];
+    if big_endian { u16::from_be_bytes(bytes) } else { u16::from_le_bytes(bytes) }
+}
+
+fn read_u32(bytes: &[u8], big_endian: bool) -> u32 {
+    let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+    if big_endian { u32::from_be_bytes(bytes) } else { u32::from_le_bytes(bytes) }
+}
+
+/// 跳过 len 字节，不分配缓冲区
+fn skip<R: Read>(reader: &mut R, len: usize) -> io::Result<()> {
+    let skipped = io::copy(&mut reader.take(len as u64), &mut io::sink())?;
+    if skipped < len as u64 {
+        return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+    }
+    Ok(())
+}
+
+/// 读满 buf；在一开始就遇到文件结尾时返回 false
+fn read_exact_or_eof<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<bool> {
+    let mut read = 0;
+    while read < buf.len() {
+        match reader.read(&mut buf[read..]) {
+            Ok(0) if read == 0 => return Ok(false),
+            Ok(0) => return Err(io::Error::from(io::ErrorKind::UnexpectedEof)),
+            Ok(n) => read += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(true)
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 经典 pcap 文件：文件头 + 每个数据包的记录头和数据
+    fn classic_pcap(magic: u32, big_endian: bool, packets: &[(u32, u32, &[u8])]) -> Vec<u8> {
+        let u32_bytes = |v: u32| if big_endian { v.to_be_bytes() } else { v.to_le_bytes() };
+        let u16_bytes = |v: u16| if big_endian { v.to_be_bytes() } else { v.to_le_bytes() };
+
+        let mut file = Vec::new();
+        file.extend_from_slice(&u32_bytes(magic));
+        file.extend_from_slice(&u16_bytes(2));
+        file.extend_from_slice(&u16_bytes(4));
+        file.extend_from_slice(&u32_bytes(0));
+        file.extend_from_slice(&u32_bytes(0));
+        file.extend_from_slice(&u32_bytes(65535));
+        file.extend_from_slice(&u32_bytes(LINKTYPE_ETHERNET as u32));
+        for &(ts_sec, ts_frac, data) in packets {
+            file.extend_from_slice(&u32_bytes(ts_sec));
+            file.extend_from_slice(&u32_bytes(ts_frac));
+            file.extend_from_slice(&u32_bytes(data.len() as u32));
+            file.extend_from_slice(&u32_bytes(data.len() as u32 + 10));
+            file.extend_from_slice(data);
+        }
+        file
+    }
+
+    fn read_all(file: &[u8]) -> io::Result<Vec<PcapPacket>> {
+        let mut reader = PcapReader::new(file)?;
+        let mut packets = Vec::new();
+        while let Some(packet) = reader.next_packet()? {
+            packets.push(packet);
+        }
+        Ok(packets)
+    }
+
+    #[test]
+    fn pcapng_round_trip() {
+        let mut writer = PcapngWriter::new(Vec::new(), 65535).unwrap();
+        writer.write_packet("eth0", 1_700_000_000_123_456_789, &[1, 2, 3, 4, 5], 60, DIRECTION_INGRESS).unwrap();
+        writer.write_packet("lo", 1_700_000_001_000_000_001, &[6; 64], 64, DIRECTION_EGRESS).unwrap();
+        writer.write_packet("eth0", 1_700_000_002_000_000_000, &[], 0, DIRECTION_INGRESS).unwrap();
+        let file = writer.writer;
+
+        let packets = read_all(&file).unwrap();
+        assert_eq!(packets.len(), 3);
+
+        assert_eq!(packets[0].timestamp_ns, 1_700_000_000_123_456_789);
+        assert_eq!(packets[0].data, [1, 2, 3, 4, 5]);
+        assert_eq!(packets[0].orig_len, 60);
+        assert_eq!(packets[0].direction, DIRECTION_INGRESS);
+        assert_eq!(packets[0].iface.as_deref(), Some("eth0"));
+
+        assert_eq!(packets[1].timestamp_ns, 1_700_000_001_000_000_001);
+        assert_eq!(packets[1].data, [6; 64]);
+        assert_eq!(packets[1].direction, DIRECTION_EGRESS);
+        assert_eq!(packets[1].iface.as_deref(), Some("lo"));
+
+        assert!(packets[2].data.is_empty());
+        assert_eq!(packets[2].iface.as_deref(), Some("eth0"));
+    }
+
+    #[test]
+    fn classic_pcap_microseconds() {
+        let file = classic_pcap(PCAP_MAGIC_USEC, false, &[(1_700_000_000, 123_456, &[0xaa; 14])]);
+        let packets = read_all(&file).unwrap();
+        assert_eq!(packets.len(), 1);
+        assert_eq!(packets[0].timestamp_ns, 1_700_000_000_123_456_000);
+        assert_eq!(packets[0].data, [0xaa; 14]);
+        assert_eq!(packets[0].orig_len, 24);
+        assert_eq!(packets[0].direction, DIRECTION_INGRESS);
+        assert_eq!(packets[0].iface, None);
+    }
+
+    #[test]
+    fn classic_pcap_nanoseconds_big_endian() {
+        let file = classic_pcap(PCAP_MAGIC_NSEC, true, &[(1, 999_999_999, &[1, 2, 3]), (2, 0, &[4])]);
+        let packets = read_all(&file).unwrap();
+        assert_eq!(packets.len(), 2);
+        assert_eq!(packets[0].timestamp_ns, 1_999_999_999);
+        assert_eq!(packets[0].data, [1, 2, 3]);
+        assert_eq!(packets[1].timestamp_ns, 2_000_000_000);
+        assert_eq!(packets[1].data, [4]);
+    }
+
+    #[test]
+    fn truncated_files() {
+        let classic = classic_pcap(PCAP_MAGIC_USEC, false, &[(1, 0, &[0; 32])]);
+        let err = read_all(&classic[..classic.len() - 1]).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+
+        let mut writer = PcapngWriter::new(Vec::new(), 65535).unwrap();
+        writer.write_packet("eth0", 1, &[0; 32], 32, DIRECTION_INGRESS).unwrap();
+        let pcapng = writer.writer;
+        for cut in [1, 4, 20, pcapng.len() - 30] {
+            let err = read_all(&pcapng[..pcapng.len() - cut]).err().unwrap();
+            assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof, "cut {}", cut);
+        }
+
+        // 文件头不完整
+        assert!(PcapReader::new(&classic[..10]).is_err());
+    }
+
+    #[test]
+    fn invalid_lengths() {
+        // 超过上限的 incl_len 不会按该长度分配内存
+        let mut classic = classic_pcap(PCAP_MAGIC_USEC, false, &[(1, 0, &[0; 4])]);
+        classic[32..36].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(read_all(&classic).err().unwrap().kind(), io::ErrorKind::InvalidData);
+
+        let mut writer = PcapngWriter::new(Vec::new(), 65535).unwrap();
+        writer.write_packet("eth0", 1, &[0; 4], 4, DIRECTION_INGRESS).unwrap();
+        let pcapng = writer.writer;
+        let shb_len = u32::from_le_bytes(pcapng[4..8].try_into().unwrap()) as usize;
+
+        // 块长度小于 12、没有按 4 字节对齐或超过上限
+        for total_len in [0u32, 8, 13, u32::MAX - 3] {
+            let mut file = pcapng.clone();
+            file[shb_len + 4..shb_len + 8].copy_from_slice(&total_len.to_le_bytes());
+            assert_eq!(read_all(&file).err().unwrap().kind(), io::ErrorKind::InvalidData, "total_len {}", total_len);
+        }
+    }
+
+    #[test]
+    fn coarse_timestamp_resolution_saturates() {
+        assert_eq!(ticks_to_ns(1_500_000, 1_000_000), 1_500_000_000);
+        assert_eq!(ticks_to_ns(3, 2), 1_500_000_000);
+        // if_tsresol = 0：每个计数为 1 秒
+        assert_eq!(ticks_to_ns(u64::MAX, 1), u64::MAX);
+    }
+}
@@ -0,0 +1,166 @@
+//! 端口范围
+//!
+//! `--src-port` / `--dst-port` / `--port` 的参数类型，支持 `443`、`8000-8100` 以及逗号分隔的组合。
+//! 实时捕获时展开为 eBPF `PORT_BITMAP` 中的位图，每个端口 1 bit。
+
+use std::{fmt, str::FromStr};
+
+use aya_network_monitor_common::PORT_BITMAP_WORDS;
+
+use crate::filter::FilterError;
+
+/// 闭区间端口范围（主机字节序）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PortRange {
+    start: u16,
+    end: u16,
+}
+
+impl PortRange {
+    /// (起始端口, 结束端口)
+    pub fn bounds(&self) -> (u16, u16) {
+        (self.start, self.end)
+    }
+
+    pub fn is_single(&self) -> bool {
+        self.start == self.end
+    }
+
+    pub fn contains(&self, port: u16) -> bool {
+        self.start <= port && port <= self.end
+    }
+}
+
+impl FromStr for PortRange {
+    type Err = FilterError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse = |p: &str| p.trim().parse::<u16>().map_err(|_| FilterError::InvalidPort(p.to_string()));
+
+        let (start, end) = match s.split_once('-') {
+            Some((start, end)) => (parse(start)?, parse(end)?),
+            None => {
+                let port = parse(s)?;
+                (port, port)
+            }
+        };
+
+        if start > end {
+            return Err(FilterError::InvalidPortRange(s.to_string()));
+        }
+
+        Ok(PortRange { start, end })
+    }
+}
+
+impl fmt::Display for PortRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.start == self.end {
+            write!(f, "{}", self.start)
+        } else {
+            write!(f, "{}-{}", self.start, self.end)
+        }
+    }
+}
+
+/// 展开为 65536 bit 的位图（第 port / 64 个字的第 port % 64 位）
+pub fn port_bitmap(ranges: &[PortRange]) -> Vec<u64> {
+    let mut bitmap = vec![0u64; PORT_BITMAP_WORDS as usize];
+    for range in ranges {
+        for port in range.start..=range.end {
+            bitmap[port as usize / 64] |= 1 << (port % 64);
+        }
+    }
+    bitmap
+}
+
+pub fn format_ports(ranges: &[PortRange]) -> String {
+    ranges.iter().map(|range| range.to_string()).collect::<Vec<_>>().join(",")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ranges(spec: &str) -> Vec<PortRange> {
+        spec.split(',').map(|s| s.parse().unwrap()).collect()
+    }
+
+    fn any_contains(set: &[PortRange], port: u16) -> bool {
+        set.iter().any(|range| range.contains(port))
+    }
+
+    /// 与 eBPF 中 port_in_set 相同的查表方式
+    fn bit_set(bitmap: &[u64], port: u16) -> bool {
+        (bitmap[port as usize / 64] >> (port % 64)) & 1 != 0
+    }
+
+    #[test]
+    fn parse_ranges() {
+        let cases = [
+            ("443", (443, 443), "443"),
+            ("0", (0, 0), "0"),
+            ("65535", (65535, 65535), "65535"),
+            ("8000-8100", (8000, 8100), "8000-8100"),
+            (" 20 - 21 ", (20, 21), "20-21"),
+            ("53-53", (53, 53), "53"),
+            ("0-65535", (0, 65535), "0-65535"),
+        ];
+        for (input, (start, end), display) in cases {
+            let range: PortRange = input.parse().unwrap_or_else(|e| panic!("{}: {}", input, e));
+            assert_eq!(range, PortRange { start, end }, "{}", input);
+            assert_eq!(range.to_string(), display, "{}", input);
+            assert_eq!(range.is_single(), start == end, "{}", input);
+        }
+    }
+
+    #[test]
+    fn reject_invalid_ranges() {
+        for input in ["", "http", "65536", "-1", "80-", "-80", "100-20", "1-2-3", "80,443"] {
+            assert!(input.parse::<PortRange>().is_err(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn set_membership() {
+        let set = ranges("22,80,8000-8100");
+        let cases = [
+            (22, true),
+            (23, false),
+            (80, true),
+            (7999, false),
+            (8000, true),
+            (8050, true),
+            (8100, true),
+            (8101, false),
+        ];
+        for (port, expected) in cases {
+            assert_eq!(any_contains(&set, port), expected, "{}", port);
+        }
+        assert!(!any_contains(&[], 80));
+        assert_eq!(format_ports(&set), "22,80,8000-8100");
+    }
+
+    #[test]
+    fn bitmap_layout() {
+        let bitmap = port_bitmap(&ranges("0,63,64,443,1000-1003,65535"));
+        assert_eq!(bitmap.len(), PORT_BITMAP_WORDS as usize);
+
+        // 第 port / 64 个字的第 port % 64 位
+        assert_eq!(bitmap[0], 1 | 1 << 63);
+        assert_eq!(bitmap[1], 1);
+        assert_eq!(bitmap[443 / 64], 1 << (443 % 64));
+        assert_eq!(bitmap[1000 / 64], 0b1111 << (1000 % 64));
+        assert_eq!(bitmap[1023], 1 << 63);
+        assert_eq!(bitmap.iter().map(|word| word.count_ones()).sum::<u32>(), 9);
+
+        // 与逐个范围判断的结果一致
+        let set = ranges("22,1024-2047,60000-60100");
+        let bitmap = port_bitmap(&set);
+        for port in 0..=u16::MAX {
+            assert_eq!(bit_set(&bitmap, port), any_contains(&set, port), "{}", port);
+        }
+
+        assert!(port_bitmap(&[]).iter().all(|&word| word == 0));
+    }
+}
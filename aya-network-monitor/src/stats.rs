@@ -0,0 +1,314 @@
+//! 捕获统计
+//!
+//! 汇总 eBPF 端每 CPU 的 `CAPTURE_STATS` 计数和用户空间观察到的 perf 丢失样本数，
+//! 用来判断一次捕获是否完整；退出时与各读取任务的计数合并为 `Summary`。
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use anyhow::Context as _;
+use aya::maps::{MapData, PerCpuArray};
+use aya_network_monitor_common::CaptureStats;
+use log::{info, warn};
+use serde::Serialize;
+
+use crate::format::format_protocol;
+
+/// 每 CPU 的 perf 丢失样本计数（perf buffer 写满时内核丢弃的记录），由读取任务累加
+#[derive(Debug, Clone, Default)]
+pub struct LostCounters {
+    counters: Arc<BTreeMap<u32, AtomicU64>>,
+}
+
+impl LostCounters {
+    pub fn new(cpus: &[u32]) -> Self {
+        let counters = cpus.iter().map(|&cpu_id| (cpu_id, AtomicU64::new(0))).collect();
+        LostCounters { counters: Arc::new(counters) }
+    }
+
+    pub fn add(&self, cpu_id: u32, lost: usize) {
+        if let Some(counter) = self.counters.get(&cpu_id) {
+            counter.fetch_add(lost as u64, Ordering::Relaxed);
+        }
+    }
+
+    pub fn get(&self, cpu_id: u32) -> u64 {
+        self.counters
+            .get(&cpu_id)
+            .map(|counter| counter.load(Ordering::Relaxed))
+            .unwrap_or(0)
+    }
+}
+
+/// 单个 CPU 的统计快照
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CpuStats {
+    pub cpu_id: u32,
+    pub kernel: CaptureStats,
+    pub lost: u64,
+}
+
+impl CpuStats {
+    /// 匹配过滤条件却没有到达用户空间的事件数
+    pub fn missing(&self) -> u64 {
+        self.kernel.dropped + self.lost
+    }
+}
+
+/// 读取 CAPTURE_STATS 的每 CPU 计数，与 perf 丢失计数合并
+pub fn snapshot(
+    map: &PerCpuArray<MapData, CaptureStats>,
+    lost: &LostCounters,
+) -> anyhow::Result<Vec<CpuStats>> {
+    let values = map.get(&0, 0).context("读取 CAPTURE_STATS 失败")?;
+
+    Ok(values
+        .iter()
+        .enumerate()
+        .map(|(cpu_id, kernel)| CpuStats {
+            cpu_id: cpu_id as u32,
+            kernel: *kernel,
+            lost: lost.get(cpu_id as u32),
+        })
+        .collect())
+}
+
+/// 所有 CPU 的合计
+pub fn total(rows: &[CpuStats]) -> CpuStats {
+    rows.iter().fold(CpuStats::default(), |mut acc, row| {
+        acc.kernel.seen += row.kernel.seen;
+        acc.kernel.matched += row.kernel.matched;
+        acc.kernel.emitted += row.kernel.emitted;
+        acc.kernel.dropped += row.kernel.dropped;
+        acc.lost += row.lost;
+        acc
+    })
+}
+
+/// 输出统计到日志（stderr），不干扰 stdout 上的事件输出
+pub fn log_stats(rows: &[CpuStats]) {
+    for row in rows.iter().filter(|row| row.kernel.seen > 0 || row.lost > 0) {
+        info!(
+            "CPU {:>3}: 收到 {} 匹配 {} 上送 {} 丢弃 {} 丢失 {}",
+            row.cpu_id, row.kernel.seen, row.kernel.matched, row.kernel.emitted, row.kernel.dropped, row.lost
+        );
+    }
+
+    let sum = total(rows);
+    info!(
+        "合计   : 收到 {} 匹配 {} 上送 {} 丢弃 {} 丢失 {}",
+        sum.kernel.seen, sum.kernel.matched, sum.kernel.emitted, sum.kernel.dropped, sum.lost
+    );
+
+    if sum.missing() > 0 {
+        warn!("捕获不完整：{} 个匹配的事件未到达用户空间", sum.missing());
+    }
+}
+
+/// 单个读取任务（某个 CPU 的 perf buffer、ring buffer 或离线文件）的计数
+#[derive(Debug, Clone, Default)]
+pub struct ReaderSummary {
+    pub label: String,
+    pub total: usize,
+    pub filtered: usize,
+    pub bytes: u64,
+    pub protocols: HashMap<u8, usize>,
+}
+
+/// 内核端合计（仅实时捕获）
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct KernelSummary {
+    pub seen: u64,
+    pub matched: u64,
+    pub emitted: u64,
+    pub dropped: u64,
+    pub lost: u64,
+}
+
+impl From<CpuStats> for KernelSummary {
+    fn from(sum: CpuStats) -> Self {
+        KernelSummary {
+            seen: sum.kernel.seen,
+            matched: sum.kernel.matched,
+            emitted: sum.kernel.emitted,
+            dropped: sum.kernel.dropped,
+            lost: sum.lost,
+        }
+    }
+}
+
+/// 每个读取任务在汇总表中的一行
+#[derive(Debug, Clone, Serialize)]
+pub struct ReaderRow {
+    pub label: String,
+    pub total: usize,
+    pub filtered: usize,
+    pub bytes: u64,
+}
+
+/// 退出时的汇总
+#[derive(Debug, Clone, Serialize)]
+pub struct Summary {
+    pub duration_secs: f64,
+    pub total: usize,
+    pub filtered: usize,
+    pub bytes: u64,
+    pub protocols: BTreeMap<String, usize>,
+    pub readers: Vec<ReaderRow>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kernel: Option<KernelSummary>,
+}
+
+impl Summary {
+    pub fn new(readers: Vec<ReaderSummary>, duration: Duration, kernel: Option<KernelSummary>) -> Self {
+        let mut protocols = BTreeMap::new();
+        for reader in &readers {
+            for (&protocol, &count) in &reader.protocols {
+                *protocols.entry(format_protocol(protocol).to_string()).or_insert(0) += count;
+            }
+        }
+
+        Summary {
+            duration_secs: duration.as_secs_f64(),
+            total: readers.iter().map(|r| r.total).sum(),
+            filtered: readers.iter().map(|r| r.filtered).sum(),
+            bytes: readers.iter().map(|r| r.bytes).sum(),
+            protocols,
+            readers: readers
+                .into_iter()
+                .map(|r| ReaderRow { label: r.label, total: r.total, filtered: r.filtered, bytes: r.bytes })
+                .collect(),
+            kernel,
+        }
+    }
+
+    /// 文本汇总表
+    pub fn to_table(&self) -> String {
+        let mut out = String::new();
+
+        out.push_str("═══════════════════════════════════════\n");
+        out.push_str("统计汇总\n");
+        out.push_str("═══════════════════════════════════════\n");
+        out.push_str(&format!("运行时长: {:.3} 秒\n", self.duration_secs));
+        out.push_str(&format!("事件总数: {}\n", self.total));
+        out.push_str(&format!("匹配过滤: {}\n", self.filtered));
+        out.push_str(&format!("匹配字节: {}\n", self.bytes));
+        if self.duration_secs > 0.0 {
+            out.push_str(&format!(
+                "平均速率: {:.1} 包/秒, {:.1} 字节/秒\n",
+                self.filtered as f64 / self.duration_secs,
+                self.bytes as f64 / self.duration_secs
+            ));
+        }
+
+        if !self.protocols.is_empty() {
+            out.push_str("\n协议        数量\n");
+            for (protocol, count) in &self.protocols {
+                out.push_str(&format!("{:<10} {:>6}\n", protocol, count));
+            }
+        }
+
+        out.push_str("\n读取任务      事件     匹配         字节\n");
+        for row in &self.readers {
+            out.push_str(&format!(
+                "{:<10} {:>8} {:>8} {:>12}\n",
+                row.label, row.total, row.filtered, row.bytes
+            ));
+        }
+
+        if let Some(kernel) = self.kernel {
+            out.push_str(&format!(
+                "\n内核: 收到 {} 匹配 {} 上送 {} 丢弃 {} 丢失 {}\n",
+                kernel.seen, kernel.matched, kernel.emitted, kernel.dropped, kernel.lost
+            ));
+        }
+
+        out.push_str("═══════════════════════════════════════");
+        out
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap_or_else(|_| "{}".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(cpu_id: u32, seen: u64, matched: u64, emitted: u64, dropped: u64, lost: u64) -> CpuStats {
+        CpuStats {
+            cpu_id,
+            kernel: CaptureStats { seen, matched, emitted, dropped },
+            lost,
+        }
+    }
+
+    #[test]
+    fn lost_counters_per_cpu() {
+        let lost = LostCounters::new(&[0, 3]);
+        lost.add(3, 5);
+        lost.add(3, 2);
+        // 不在在线 CPU 列表中的计数被忽略
+        lost.add(7, 100);
+
+        assert_eq!(lost.get(0), 0);
+        assert_eq!(lost.get(3), 7);
+        assert_eq!(lost.get(7), 0);
+
+        // 克隆共享同一组计数器
+        lost.clone().add(0, 1);
+        assert_eq!(lost.get(0), 1);
+    }
+
+    #[test]
+    fn total_sums_all_cpus() {
+        let rows = [row(0, 100, 10, 9, 1, 0), row(1, 50, 5, 5, 0, 2)];
+        let sum = total(&rows);
+
+        assert_eq!(sum.kernel.seen, 150);
+        assert_eq!(sum.kernel.matched, 15);
+        assert_eq!(sum.kernel.emitted, 14);
+        assert_eq!(sum.kernel.dropped, 1);
+        assert_eq!(sum.lost, 2);
+        assert_eq!(sum.missing(), 3);
+        assert_eq!(total(&[]).missing(), 0);
+    }
+    #[test]
+    fn summary_merges_readers() {
+        let reader = |label: &str, total, filtered, bytes, protocols: &[(u8, usize)]| ReaderSummary {
+            label: label.to_string(),
+            total,
+            filtered,
+            bytes,
+            protocols: protocols.iter().copied().collect(),
+        };
+        let readers = vec![
+            reader("CPU 0", 10, 4, 400, &[(6, 3), (17, 1)]),
+            reader("CPU 1", 5, 2, 100, &[(6, 2)]),
+        ];
+        let summary = Summary::new(readers, Duration::from_secs(2), None);
+
+        assert_eq!((summary.total, summary.filtered, summary.bytes), (15, 6, 500));
+        assert_eq!(summary.protocols.get("TCP"), Some(&5));
+        assert_eq!(summary.protocols.get("UDP"), Some(&1));
+        assert_eq!(summary.readers.len(), 2);
+
+        let table = summary.to_table();
+        assert!(table.contains("匹配过滤: 6\n"));
+        assert!(table.contains("平均速率: 3.0 包/秒, 250.0 字节/秒"));
+        assert!(!table.contains("内核:"));
+
+        // 没有内核统计时 JSON 中不输出 kernel 字段
+        let json: serde_json::Value = serde_json::from_str(&summary.to_json()).unwrap();
+        assert_eq!(json["filtered"], 6);
+        assert_eq!(json["protocols"]["TCP"], 5);
+        assert!(json.get("kernel").is_none());
+    }
+}
@@ -0,0 +1,618 @@
+//! TLS 握手解析
+//!
+//! 从 TCP payload 中识别 TLS 握手记录，解析 ClientHello / ServerHello 的 SNI、ALPN、
+//! 支持的版本和加密套件，并计算 JA3/JA3S 和 JA4 指纹。
+//!
+//! 默认 `--snaplen` 下 ClientHello 常被截断：已解析到的字段照常输出，
+//! 但指纹需要完整的扩展列表，截断时不计算（`--snaplen 1536` 可以捕获完整的 ClientHello）。
+
+use md5::{Digest as _, Md5};
+use sha2::Sha256;
+
+use crate::dissect::{Dissector, Node, Packet};
+
+const CONTENT_HANDSHAKE: u8 = 22;
+const HANDSHAKE_CLIENT_HELLO: u8 = 1;
+const HANDSHAKE_SERVER_HELLO: u8 = 2;
+
+const EXT_SERVER_NAME: u16 = 0x0000;
+const EXT_SUPPORTED_GROUPS: u16 = 0x000a;
+const EXT_EC_POINT_FORMATS: u16 = 0x000b;
+const EXT_SIGNATURE_ALGORITHMS: u16 = 0x000d;
+const EXT_ALPN: u16 = 0x0010;
+const EXT_SUPPORTED_VERSIONS: u16 = 0x002b;
+
+/// ClientHello 中的字段
+#[derive(Debug, Clone, Default)]
+pub struct ClientHello {
+    /// 记录中的 legacy_version（TLS 1.3 的 ClientHello 仍为 0x0303）
+    pub version: u16,
+    /// supported_versions 扩展中的版本（不含 GREASE）
+    pub supported_versions: Vec<u16>,
+    pub cipher_suites: Vec<u16>,
+    /// 扩展类型，按出现顺序
+    pub extensions: Vec<u16>,
+    pub sni: Option<String>,
+    pub alpn: Vec<String>,
+    pub supported_groups: Vec<u16>,
+    pub ec_point_formats: Vec<u8>,
+    pub signature_algorithms: Vec<u16>,
+    /// payload 在握手消息结束前被截断
+    pub truncated: bool,
+    pub ja3: Option<String>,
+    pub ja4: Option<String>,
+}
+
+/// ServerHello 中的字段
+#[derive(Debug, Clone, Default)]
+pub struct ServerHello {
+    pub version: u16,
+    /// supported_versions 扩展选定的版本（TLS 1.3）
+    pub selected_version: Option<u16>,
+    pub cipher_suite: u16,
+    pub extensions: Vec<u16>,
+    pub alpn: Option<String>,
+    pub truncated: bool,
+    pub ja3s: Option<String>,
+}
+
+/// 解析出的握手消息
+#[derive(Debug, Clone)]
+pub enum TlsHandshake {
+    ClientHello(ClientHello),
+    ServerHello(ServerHello),
+}
+
+/// 按大端读取的游标；越界时返回 None
+struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(buf: &'a [u8]) -> Self {
+        Reader { buf, pos: 0 }
+    }
+
+    fn remaining(&self) -> usize {
+        self.buf.len() - self.pos
+    }
+
+    fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.remaining() < len {
+            return None;
+        }
+        let bytes = &self.buf[self.pos..self.pos + len];
+        self.pos += len;
+        Some(bytes)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.bytes(1).map(|b| b[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        self.bytes(2).map(|b| u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    fn u24(&mut self) -> Option<usize> {
+        self.bytes(3).map(|b| ((b[0] as usize) << 16) | ((b[1] as usize) << 8) | b[2] as usize)
+    }
+
+    /// 长度前缀（1 或 2 字节）的向量
+    fn vec8(&mut self) -> Option<&'a [u8]> {
+        let len = self.u8()? as usize;
+        self.bytes(len)
+    }
+
+    fn vec16(&mut self) -> Option<&'a [u8]> {
+        let len = self.u16()? as usize;
+        self.bytes(len)
+    }
+}
+
+/// 按 2 字节拆分列表，忽略末尾不足 2 字节的部分
+fn u16_list(bytes: &[u8]) -> Vec<u16> {
+    bytes.chunks_exact(2).map(|b| u16::from_be_bytes([b[0], b[1]])).collect()
+}
+
+/// ALPN 协议名：逐字节转换为字符，非 UTF-8 的值也保留原始字节（JA4 需要首末字节）
+fn protocol_id(bytes: &[u8]) -> String {
+    bytes.iter().map(|&b| b as char).collect()
+}
+
+/// GREASE 值（RFC 8701）：0x0a0a, 0x1a1a, ..., 0xfafa
+fn is_grease(value: u16) -> bool {
+    value & 0x0f0f == 0x0a0a && (value >> 8) == (value & 0xff)
+}
+
+/// 解析 TCP payload 开头的 TLS 握手记录；不是 ClientHello / ServerHello 时返回 None
+pub fn parse(payload: &[u8]) -> Option<TlsHandshake> {
+    let mut record = Reader::new(payload);
+    if record.u8()? != CONTENT_HANDSHAKE {
+        return None;
+    }
+    let record_version = record.u16()?;
+    if record_version >> 8 != 0x03 || record_version & 0xff > 0x04 {
+        return None;
+    }
+    let _record_len = record.u16()?;
+
+    let handshake_type = record.u8()?;
+    let handshake_len = record.u24()?;
+    // 握手消息可能跨多个记录，这里只看第一个记录中捕获到的部分
+    let available = handshake_len.min(record.remaining());
+    let truncated = available < handshake_len;
+    let body = record.bytes(available)?;
+
+    match handshake_type {
+        HANDSHAKE_CLIENT_HELLO => Some(TlsHandshake::ClientHello(parse_client_hello(body, truncated)?)),
+        HANDSHAKE_SERVER_HELLO => Some(TlsHandshake::ServerHello(parse_server_hello(body, truncated)?)),
+        _ => None,
+    }
+}
+
+fn parse_client_hello(body: &[u8], truncated: bool) -> Option<ClientHello> {
+    let mut r = Reader::new(body);
+    let mut hello = ClientHello { version: r.u16()?, truncated, ..Default::default() };
+
+    // random + session_id 之后的字段都可能被截断，解析到哪里算哪里
+    let complete = (|| {
+        r.bytes(32)?;
+        r.vec8()?;
+        hello.cipher_suites = u16_list(r.vec16()?);
+        r.vec8()?; // compression_methods
+
+        if r.remaining() == 0 {
+            return Some(());
+        }
+        // 扩展列表被截断时仍解析捕获到的完整扩展
+        let len = r.u16()? as usize;
+        let mut extensions = Reader::new(r.bytes(len.min(r.remaining()))?);
+        while extensions.remaining() > 0 {
+            let ext_type = extensions.u16()?;
+            let data = extensions.vec16()?;
+            hello.extensions.push(ext_type);
+            parse_client_extension(&mut hello, ext_type, data);
+        }
+        (extensions.buf.len() == len).then_some(())
+    })()
+    .is_some();
+    hello.truncated |= !complete;
+
+    if !hello.truncated {
+        hello.ja3 = Some(ja3(&hello));
+        hello.ja4 = Some(ja4(&hello));
+    }
+    Some(hello)
+}
+
+fn parse_client_extension(hello: &mut ClientHello, ext_type: u16, data: &[u8]) {
+    let mut r = Reader::new(data);
+    match ext_type {
+        EXT_SERVER_NAME => {
+            // server_name_list 中第一个 host_name（类型 0）
+            if let Some(list) = r.vec16() {
+                let mut list = Reader::new(list);
+                while let (Some(name_type), Some(name)) = (list.u8(), list.vec16()) {
+                    if name_type == 0 {
+                        hello.sni = Some(String::from_utf8_lossy(name).into_owned());
+                        break;
+                    }
+                }
+            }
+        }
+        EXT_ALPN => {
+            if let Some(list) = r.vec16() {
+                let mut list = Reader::new(list);
+                while let Some(protocol) = list.vec8() {
+                    hello.alpn.push(protocol_id(protocol));
+                }
+            }
+        }
+        EXT_SUPPORTED_GROUPS => {
+            if let Some(list) = r.vec16() {
+                hello.supported_groups = u16_list(list);
+            }
+        }
+        EXT_EC_POINT_FORMATS => {
+            if let Some(list) = r.vec8() {
+                hello.ec_point_formats = list.to_vec();
+            }
+        }
+        EXT_SIGNATURE_ALGORITHMS => {
+            if let Some(list) = r.vec16() {
+                hello.signature_algorithms = u16_list(list);
+            }
+        }
+        EXT_SUPPORTED_VERSIONS => {
+            if let Some(list) = r.vec8() {
+                hello.supported_versions = u16_list(list).into_iter().filter(|v| !is_grease(*v)).collect();
+            }
+        }
+        _ => {}
+    }
+}
+
+fn parse_server_hello(body: &[u8], truncated: bool) -> Option<ServerHello> {
+    let mut r = Reader::new(body);
+    let mut hello = ServerHello { version: r.u16()?, truncated, ..Default::default() };
+
+    r.bytes(32)?;
+    r.vec8()?;
+    hello.cipher_suite = r.u16()?;
+    r.u8()?; // compression_method
+
+    let complete = (|| {
+        if r.remaining() == 0 {
+            return Some(());
+        }
+        let len = r.u16()? as usize;
+        let mut extensions = Reader::new(r.bytes(len.min(r.remaining()))?);
+        while extensions.remaining() > 0 {
+            let ext_type = extensions.u16()?;
+            let data = extensions.vec16()?;
+            hello.extensions.push(ext_type);
+
+            let mut data = Reader::new(data);
+            match ext_type {
+                EXT_SUPPORTED_VERSIONS => hello.selected_version = data.u16(),
+                EXT_ALPN => {
+                    hello.alpn = data
+                        .vec16()
+                        .and_then(|list| Reader::new(list).vec8())
+                        .map(protocol_id);
+                }
+                _ => {}
+            }
+        }
+        (extensions.buf.len() == len).then_some(())
+    })()
+    .is_some();
+    hello.truncated |= !complete;
+
+    if !hello.truncated {
+        hello.ja3s = Some(ja3s(&hello));
+    }
+    Some(hello)
+}
+
+/// 十进制列表，以 - 连接（JA3 格式）
+fn dash_list<T: ToString>(values: impl IntoIterator<Item = T>) -> String {
+    values.into_iter().map(|v| v.to_string()).collect::<Vec<_>>().join("-")
+}
+
+fn md5_hex(text: &str) -> String {
+    format!("{:x}", Md5::digest(text.as_bytes()))
+}
+
+/// SHA-256 的前 12 个十六进制字符（JA4 格式）；空列表为 12 个 0
+fn sha256_12(text: &str) -> String {
+    if text.is_empty() {
+        return "000000000000".to_string();
+    }
+    format!("{:x}", Sha256::digest(text.as_bytes()))[..12].to_string()
+}
+
+/// JA3：MD5("版本,加密套件,扩展,椭圆曲线,点格式")，不含 GREASE
+pub fn ja3(hello: &ClientHello) -> String {
+    let text = format!(
+        "{},{},{},{},{}",
+        hello.version,
+        dash_list(hello.cipher_suites.iter().filter(|v| !is_grease(**v))),
+        dash_list(hello.extensions.iter().filter(|v| !is_grease(**v))),
+        dash_list(hello.supported_groups.iter().filter(|v| !is_grease(**v))),
+        dash_list(hello.ec_point_formats.iter()),
+    );
+    md5_hex(&text)
+}
+
+/// JA3S：MD5("版本,加密套件,扩展")
+pub fn ja3s(hello: &ServerHello) -> String {
+    let text = format!("{},{},{}", hello.version, hello.cipher_suite, dash_list(hello.extensions.iter()));
+    md5_hex(&text)
+}
+
+/// JA4 中的两位版本号
+fn ja4_version(version: u16) -> &'static str {
+    match version {
+        0x0304 => "13",
+        0x0303 => "12",
+        0x0302 => "11",
+        0x0301 => "10",
+        0x0300 => "s3",
+        _ => "00",
+    }
+}
+
+/// JA4（TCP）：t + 版本 + SNI 标记 + 套件数 + 扩展数 + ALPN 首末字符 _ 套件哈希 _ 扩展和签名算法哈希
+pub fn ja4(hello: &ClientHello) -> String {
+    let version = hello.supported_versions.iter().copied().max().unwrap_or(hello.version);
+    let ciphers: Vec<u16> = hello.cipher_suites.iter().copied().filter(|v| !is_grease(*v)).collect();
+    let extensions: Vec<u16> = hello.extensions.iter().copied().filter(|v| !is_grease(*v)).collect();
+
+    // ALPN 取第一个协议的首末字符，非字母数字时取其十六进制表示的首末字符
+    let alpn = match hello.alpn.first().map(|p| p.chars().map(|c| c as u32 as u8).collect::<Vec<_>>()) {
+        Some(bytes) if !bytes.is_empty() => {
+            let (first, last) = (bytes[0], bytes[bytes.len() - 1]);
+            if first.is_ascii_alphanumeric() && last.is_ascii_alphanumeric() {
+                format!("{}{}", first as char, last as char)
+            } else {
+                let hex = format!("{:02x}{:02x}", first, last);
+                format!("{}{}", &hex[..1], &hex[3..])
+            }
+        }
+        _ => "00".to_string(),
+    };
+
+    let prefix = format!(
+        "t{}{}{:02}{:02}{}",
+        ja4_version(version),
+        if hello.sni.is_some() { 'd' } else { 'i' },
+        ciphers.len().min(99),
+        extensions.len().min(99),
+        alpn,
+    );
+
+    let mut sorted_ciphers = ciphers;
+    sorted_ciphers.sort_unstable();
+    let cipher_text = sorted_ciphers.iter().map(|v| format!("{:04x}", v)).collect::<Vec<_>>().join(",");
+
+    // 扩展哈希不含 SNI 和 ALPN，排序后拼接原始顺序的签名算法
+    let mut sorted_extensions: Vec<u16> =
+        extensions.into_iter().filter(|v| *v != EXT_SERVER_NAME && *v != EXT_ALPN).collect();
+    sorted_extensions.sort_unstable();
+    let mut extension_text = sorted_extensions.iter().map(|v| format!("{:04x}", v)).collect::<Vec<_>>().join(",");
+    if !hello.signature_algorithms.is_empty() {
+        extension_text.push('_');
+        extension_text.push_str(
+            &hello.signature_algorithms.iter().map(|v| format!("{:04x}", v)).collect::<Vec<_>>().join(","),
+        );
+    }
+
+    format!("{}_{}_{}", prefix, sha256_12(&cipher_text), sha256_12(&extension_text))
+}
+
+/// 版本号的名称，如 TLS 1.3
+pub fn version_name(version: u16) -> String {
+    match version {
+        0x0304 => "TLS 1.3".to_string(),
+        0x0303 => "TLS 1.2".to_string(),
+        0x0302 => "TLS 1.1".to_string(),
+        0x0301 => "TLS 1.0".to_string(),
+        0x0300 => "SSL 3.0".to_string(),
+        other => format!("0x{:04x}", other),
+    }
+}
+
+fn hex_list(values: &[u16]) -> Vec<String> {
+    values.iter().map(|v| format!("0x{:04x}", v)).collect()
+}
+
+/// 解析树：字段名与 JSON 输出一致
+pub fn to_node(handshake: &TlsHandshake) -> Node {
+    match handshake {
+        TlsHandshake::ClientHello(hello) => {
+            let version = hello.supported_versions.iter().copied().max().unwrap_or(hello.version);
+            let summary = format!(
+                "TLS ClientHello ({}){}",
+                version_name(version),
+                if hello.truncated { " [truncated]" } else { "" }
+            );
+            let supported_versions: Vec<String> = hello.supported_versions.iter().map(|v| version_name(*v)).collect();
+            Node::new(summary)
+                .field("handshake", "client_hello")
+                .field("version", version_name(hello.version))
+                .field_opt("sni", hello.sni.clone())
+                .field("alpn", hello.alpn.clone())
+                .field("supported_versions", supported_versions)
+                .field("cipher_suites", hex_list(&hello.cipher_suites))
+                .field("extensions", hello.extensions.clone())
+                .field("supported_groups", hello.supported_groups.clone())
+                .field("ec_point_formats", hello.ec_point_formats.clone())
+                .field("signature_algorithms", hex_list(&hello.signature_algorithms))
+                .field("truncated", hello.truncated)
+                .field_opt("ja3", hello.ja3.clone())
+                .field_opt("ja4", hello.ja4.clone())
+        }
+        TlsHandshake::ServerHello(hello) => {
+            let version = hello.selected_version.unwrap_or(hello.version);
+            let summary = format!(
+                "TLS ServerHello ({}){}",
+                version_name(version),
+                if hello.truncated { " [truncated]" } else { "" }
+            );
+            Node::new(summary)
+                .field("handshake", "server_hello")
+                .field("version", version_name(version))
+                .field("cipher_suite", format!("0x{:04x}", hello.cipher_suite))
+                .field("extensions", hello.extensions.clone())
+                .field_opt("alpn", hello.alpn.clone())
+                .field("truncated", hello.truncated)
+                .field_opt("ja3s", hello.ja3s.clone())
+        }
+    }
+}
+
+/// TLS 握手解析器：按内容识别，不限于 443 端口
+pub struct TlsDissector;
+
+impl Dissector for TlsDissector {
+    fn name(&self) -> &'static str {
+        "tls"
+    }
+
+    fn ports(&self) -> &[(u8, u16)] {
+        &[(6, 443)]
+    }
+
+    fn probe(&self, packet: &Packet) -> bool {
+        let payload = packet.payload;
+        packet.protocol == 6 && payload.len() >= 6 && payload[0] == CONTENT_HANDSHAKE && payload[1] == 0x03
+    }
+
+    fn decode(&self, packet: &Packet) -> Option<Node> {
+        if packet.protocol != 6 {
+            return None;
+        }
+        parse(packet.payload).as_ref().map(to_node)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(lines: &[&str]) -> Vec<u8> {
+        let text = lines.concat();
+        (0..text.len()).step_by(2).map(|i| u8::from_str_radix(&text[i..i + 2], 16).unwrap()).collect()
+    }
+
+    fn client_hello(payload: &[u8]) -> ClientHello {
+        match parse(payload) {
+            Some(TlsHandshake::ClientHello(hello)) => hello,
+            other => panic!("不是 ClientHello: {:?}", other),
+        }
+    }
+
+    fn server_hello(payload: &[u8]) -> ServerHello {
+        match parse(payload) {
+            Some(TlsHandshake::ServerHello(hello)) => hello,
+            other => panic!("不是 ServerHello: {:?}", other),
+        }
+    }
+
+    /// JA3 文档中的示例：769,47-53-5-10-49161-49162-49171-49172-50-56-19-4,0-10-11,23-24-25,0
+    fn ja3_example() -> Vec<u8> {
+        hex(&[
+            "160301006b010000670301000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f000018002f",
+            "00350005000ac009c00ac013c01400320038001300040100002600000010000e00000b6578616d706c652e636f6d000a",
+            "00080006001700180019000b00020100",
+        ])
+    }
+
+    /// Chrome 的 ClientHello（带 GREASE），JA4 文档中的示例 t13d1516h2_8daaf6152771_e5627efa2ab1
+    fn chrome() -> Vec<u8> {
+        hex(&[
+            "16030101400100013c0303000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f2000010203",
+            "0405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f00200a0a130113021303c02bc02fc02cc030cca9",
+            "cca8c013c014009c009d002f0035010000d31a1a000000000014001200000f7777772e6578616d706c652e636f6d0017",
+            "0000ff01000100000a000a00082a2a001d00170018000b00020100002300000010000e000c02683208687474702f312e",
+            "31000500050100000000000d0012001004030804040105030805050108060601001200000033002b00292a2a00010000",
+            "1d0020000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f002d00020101002b0007063a3a",
+            "03040303001b00030200024469000500030268320015000800000000000000004a4a000100",
+        ])
+    }
+
+    #[test]
+    fn ja3_known_answers() {
+        let hello = client_hello(&ja3_example());
+        assert!(!hello.truncated);
+        assert_eq!(hello.version, 0x0301);
+        assert_eq!(hello.sni.as_deref(), Some("example.com"));
+        assert_eq!(hello.extensions, [0, 10, 11]);
+        assert_eq!(hello.supported_groups, [23, 24, 25]);
+        assert_eq!(hello.ec_point_formats, [0]);
+        assert_eq!(hello.ja3.as_deref(), Some("ada70206e40642a3e4461f35503241d5"));
+
+        // 没有扩展：769,4-5-10-9-100-98-3-6-19-18-99,,,
+        let hello = client_hello(&hex(&[
+            "16030100410100003d0301000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f0000160004",
+            "0005000a000900640062000300060013001200630100",
+        ]));
+        assert!(hello.extensions.is_empty());
+        assert_eq!(hello.ja3.as_deref(), Some("de350869b8c85de67a350c8d186f11e6"));
+    }
+
+    #[test]
+    fn ja4_known_answer() {
+        let hello = client_hello(&chrome());
+        assert!(!hello.truncated);
+        assert_eq!(hello.sni.as_deref(), Some("www.example.com"));
+        assert_eq!(hello.alpn, ["h2", "http/1.1"]);
+        assert_eq!(hello.supported_versions, [0x0304, 0x0303]);
+        assert_eq!(hello.signature_algorithms.len(), 8);
+        assert_eq!(hello.ja4.as_deref(), Some("t13d1516h2_8daaf6152771_e5627efa2ab1"));
+    }
+
+    #[test]
+    fn ja3s_known_answer() {
+        // 769,47,65281
+        let hello = server_hello(&hex(&[
+            "16030100310200002d0301202122232425262728292a2b2c2d2e2f303132333435363738393a3b3c3d3e3f00002f0000",
+            "05ff01000100",
+        ]));
+        assert_eq!(hello.version, 0x0301);
+        assert_eq!(hello.cipher_suite, 0x002f);
+        assert_eq!(hello.extensions, [0xff01]);
+        assert_eq!(hello.ja3s.as_deref(), Some("4192c0a946c5bd9b544b4656d9f624a4"));
+    }
+
+    #[test]
+    fn grease_values_are_ignored() {
+        for high in 0..16u16 {
+            assert!(is_grease((high << 12) | (0xa << 8) | (high << 4) | 0xa));
+        }
+        assert!(!is_grease(0x0a1a));
+        assert!(!is_grease(0x1301));
+        assert!(!is_grease(0xaaab));
+
+        // 去掉所有 GREASE 值后指纹不变
+        let hello = client_hello(&chrome());
+        assert!(hello.cipher_suites.contains(&0x0a0a));
+        assert!(hello.extensions.contains(&0x1a1a) && hello.extensions.contains(&0x4a4a));
+        let mut stripped = hello.clone();
+        stripped.cipher_suites.retain(|v| !is_grease(*v));
+        stripped.extensions.retain(|v| !is_grease(*v));
+        stripped.supported_groups.retain(|v| !is_grease(*v));
+        assert_eq!(ja3(&stripped), hello.ja3.clone().unwrap());
+        assert_eq!(ja4(&stripped), hello.ja4.clone().unwrap());
+    }
+
+    #[test]
+    fn ja4_alpn_hex_fallback() {
+        let ja4_a = |alpn: Vec<String>| {
+            let hello = ClientHello { version: 0x0303, alpn, ..Default::default() };
+            ja4(&hello).split('_').next().unwrap().to_string()
+        };
+        assert_eq!(ja4_a(vec!["h2".into()]), "t12i0000h2");
+        assert_eq!(ja4_a(vec!["h".into()]), "t12i0000hh");
+        assert_eq!(ja4_a(vec![]), "t12i000000");
+        // 首或末字节不是字母数字时取十六进制表示的首末字符：0xab 0xcd -> "abcd" -> "ad"
+        assert_eq!(ja4_a(vec![protocol_id(&[0xab, 0xcd])]), "t12i0000ad");
+        assert_eq!(ja4_a(vec![protocol_id(b"h2-")]), "t12i00006d");
+    }
+
+    #[test]
+    fn truncated_records() {
+        let full = chrome();
+        for len in 0..full.len() {
+            match parse(&full[..len]) {
+                Some(TlsHandshake::ClientHello(hello)) => {
+                    assert!(hello.truncated, "长度 {}", len);
+                    assert!(hello.ja3.is_none() && hello.ja4.is_none(), "长度 {}", len);
+                }
+                Some(TlsHandshake::ServerHello(_)) => panic!("长度 {} 解析为 ServerHello", len),
+                None => assert!(len < 11, "长度 {} 应当仍能解析出版本", len),
+            }
+        }
+
+        // 截断在扩展列表中间：已解析的 SNI 保留
+        let hello = client_hello(&full[..160]);
+        assert_eq!(hello.sni.as_deref(), Some("www.example.com"));
+        assert!(hello.alpn.is_empty());
+
+        // ServerHello 截断在加密套件之前无法解析
+        let server = hex(&["16030100310200002d0301202122232425262728292a2b2c2d2e2f303132333435363738393a3b3c3d3e3f00002f"]);
+        assert!(parse(&server[..server.len() - 2]).is_none());
+    }
+
+    #[test]
+    fn not_a_hello() {
+        // 应用数据记录、版本不是 3.x、其他握手消息
+        assert!(parse(&hex(&["170303000501020304"])).is_none());
+        assert!(parse(&hex(&["160205002f0100002b"])).is_none());
+        assert!(parse(&hex(&["160303000a0b00000600000300000100"])).is_none());
+        assert!(parse(&[]).is_none());
+    }
+}